@@ -53,6 +53,47 @@ pub struct DispatcherSprites {
     pub meme_folder: Rc<PixelTexture>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl DispatcherSprites {
+    /// Single sprites of the fields, including the novella ones.
+    pub const FILES: [&str; 32] = [
+        "door",
+        "sign_open",
+        "sign_closed",
+        "table",
+        "monitor",
+        "cactus",
+        "real_mouse",
+        "book",
+        "book_open",
+        "the_sock",
+        "button_station_open",
+        "button_station_closed",
+        "button_base",
+        "button",
+        "button_pressed",
+        "button_big",
+        "button_big_pressed",
+        "tea",
+        "arrow_left",
+        "arrow_right",
+        "user_icon",
+        "login_screen",
+        "workspace",
+        "workspace_v2",
+        "file",
+        "file_window",
+        "meme_folder",
+        "novella/background",
+        "novella/textbox",
+        "novella/neutral",
+        "novella/surprised",
+        "novella/angry",
+    ];
+    /// Files loaded as numbered lists, with their inclusive ranges.
+    pub const LISTS: [(&str, u32, u32); 2] = [("head", 1, 7), ("memes", 0, 9)];
+}
+
 #[derive(geng::asset::Load)]
 pub struct NovellaSprites {
     pub background: Rc<PixelTexture>,
@@ -111,6 +152,24 @@ pub enum DispatcherItem {
     Tea,
 }
 
+impl DispatcherItem {
+    /// Colored buttons of the button station, pressed to make the sounds.
+    pub fn is_button(&self) -> bool {
+        matches!(
+            self,
+            Self::ButtonYellow
+                | Self::ButtonGreen
+                | Self::ButtonSalad
+                | Self::ButtonPink
+                | Self::ButtonBlue
+                | Self::ButtonWhite
+                | Self::ButtonPurple
+                | Self::ButtonOrange
+                | Self::ButtonCyan
+        )
+    }
+}
+
 /// Positioning in screen-space with fixed 1920x1080 resolution.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DispatcherItemPosition {
@@ -124,6 +183,42 @@ fn default_alignment() -> vec2<f32> {
     vec2(0.5, 0.5)
 }
 
+impl DispatcherItemPosition {
+    /// Area of the item, sized like its texture unless the size is given.
+    pub fn aabb(&self, texture_size: vec2<f32>) -> Aabb2<f32> {
+        let size = self.size.unwrap_or(texture_size);
+        Aabb2::point(self.anchor - size * self.alignment).extend_positive(size)
+    }
+}
+
+impl DispatcherSprites {
+    /// Texture of the item before anyone has interacted with it.
+    pub fn item_texture(&self, item: DispatcherItem) -> &Rc<PixelTexture> {
+        match item {
+            DispatcherItem::Door => &self.door,
+            DispatcherItem::DoorSign => &self.sign_closed,
+            DispatcherItem::Table => &self.table,
+            DispatcherItem::Monitor => &self.monitor,
+            DispatcherItem::RealMouse => &self.real_mouse,
+            DispatcherItem::Cactus => &self.cactus,
+            DispatcherItem::Book => &self.book,
+            DispatcherItem::TheSock => &self.the_sock,
+            DispatcherItem::ButtonStation => &self.button_station_closed,
+            DispatcherItem::Bfb => &self.button_big,
+            DispatcherItem::ButtonYellow
+            | DispatcherItem::ButtonGreen
+            | DispatcherItem::ButtonSalad
+            | DispatcherItem::ButtonPink
+            | DispatcherItem::ButtonBlue
+            | DispatcherItem::ButtonWhite
+            | DispatcherItem::ButtonPurple
+            | DispatcherItem::ButtonOrange
+            | DispatcherItem::ButtonCyan => &self.button,
+            DispatcherItem::Tea => &self.tea,
+        }
+    }
+}
+
 impl DispatcherLevel {
     pub fn get_side(&self, side: DispatcherViewSide) -> &DispatcherView {
         match side {
@@ -133,9 +228,30 @@ impl DispatcherLevel {
             DispatcherViewSide::Back => &self.back,
         }
     }
+
+    pub fn get_side_mut(&mut self, side: DispatcherViewSide) -> &mut DispatcherView {
+        match side {
+            DispatcherViewSide::Front => &mut self.front,
+            DispatcherViewSide::Left => &mut self.left,
+            DispatcherViewSide::Right => &mut self.right,
+            DispatcherViewSide::Back => &mut self.back,
+        }
+    }
 }
 
 impl DispatcherViewSide {
+    pub const ALL: [Self; 4] = [Self::Front, Self::Left, Self::Right, Self::Back];
+
+    /// Name of the file the view is loaded from.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Front => "front.ron",
+            Self::Left => "left.ron",
+            Self::Right => "right.ron",
+            Self::Back => "back.ron",
+        }
+    }
+
     pub fn cycle_left(self) -> Self {
         match self {
             Self::Front => Self::Left,
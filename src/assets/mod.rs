@@ -1,8 +1,14 @@
 mod dispatcher;
 mod font;
+mod pack;
 mod solver;
+mod tiles;
+#[cfg(not(target_arch = "wasm32"))]
+mod validate;
 
-pub use self::{dispatcher::*, font::Font, solver::*};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::validate::*;
+pub use self::{dispatcher::*, font::Font, pack::*, solver::*, tiles::*};
 
 use crate::render::Color;
 
@@ -28,6 +34,7 @@ pub struct Assets {
 pub struct Palette {
     pub background: Color,
     pub text: Color,
+    pub error: Color,
 }
 
 #[derive(geng::asset::Load)]
@@ -72,6 +79,42 @@ pub struct SoundAssets {
     pub spit: Rc<geng::Sound>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl SoundAssets {
+    /// Files of the fields above, checked by the asset validator. Keep in sync with the fields.
+    pub const FILES: [&str; 17] = [
+        "music",
+        "dispatcher",
+        "boss",
+        "click",
+        "hover",
+        "mouse",
+        "book",
+        "button",
+        "cactus",
+        "clop",
+        "duck",
+        "k",
+        "kick",
+        "liproll",
+        "oo",
+        "psh",
+        "spit",
+    ];
+    /// Numbered lists, with their inclusive ranges.
+    pub const LISTS: [(&str, u32, u32); 1] = [("pop", 0, 2)];
+}
+
+impl SoundAssets {
+    pub fn level_music(&self, music: LevelMusic) -> &Rc<geng::Sound> {
+        match music {
+            LevelMusic::Menu => &self.music,
+            LevelMusic::Dispatcher => &self.dispatcher,
+            LevelMusic::Boss => &self.boss,
+        }
+    }
+}
+
 #[derive(geng::asset::Load)]
 pub struct ShaderAssets {
     pub masked: Rc<ugli::Program>,
@@ -91,6 +134,22 @@ friendly_derive::texture_atlas!(pub SpritesAtlas {
     run1,
 });
 
+#[cfg(not(target_arch = "wasm32"))]
+impl SpritesAtlas {
+    /// Sprites packed into the atlas.
+    pub const FILES: [&str; 9] = [
+        "white",
+        "menu",
+        "button_background",
+        "code_background",
+        "lobby",
+        "think0",
+        "think1",
+        "run0",
+        "run1",
+    ];
+}
+
 fn load_gif(
     manager: &geng::asset::Manager,
     path: &std::path::Path,
@@ -0,0 +1,194 @@
+use super::*;
+
+/// Community-made puzzle chain loaded from a directory laid out like the shipped assets:
+/// `solver/levels/_list.ron` with the levels, and optionally `dispatcher/level/*.ron`,
+/// `dispatcher/files/*.txt`, `dispatcher/book_text.txt` and `dispatcher/novella.txt`.
+pub struct LevelPack {
+    /// Name of the pack's directory, used to agree on the pack with the server.
+    pub name: String,
+    pub dir: PathBuf,
+    pub levels: Vec<SolverLevel>,
+    /// Parts missing from the pack are taken from the shipped assets.
+    pub room: Option<DispatcherLevel>,
+    pub files: Option<Vec<String>>,
+    pub book_text: Option<String>,
+    pub novella: Option<String>,
+}
+
+/// Content the game is played with.
+pub struct PackContent<'a> {
+    pub levels: &'a [SolverLevel],
+    pub room: &'a DispatcherLevel,
+    pub files: &'a [String],
+    pub book_text: &'a str,
+    pub novella: &'a str,
+}
+
+impl Assets {
+    /// Content of the pack, or of the shipped assets without one.
+    pub fn content<'a>(&'a self, pack: Option<&'a LevelPack>) -> PackContent<'a> {
+        let dispatcher = &self.dispatcher;
+        PackContent {
+            levels: pack.map_or(&self.solver.levels, |pack| &pack.levels),
+            room: pack
+                .and_then(|pack| pack.room.as_ref())
+                .unwrap_or(&dispatcher.level),
+            files: pack
+                .and_then(|pack| pack.files.as_deref())
+                .unwrap_or(&dispatcher.files),
+            book_text: pack
+                .and_then(|pack| pack.book_text.as_deref())
+                .unwrap_or(&dispatcher.book_text),
+            novella: pack
+                .and_then(|pack| pack.novella.as_deref())
+                .unwrap_or(&dispatcher.novella),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::files::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod files {
+    use super::*;
+
+    use anyhow::Context as _;
+
+    /// Directory of the levels shipped with the game.
+    pub fn default_levels_dir() -> PathBuf {
+        run_dir().join("assets").join("solver").join("levels")
+    }
+
+    /// Directory of the dispatcher's room shipped with the game.
+    pub fn default_room_dir() -> PathBuf {
+        run_dir().join("assets").join("dispatcher").join("level")
+    }
+
+    /// Directory with the installed level packs, one per subdirectory.
+    pub fn packs_dir() -> PathBuf {
+        run_dir().join("packs")
+    }
+
+    /// Directories of all installed level packs, plus the extra one.
+    pub fn pack_dirs(extra: Option<&std::path::Path>) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = match std::fs::read_dir(packs_dir()) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        dirs.sort();
+        dirs.extend(extra.map(|dir| dir.to_owned()));
+        dirs
+    }
+
+    /// Load the levels listed in the `_list.ron` of the directory.
+    pub fn load_levels(dir: &std::path::Path) -> anyhow::Result<Vec<SolverLevel>> {
+        level_names(dir)?
+            .iter()
+            .map(|name| load_level(dir, name))
+            .collect()
+    }
+
+    /// File names of the levels in the order they are played.
+    pub fn level_names(dir: &std::path::Path) -> anyhow::Result<Vec<String>> {
+        let list = std::fs::read_to_string(dir.join("_list.ron"))?;
+        ron::from_str(&list).context("invalid level list")
+    }
+
+    pub fn load_level(dir: &std::path::Path, name: &str) -> anyhow::Result<SolverLevel> {
+        let level = std::fs::read_to_string(dir.join(name))?;
+        ron::from_str(&level).with_context(|| format!("invalid level {name}"))
+    }
+
+    pub fn load_room(dir: &std::path::Path) -> anyhow::Result<DispatcherLevel> {
+        let load_view = |side: DispatcherViewSide| -> anyhow::Result<DispatcherView> {
+            let view = std::fs::read_to_string(dir.join(side.file_name()))?;
+            ron::from_str(&view).with_context(|| format!("invalid view {}", side.file_name()))
+        };
+        Ok(DispatcherLevel {
+            front: load_view(DispatcherViewSide::Front)?,
+            left: load_view(DispatcherViewSide::Left)?,
+            right: load_view(DispatcherViewSide::Right)?,
+            back: load_view(DispatcherViewSide::Back)?,
+        })
+    }
+
+    /// Name of the pack in the directory.
+    pub fn pack_name(dir: &std::path::Path) -> anyhow::Result<String> {
+        dir.file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| is_valid_pack_name(name))
+            .map(|name| name.to_owned())
+            .with_context(|| format!("{dir:?} is not a valid level pack name"))
+    }
+
+    /// Names are sent over the network, so keep them to plain directory names.
+    fn is_valid_pack_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    }
+
+    impl LevelPack {
+        pub fn load(dir: &std::path::Path) -> anyhow::Result<Self> {
+            let name = pack_name(dir)?;
+            let levels = load_levels(&dir.join("solver").join("levels"))?;
+
+            let dispatcher = dir.join("dispatcher");
+            let room_dir = dispatcher.join("level");
+            let room = room_dir
+                .is_dir()
+                .then(|| load_room(&room_dir))
+                .transpose()?;
+            let files_dir = dispatcher.join("files");
+            let files = files_dir
+                .is_dir()
+                .then(|| {
+                    (0..)
+                        .map(|i| files_dir.join(format!("{i}.txt")))
+                        .take_while(|path| path.is_file())
+                        .map(std::fs::read_to_string)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?;
+            let read_text = |name: &str| {
+                let path = dispatcher.join(name);
+                path.is_file()
+                    .then(|| std::fs::read_to_string(path))
+                    .transpose()
+            };
+
+            Ok(Self {
+                levels,
+                room,
+                files,
+                book_text: read_text("book_text.txt")?,
+                novella: read_text("novella.txt")?,
+                name,
+                dir: dir.to_owned(),
+            })
+        }
+
+        /// Load the installed pack with the given name.
+        pub fn find(name: &str) -> anyhow::Result<Self> {
+            if !is_valid_pack_name(name) {
+                anyhow::bail!("invalid level pack name {name:?}");
+            }
+            Self::load(&packs_dir().join(name))
+        }
+
+        pub fn levels_dir(&self) -> PathBuf {
+            self.dir.join("solver").join("levels")
+        }
+
+        /// Directory of the pack's room, which might not exist if it uses the shipped one.
+        pub fn room_dir(&self) -> PathBuf {
+            self.dir.join("dispatcher").join("level")
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use super::*;
 
-use crate::model::{Collider, FCoord, FTime};
+use crate::{
+    context::GamepadInput,
+    model::{Collider, FCoord, FTime, LevelTrigger},
+};
 
 use geng_utils::key::EventKey;
 
@@ -41,6 +44,43 @@ pub struct SolverSprites {
     pub explosion: Vec<GifFrame>,
     pub projectile: Rc<PixelTexture>,
     pub dispatcher: Rc<PixelTexture>,
+    /// Square tiles of the tile maps in a single row.
+    pub tileset: Rc<PixelTexture>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SolverSprites {
+    /// Single sprites of the fields, including the gif.
+    pub const FILES: [&str; 19] = [
+        "interact",
+        "level_bounds",
+        "door_open",
+        "door_closed",
+        "platform",
+        "level1",
+        "grandpa",
+        "grandson",
+        "trashcan_evil",
+        "trashcan",
+        "recycle",
+        "green_hint",
+        "bubble_tea",
+        "bubble_door",
+        "bubble_code",
+        "projectile",
+        "dispatcher",
+        "explosion.gif",
+        "tileset",
+    ];
+    /// Files loaded as numbered lists, with their inclusive ranges.
+    pub const LISTS: [(&str, u32, u32); 6] = [
+        ("fish", 1, 7),
+        ("excalibur", 1, 2),
+        ("balls", 0, 2),
+        ("player/idle", 0, 1),
+        ("player/running", 0, 3),
+        ("player/jump", 0, 1),
+    ];
 }
 
 #[derive(geng::asset::Load)]
@@ -58,13 +98,55 @@ pub struct SolverPlayerSprites {
 pub struct SolverControls {
     pub move_left: Vec<EventKey>,
     pub move_right: Vec<EventKey>,
+    /// Drop through the platforms that allow it.
+    #[serde(default)]
+    pub move_down: Vec<EventKey>,
     pub jump: Vec<EventKey>,
     pub pickup: Vec<EventKey>,
+    /// Gamepad bindings, working along with the keyboard.
+    #[serde(default)]
+    pub gamepad: GamepadControls,
 }
 
-#[derive(geng::asset::Load, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GamepadControls {
+    pub move_left: Vec<GamepadInput>,
+    pub move_right: Vec<GamepadInput>,
+    pub move_down: Vec<GamepadInput>,
+    pub jump: Vec<GamepadInput>,
+    pub pickup: Vec<GamepadInput>,
+    /// Stick tilt from 0 to 1 that is ignored, so that a worn stick does not move the player.
+    pub deadzone: f32,
+}
+
+impl Default for GamepadControls {
+    fn default() -> Self {
+        use crate::context::{AxisDirection::*, GamepadAxis::*, GamepadButton::*};
+        Self {
+            move_left: vec![
+                GamepadInput::Button(DPadLeft),
+                GamepadInput::Axis(LeftStickX, Negative),
+            ],
+            move_right: vec![
+                GamepadInput::Button(DPadRight),
+                GamepadInput::Axis(LeftStickX, Positive),
+            ],
+            move_down: vec![
+                GamepadInput::Button(DPadDown),
+                GamepadInput::Axis(LeftStickY, Negative),
+            ],
+            jump: vec![GamepadInput::Button(South)],
+            pickup: vec![GamepadInput::Button(West)],
+            deadzone: 0.25,
+        }
+    }
+}
+
+#[derive(geng::asset::Load, Debug, Clone, Serialize, Deserialize)]
 #[load(serde = "ron")]
 pub struct SolverRules {
+    /// Number of fixed simulation steps per second.
+    pub ticks_per_second: FTime,
     pub buffer_time: FTime,
     pub coyote_time: FTime,
     pub gravity: vec2<FCoord>,
@@ -80,7 +162,7 @@ pub struct SolverRules {
     pub jump_strength: FCoord,
 }
 
-#[derive(geng::asset::Load, Serialize, Deserialize)]
+#[derive(geng::asset::Load, Debug, Clone, Serialize, Deserialize)]
 #[load(serde = "ron")]
 pub struct SolverLevel {
     pub door_entrance: bool,
@@ -89,14 +171,155 @@ pub struct SolverLevel {
     pub transition: Aabb2<FCoord>,
     #[serde(default)]
     pub platforms: Vec<Platform>,
+    /// Solid static geometry, like slopes and ramps.
+    #[serde(default)]
+    pub geometry: Vec<Collider>,
+    /// Walls and rooms drawn with tiles.
+    #[serde(default)]
+    pub tiles: Option<TileMap>,
+    /// Areas that restart the level from the last checkpoint on touch.
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
     #[serde(default)]
     pub items: Vec<SolverItem>,
+    /// Sprites drawn behind everything else.
+    #[serde(default)]
+    pub decorations: Vec<LevelDecoration>,
+    #[serde(default)]
+    pub hide_bounds: bool,
+    /// Grandpa can drill through the floor, dropping everything below the level.
+    #[serde(default)]
+    pub drillable_floor: bool,
+    /// Stream the player's position to the dispatcher's monitor.
+    #[serde(default)]
+    pub stream_player: bool,
+    /// Reveal the meme folder on the dispatcher's monitor from this level on.
+    #[serde(default)]
+    pub unlocks_memes: bool,
+    #[serde(default)]
+    pub triggers: Vec<LevelTrigger>,
+    /// Regions that move the respawn point once the player enters them, in the order of progress.
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub area: Aabb2<FCoord>,
+    pub spawnpoint: vec2<FCoord>,
+}
+
+impl SolverLevel {
+    /// Where the player appears after entering the level or respawning.
+    pub fn respawn_point(&self, checkpoint: Option<usize>) -> vec2<FCoord> {
+        checkpoint
+            .and_then(|i| self.checkpoints.get(i))
+            .map_or(self.spawnpoint, |checkpoint| checkpoint.spawnpoint)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelDecoration {
+    pub sprite: LevelSprite,
+    pub area: Aabb2<f32>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Sprites that levels can place.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LevelSprite {
+    Level1,
+    GreenHint,
+    BubbleTea,
+    BubbleDoor,
+    Dispatcher,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LevelMusic {
+    Menu,
+    Dispatcher,
+    Boss,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Platform {
     pub pos: vec2<FCoord>,
     pub width: FCoord,
+    /// Move along the path, starting from `pos`.
+    #[serde(default)]
+    pub path: Option<PlatformPath>,
+    /// Holding down lets the player fall through the platform.
+    #[serde(default)]
+    pub drop_through: bool,
+    /// Seconds the platform holds the player before falling apart until the level restarts.
+    #[serde(default)]
+    pub crumble_time: Option<FTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlatformPath {
+    /// Points visited after `pos`, before returning to it and starting over.
+    pub waypoints: Vec<vec2<FCoord>>,
+    /// Distance travelled per second.
+    pub speed: FCoord,
+}
+
+impl Platform {
+    pub fn new(pos: vec2<FCoord>, width: FCoord) -> Self {
+        Self {
+            pos,
+            width,
+            path: None,
+            drop_through: false,
+            crumble_time: None,
+        }
+    }
+
+    /// Area covered by the platform, whose top edge is centered at `pos`.
+    pub fn aabb(&self, aspect: FCoord) -> Aabb2<FCoord> {
+        Aabb2::point(self.pos)
+            .extend_symmetric(vec2(self.width, FCoord::ZERO))
+            .extend_down(self.width / aspect)
+    }
+
+    /// Position of the platform after travelling the distance along its path.
+    pub fn position_at(&self, distance: FCoord) -> vec2<FCoord> {
+        let Some(path) = &self.path else {
+            return self.pos;
+        };
+        let points: Vec<vec2<FCoord>> = std::iter::once(self.pos)
+            .chain(path.waypoints.iter().copied())
+            .chain(std::iter::once(self.pos))
+            .collect();
+        let length = points
+            .windows(2)
+            .fold(FCoord::ZERO, |length, w| length + (w[1] - w[0]).len());
+        if length == FCoord::ZERO {
+            return self.pos;
+        }
+
+        let mut distance = distance - (distance / length).floor() * length;
+        for segment in points.windows(2) {
+            let (from, to) = (segment[0], segment[1]);
+            let segment_length = (to - from).len();
+            if segment_length == FCoord::ZERO {
+                continue;
+            }
+            if distance <= segment_length {
+                return from + (to - from) * (distance / segment_length);
+            }
+            distance -= segment_length;
+        }
+        self.pos
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hazard {
+    pub area: Aabb2<FCoord>,
+    /// Draw spikes along the area, otherwise it is an invisible kill zone.
+    #[serde(default)]
+    pub spikes: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -108,6 +331,12 @@ pub struct SolverItem {
     pub can_pickup: bool,
     #[serde(default)]
     pub has_gravity: bool,
+    /// Shoot at the player until the exit opens.
+    #[serde(default)]
+    pub shoots: bool,
+    /// Stay where it is when the player respawns at a checkpoint.
+    #[serde(default)]
+    pub persistent: bool,
     pub collider: Collider,
 }
 
@@ -134,4 +363,14 @@ impl SolverSprites {
             SolverItemKind::BubbleCode => &self.bubble_code,
         }
     }
+
+    pub fn level_sprite(&self, sprite: LevelSprite) -> &Rc<PixelTexture> {
+        match sprite {
+            LevelSprite::Level1 => &self.level1,
+            LevelSprite::GreenHint => &self.green_hint,
+            LevelSprite::BubbleTea => &self.bubble_tea,
+            LevelSprite::BubbleDoor => &self.bubble_door,
+            LevelSprite::Dispatcher => &self.dispatcher,
+        }
+    }
 }
@@ -0,0 +1,304 @@
+use super::*;
+
+use crate::model::{Collider, FCoord};
+
+/// Characters that leave a cell of the tile map empty.
+#[cfg(not(target_arch = "wasm32"))]
+const EMPTY_TILES: [char; 2] = ['.', ' '];
+/// Characters given to the tiles imported from Tiled, in the order of their appearance.
+#[cfg(not(target_arch = "wasm32"))]
+const IMPORTED_TILES: &str = "#ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Grid of tiles drawn as character art, with the top row first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileMap {
+    /// Bottom left corner of the map.
+    #[serde(default = "default_origin")]
+    pub origin: vec2<FCoord>,
+    pub tile_size: FCoord,
+    /// Tiles of the characters used in the rows, dots and spaces are empty.
+    pub legend: HashMap<char, Tile>,
+    pub rows: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Tile {
+    /// Index of the tile in the tileset sprite sheet, counting from the left.
+    pub index: usize,
+    /// Decorative tiles do not block the player.
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+}
+
+fn default_origin() -> vec2<FCoord> {
+    vec2::ZERO
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+impl TileMap {
+    /// Tiles with their cells, counting rows from the bottom.
+    pub fn tiles(&self) -> impl Iterator<Item = (vec2<usize>, Tile)> + '_ {
+        let height = self.rows.len();
+        self.rows.iter().enumerate().flat_map(move |(row, line)| {
+            let y = height - 1 - row;
+            line.chars()
+                .enumerate()
+                .filter_map(move |(x, c)| self.legend.get(&c).map(|&tile| (vec2(x, y), tile)))
+        })
+    }
+
+    /// Characters used in the rows that are neither empty nor in the legend.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn unknown_characters(&self) -> Vec<char> {
+        let mut unknown: Vec<char> = self
+            .rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .filter(|c| !EMPTY_TILES.contains(c) && !self.legend.contains_key(c))
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        unknown
+    }
+
+    pub fn cell_aabb(&self, cell: vec2<usize>) -> Aabb2<FCoord> {
+        let min = self.origin + cell.map(|x| r32(x as f32)) * self.tile_size;
+        Aabb2::point(min).extend_positive(vec2::splat(self.tile_size))
+    }
+
+    /// Colliders of the solid tiles, merged into as few rectangles as possible.
+    pub fn colliders(&self) -> Vec<Collider> {
+        let mut solid: Vec<Vec<bool>> = vec![Vec::new(); self.rows.len()];
+        for (cell, tile) in self.tiles() {
+            let row = &mut solid[cell.y];
+            if row.len() <= cell.x {
+                row.resize(cell.x + 1, false);
+            }
+            row[cell.x] |= tile.solid;
+        }
+
+        // Horizontal runs of tiles, grown upwards while the rows above have the same run
+        let mut rects = Vec::new();
+        let mut open: Vec<Aabb2<usize>> = Vec::new();
+        for (y, row) in solid.iter().enumerate() {
+            let mut next = Vec::new();
+            let mut x = 0;
+            while x < row.len() {
+                if !row[x] {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < row.len() && row[x] {
+                    x += 1;
+                }
+                let rect = match open
+                    .iter()
+                    .position(|rect| rect.min.x == start && rect.max.x == x)
+                {
+                    Some(i) => {
+                        let mut rect = open.swap_remove(i);
+                        rect.max.y += 1;
+                        rect
+                    }
+                    None => Aabb2 {
+                        min: vec2(start, y),
+                        max: vec2(x, y + 1),
+                    },
+                };
+                next.push(rect);
+            }
+            rects.append(&mut open);
+            open = next;
+        }
+        rects.append(&mut open);
+
+        rects
+            .into_iter()
+            .map(|rect| {
+                let min = self.cell_aabb(rect.min).min;
+                let max = self.cell_aabb(rect.max - vec2(1, 1)).max;
+                Collider::aabb(Aabb2 { min, max })
+            })
+            .collect()
+    }
+
+    /// Convert the first tile layer of a map exported from Tiled as JSON,
+    /// scaled to fill the height of the level.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_tiled(json: &str) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct TiledMap {
+            width: usize,
+            layers: Vec<TiledLayer>,
+            tilesets: Vec<TiledTileset>,
+        }
+        #[derive(Deserialize)]
+        struct TiledLayer {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            data: Vec<u32>,
+        }
+        #[derive(Deserialize)]
+        struct TiledTileset {
+            firstgid: u32,
+        }
+        /// Higher bits of the tile ids store the flips.
+        const TILED_ID_MASK: u32 = 0x1FFF_FFFF;
+
+        let map: TiledMap = serde_json::from_str(json)?;
+        let layer = map
+            .layers
+            .iter()
+            .find(|layer| layer.kind == "tilelayer")
+            .ok_or_else(|| anyhow::anyhow!("the map has no tile layers"))?;
+        let first_id = map.tilesets.first().map_or(1, |tileset| tileset.firstgid);
+        if map.width == 0 {
+            anyhow::bail!("the map is empty");
+        }
+
+        let mut legend = HashMap::new();
+        let mut characters = HashMap::new();
+        let mut available = IMPORTED_TILES.chars();
+        let mut rows = Vec::new();
+        for ids in layer.data.chunks(map.width) {
+            let mut row = String::new();
+            for &id in ids {
+                let id = id & TILED_ID_MASK;
+                if id < first_id {
+                    row.push('.');
+                    continue;
+                }
+                let index = (id - first_id) as usize;
+                let c = match characters.get(&index) {
+                    Some(&c) => c,
+                    None => {
+                        let c = available
+                            .next()
+                            .ok_or_else(|| anyhow::anyhow!("too many different tiles"))?;
+                        characters.insert(index, c);
+                        legend.insert(c, Tile { index, solid: true });
+                        c
+                    }
+                };
+                row.push(c);
+            }
+            rows.push(row);
+        }
+
+        Ok(Self {
+            origin: vec2::ZERO,
+            tile_size: r32(crate::model::LEVEL_SIZE.y / rows.len().max(1) as f32),
+            legend,
+            rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map of unit tiles, `#` is solid and `~` is decorative.
+    fn map(rows: &[&str]) -> TileMap {
+        let tile = |index, solid| Tile { index, solid };
+        TileMap {
+            origin: vec2::ZERO,
+            tile_size: FCoord::ONE,
+            legend: HashMap::from([('#', tile(0, true)), ('~', tile(1, false))]),
+            rows: rows.iter().map(|row| row.to_string()).collect(),
+        }
+    }
+
+    /// Rectangles of the colliders, sorted from the bottom left.
+    fn rects(map: &TileMap) -> Vec<Aabb2<f32>> {
+        let mut rects: Vec<Aabb2<f32>> = map
+            .colliders()
+            .iter()
+            .map(|collider| collider.compute_aabb().map(|x| x.as_f32()))
+            .collect();
+        rects.sort_by(|a, b| (a.min.y, a.min.x).partial_cmp(&(b.min.y, b.min.x)).unwrap());
+        rects
+    }
+
+    fn rect(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Aabb2<f32> {
+        Aabb2 {
+            min: vec2(min_x, min_y),
+            max: vec2(max_x, max_y),
+        }
+    }
+
+    #[test]
+    fn l_shape_is_merged() {
+        let tiles = map(&["#..", "#..", "###"]);
+        assert_eq!(
+            rects(&tiles),
+            vec![rect(0.0, 0.0, 3.0, 1.0), rect(0.0, 1.0, 1.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn hole_is_left_open() {
+        let tiles = map(&["###", "#.#", "###"]);
+        assert_eq!(
+            rects(&tiles),
+            vec![
+                rect(0.0, 0.0, 3.0, 1.0),
+                rect(0.0, 1.0, 1.0, 2.0),
+                rect(2.0, 1.0, 3.0, 2.0),
+                rect(0.0, 2.0, 3.0, 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let tiles = map(&["##", "#", "###"]);
+        assert_eq!(
+            rects(&tiles),
+            vec![
+                rect(0.0, 0.0, 3.0, 1.0),
+                rect(0.0, 1.0, 1.0, 2.0),
+                rect(0.0, 2.0, 2.0, 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn decorative_tiles_do_not_collide() {
+        let tiles = map(&["~~#", "~~#"]);
+        assert_eq!(rects(&tiles), vec![rect(2.0, 0.0, 3.0, 2.0)]);
+        assert!(map(&["~~", "~"]).colliders().is_empty());
+    }
+
+    #[test]
+    fn tiled_import() {
+        let flipped = 0x8000_0005u32;
+        let json = format!(
+            r#"{{
+                "width": 3,
+                "layers": [
+                    {{ "type": "objectgroup" }},
+                    {{ "type": "tilelayer", "data": [0, 5, 5, {flipped}, 0, 6] }}
+                ],
+                "tilesets": [{{ "firstgid": 5 }}]
+            }}"#
+        );
+        let tiles = TileMap::from_tiled(&json).unwrap();
+        assert_eq!(tiles.rows, vec![".##", "#.A"]);
+        assert_eq!(tiles.legend[&'#'].index, 0);
+        assert_eq!(tiles.legend[&'A'].index, 1);
+        assert_eq!(tiles.legend.len(), 2);
+        assert_eq!(tiles.tile_size, r32(crate::model::LEVEL_SIZE.y / 2.0));
+    }
+
+    #[test]
+    fn tiled_map_needs_a_tile_layer() {
+        let json = r#"{ "width": 1, "layers": [], "tilesets": [] }"#;
+        assert!(TileMap::from_tiled(json).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use super::*;
+
+use crate::model::{
+    Collider, FCoord, FTime, LEVEL_SIZE, PLATFORM_ASPECT, Shape, TriggerAction, TriggerEvent,
+};
+
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+/// Check the assets directory the way the game loads it, collecting every problem found.
+/// The expected files mirror the `Load` derives of the asset structs.
+pub fn validate_assets(dir: &Path) -> Vec<String> {
+    let mut validator = Validator::new(dir);
+    validator.validate_assets();
+    validator.errors
+}
+
+/// Check the level pack directory, collecting every problem found.
+pub fn validate_pack(dir: &Path) -> Vec<String> {
+    let mut validator = Validator::new(dir);
+    if let Err(error) = pack_name(dir) {
+        validator.errors.push(format!("{error:#}"));
+    }
+    validator.validate_levels("solver/levels");
+    if dir.join("dispatcher").join("level").is_dir() {
+        validator.validate_room("dispatcher/level");
+    }
+    validator.errors
+}
+
+struct Validator<'a> {
+    root: &'a Path,
+    errors: Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(root: &'a Path) -> Self {
+        Self {
+            root,
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, path: &str, message: impl fmt::Display) {
+        let path = self.root.join(path);
+        self.errors.push(format!("{}: {message}", path.display()));
+    }
+
+    fn file(&mut self, path: &str) -> bool {
+        if self.root.join(path).is_file() {
+            return true;
+        }
+        self.error(path, "file not found");
+        false
+    }
+
+    fn files(&mut self, dir: &str, names: &[&str], ext: &str) {
+        for name in names {
+            let path = if name.contains('.') {
+                format!("{dir}/{name}")
+            } else {
+                format!("{dir}/{name}.{ext}")
+            };
+            self.file(&path);
+        }
+    }
+
+    fn lists(&mut self, dir: &str, lists: &[(&str, u32, u32)], ext: &str) {
+        for &(name, first, last) in lists {
+            for i in first..=last {
+                self.file(&format!("{dir}/{name}/{i}.{ext}"));
+            }
+        }
+    }
+
+    fn ron<T: DeserializeOwned>(&mut self, path: &str) -> Option<T> {
+        if !self.file(path) {
+            return None;
+        }
+        let result = std::fs::read_to_string(self.root.join(path))
+            .map_err(|error| error.to_string())
+            .and_then(|text| ron::from_str(&text).map_err(|error| error.to_string()));
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.error(path, error);
+                None
+            }
+        }
+    }
+
+    fn validate_assets(&mut self) {
+        self.file("default.ttf");
+        self.ron::<Palette>("palette.ron");
+        self.files("shaders", &["masked", "texture_ui"], "glsl");
+        self.files("sounds", &SoundAssets::FILES, "mp3");
+        self.lists("sounds", &SoundAssets::LISTS, "mp3");
+        self.files("atlas", &SpritesAtlas::FILES, "png");
+
+        self.ron::<SolverControls>("solver/controls.ron");
+        if let Some(rules) = self.ron::<SolverRules>("solver/rules.ron")
+            && rules.ticks_per_second <= FTime::ZERO
+        {
+            self.error("solver/rules.ron", "ticks per second are not positive");
+        }
+        self.files("solver/sprites", &SolverSprites::FILES, "png");
+        self.lists("solver/sprites", &SolverSprites::LISTS, "png");
+        self.validate_levels("solver/levels");
+
+        self.files("dispatcher/sprites", &DispatcherSprites::FILES, "png");
+        self.lists("dispatcher/sprites", &DispatcherSprites::LISTS, "png");
+        self.validate_room("dispatcher/level");
+        self.lists("dispatcher", &[("files", 0, 4)], "txt");
+        self.files("dispatcher", &["book_text", "novella"], "txt");
+    }
+
+    fn validate_levels(&mut self, dir: &str) {
+        let Some(names) = self.ron::<Vec<String>>(&format!("{dir}/_list.ron")) else {
+            return;
+        };
+        if names.is_empty() {
+            self.error(&format!("{dir}/_list.ron"), "no levels listed");
+        }
+        for name in names {
+            let path = format!("{dir}/{name}");
+            if let Some(level) = self.ron::<SolverLevel>(&path) {
+                self.validate_level(&path, &level);
+            }
+        }
+    }
+
+    /// Basic checks that the level can be completed.
+    fn validate_level(&mut self, path: &str, level: &SolverLevel) {
+        // Walls that are never removed block the player for the whole level
+        let walls = level
+            .triggers
+            .iter()
+            .filter(|trigger| trigger.on == TriggerEvent::Enter && trigger.when.is_empty())
+            .flat_map(|trigger| &trigger.then)
+            .filter_map(|action| match action {
+                TriggerAction::SpawnWall {
+                    position,
+                    size,
+                    rotation,
+                    until: None,
+                    ..
+                } => Some(Collider {
+                    position: *position,
+                    rotation: Angle::from_degrees(*rotation).as_r32(),
+                    shape: Shape::Rectangle {
+                        width: size.x,
+                        height: size.y,
+                    },
+                }),
+                _ => None,
+            });
+        let solids: Vec<Collider> = level
+            .platforms
+            .iter()
+            .map(|platform| Collider::aabb(platform.aabb(r32(PLATFORM_ASPECT))))
+            .chain(walls)
+            .chain(level.geometry.iter().cloned())
+            .chain(level.tiles.iter().flat_map(|tiles| tiles.colliders()))
+            .collect();
+
+        let bounds = Aabb2::ZERO.extend_positive(LEVEL_SIZE).as_r32();
+        let spawnpoints = std::iter::once(("spawnpoint".to_owned(), level.spawnpoint)).chain(
+            level.checkpoints.iter().enumerate().map(|(i, checkpoint)| {
+                (
+                    format!("spawnpoint of checkpoint {i}"),
+                    checkpoint.spawnpoint,
+                )
+            }),
+        );
+        for (name, spawnpoint) in spawnpoints {
+            if !bounds.contains(spawnpoint) {
+                self.error(path, format!("{name} is outside of the level"));
+            }
+            // Center of the player standing at the spawnpoint
+            let center = spawnpoint + vec2(0.0, 0.75).as_r32();
+            if solids.iter().any(|solid| solid.contains(center)) {
+                self.error(path, format!("{name} is inside a wall"));
+            }
+            if level
+                .hazards
+                .iter()
+                .any(|hazard| hazard.area.contains(center))
+            {
+                self.error(path, format!("{name} is inside a hazard"));
+            }
+        }
+        for (i, platform) in level.platforms.iter().enumerate() {
+            if let Some(platform_path) = &platform.path
+                && platform_path.speed <= FCoord::ZERO
+            {
+                self.error(path, format!("platform {i} has a path but does not move"));
+            }
+        }
+        for (i, collider) in level.geometry.iter().enumerate() {
+            if let Shape::Polygon { points } = &collider.shape
+                && points.len() < 3
+            {
+                self.error(path, format!("geometry {i} has less than 3 points"));
+            }
+        }
+        if let Some(tiles) = &level.tiles {
+            if tiles.tile_size <= FCoord::ZERO {
+                self.error(path, "tile size is not positive");
+            }
+            let unknown = tiles.unknown_characters();
+            if !unknown.is_empty() {
+                let unknown: String = unknown.into_iter().collect();
+                self.error(
+                    path,
+                    format!("tiles {unknown:?} are missing from the legend"),
+                );
+            }
+        }
+        let transition = level.transition.center();
+        if solids.iter().any(|solid| solid.contains(transition)) {
+            self.error(path, "transition is inside a wall");
+        }
+    }
+
+    fn validate_room(&mut self, dir: &str) {
+        for side in DispatcherViewSide::ALL {
+            self.ron::<DispatcherView>(&format!("{dir}/{}", side.file_name()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assets_dir() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    #[test]
+    fn shipped_assets_are_valid() {
+        assert_eq!(validate_assets(&assets_dir()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn shipped_levels_are_a_valid_pack() {
+        assert_eq!(validate_pack(&assets_dir()), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,242 @@
+use geng::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    /// Bottom face button, A on Xbox controllers.
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+/// Gamepad input that can be bound to an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GamepadInput {
+    Button(GamepadButton),
+    /// Stick tilted in the direction, with the Y axis pointing up.
+    Axis(GamepadAxis, AxisDirection),
+}
+
+impl GamepadButton {
+    pub const ALL: [Self; 16] = [
+        Self::South,
+        Self::East,
+        Self::North,
+        Self::West,
+        Self::LeftTrigger,
+        Self::LeftTrigger2,
+        Self::RightTrigger,
+        Self::RightTrigger2,
+        Self::Select,
+        Self::Start,
+        Self::LeftThumb,
+        Self::RightThumb,
+        Self::DPadUp,
+        Self::DPadDown,
+        Self::DPadLeft,
+        Self::DPadRight,
+    ];
+}
+
+impl GamepadAxis {
+    pub const ALL: [Self; 4] = [
+        Self::LeftStickX,
+        Self::LeftStickY,
+        Self::RightStickX,
+        Self::RightStickY,
+    ];
+}
+
+/// State of all connected gamepads combined, updated by [`Gamepads::poll`] once per frame.
+/// Gamepads are not supported on the web.
+pub struct Gamepads {
+    #[cfg(not(target_arch = "wasm32"))]
+    gilrs: Option<gilrs::Gilrs>,
+    pressed: HashSet<GamepadButton>,
+    /// Buttons pressed since the previous poll.
+    just_pressed: HashSet<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+    previous_axes: HashMap<GamepadAxis, f32>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            gilrs: match gilrs::Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(error) => {
+                    log::warn!("Gamepads are unavailable: {error}");
+                    None
+                }
+            },
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            axes: HashMap::new(),
+            previous_axes: HashMap::new(),
+        }
+    }
+
+    /// Process the input received since the last poll.
+    pub fn poll(&mut self) {
+        self.just_pressed.clear();
+        self.previous_axes = std::mem::take(&mut self.axes);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(event) = gilrs.next_event() {
+                if let gilrs::EventType::ButtonPressed(button, _) = event.event
+                    && let Some(button) = GamepadButton::from_gilrs(button)
+                {
+                    self.just_pressed.insert(button);
+                }
+            }
+
+            self.pressed.clear();
+            for (_, gamepad) in gilrs.gamepads() {
+                for button in GamepadButton::ALL {
+                    if gamepad.is_pressed(button.to_gilrs()) {
+                        self.pressed.insert(button);
+                    }
+                }
+                // The most tilted stick of all gamepads wins
+                for axis in GamepadAxis::ALL {
+                    let value = gamepad.value(axis.to_gilrs());
+                    let current = self.axes.entry(axis).or_insert(0.0);
+                    if value.abs() > current.abs() {
+                        *current = value;
+                    }
+                }
+            }
+        }
+    }
+
+    /// How much the input is held from 0 to 1, with the stick tilt inside the deadzone ignored.
+    pub fn value(&self, input: GamepadInput, deadzone: f32) -> f32 {
+        Self::input_value(&self.pressed, &self.axes, input, deadzone)
+    }
+
+    /// Strongest of the inputs.
+    pub fn max_value(&self, inputs: &[GamepadInput], deadzone: f32) -> f32 {
+        inputs
+            .iter()
+            .map(|&input| self.value(input, deadzone))
+            .fold(0.0, f32::max)
+    }
+
+    pub fn is_pressed(&self, inputs: &[GamepadInput], deadzone: f32) -> bool {
+        self.max_value(inputs, deadzone) > 0.0
+    }
+
+    /// Whether any of the inputs has been pressed, or the stick tilted, since the previous poll.
+    pub fn is_just_pressed(&self, inputs: &[GamepadInput], deadzone: f32) -> bool {
+        inputs.iter().any(|&input| match input {
+            GamepadInput::Button(button) => self.just_pressed.contains(&button),
+            GamepadInput::Axis(..) => {
+                self.value(input, deadzone) > 0.0
+                    && Self::input_value(&self.pressed, &self.previous_axes, input, deadzone) == 0.0
+            }
+        })
+    }
+
+    fn input_value(
+        pressed: &HashSet<GamepadButton>,
+        axes: &HashMap<GamepadAxis, f32>,
+        input: GamepadInput,
+        deadzone: f32,
+    ) -> f32 {
+        match input {
+            GamepadInput::Button(button) => {
+                if pressed.contains(&button) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            GamepadInput::Axis(axis, direction) => {
+                let value = axes.get(&axis).copied().unwrap_or(0.0);
+                let value = match direction {
+                    AxisDirection::Positive => value,
+                    AxisDirection::Negative => -value,
+                };
+                // Rescale so that the value starts from zero at the edge of the deadzone
+                let deadzone = deadzone.clamp(0.0, 0.99);
+                ((value - deadzone) / (1.0 - deadzone)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl GamepadButton {
+    fn from_gilrs(button: gilrs::Button) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_gilrs() == button)
+    }
+
+    fn to_gilrs(self) -> gilrs::Button {
+        match self {
+            Self::South => gilrs::Button::South,
+            Self::East => gilrs::Button::East,
+            Self::North => gilrs::Button::North,
+            Self::West => gilrs::Button::West,
+            Self::LeftTrigger => gilrs::Button::LeftTrigger,
+            Self::LeftTrigger2 => gilrs::Button::LeftTrigger2,
+            Self::RightTrigger => gilrs::Button::RightTrigger,
+            Self::RightTrigger2 => gilrs::Button::RightTrigger2,
+            Self::Select => gilrs::Button::Select,
+            Self::Start => gilrs::Button::Start,
+            Self::LeftThumb => gilrs::Button::LeftThumb,
+            Self::RightThumb => gilrs::Button::RightThumb,
+            Self::DPadUp => gilrs::Button::DPadUp,
+            Self::DPadDown => gilrs::Button::DPadDown,
+            Self::DPadLeft => gilrs::Button::DPadLeft,
+            Self::DPadRight => gilrs::Button::DPadRight,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl GamepadAxis {
+    fn to_gilrs(self) -> gilrs::Axis {
+        match self {
+            Self::LeftStickX => gilrs::Axis::LeftStickX,
+            Self::LeftStickY => gilrs::Axis::LeftStickY,
+            Self::RightStickX => gilrs::Axis::RightStickX,
+            Self::RightStickY => gilrs::Axis::RightStickY,
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use crate::assets::Assets;
+mod gamepad;
+
+pub use self::gamepad::*;
+
+use crate::assets::{Assets, LevelPack};
 
 use geng::prelude::*;
 
@@ -7,6 +11,11 @@ pub struct Context {
     pub geng: Geng,
     pub assets: Rc<Hot<Assets>>,
     pub music: MusicManager,
+    pub gamepads: Rc<RefCell<Gamepads>>,
+    /// File to record the solver's session into.
+    pub record: Option<std::path::PathBuf>,
+    /// Level pack to play instead of the shipped levels.
+    pub pack: Option<Rc<LevelPack>>,
 }
 
 impl Context {
@@ -15,6 +24,9 @@ impl Context {
             geng,
             assets,
             music: MusicManager::new(),
+            gamepads: Rc::new(RefCell::new(Gamepads::new())),
+            record: None,
+            pack: None,
         }
     }
 }
@@ -0,0 +1,319 @@
+use super::*;
+
+use crate::assets::{
+    self, DispatcherItem, DispatcherItemPosition, DispatcherLevel, DispatcherView,
+    DispatcherViewSide,
+};
+use geng_utils::conversions::*;
+
+const SCREEN_SIZE: vec2<usize> = vec2(1920, 1080);
+/// Step of the grid that dragged items snap to, in pixels.
+const GRID_STEP: f32 = 5.0;
+/// Items in the order `Tab` cycles through them.
+const ITEMS: [DispatcherItem; 20] = [
+    DispatcherItem::Door,
+    DispatcherItem::DoorSign,
+    DispatcherItem::Table,
+    DispatcherItem::Monitor,
+    DispatcherItem::RealMouse,
+    DispatcherItem::Cactus,
+    DispatcherItem::Book,
+    DispatcherItem::TheSock,
+    DispatcherItem::ButtonStation,
+    DispatcherItem::Bfb,
+    DispatcherItem::ButtonYellow,
+    DispatcherItem::ButtonGreen,
+    DispatcherItem::ButtonSalad,
+    DispatcherItem::ButtonPink,
+    DispatcherItem::ButtonBlue,
+    DispatcherItem::ButtonWhite,
+    DispatcherItem::ButtonPurple,
+    DispatcherItem::ButtonOrange,
+    DispatcherItem::ButtonCyan,
+    DispatcherItem::Tea,
+];
+
+/// Edits the layout of the dispatcher's room and saves it back to the view files.
+pub struct DispatcherEditor {
+    context: Context,
+    final_texture: ugli::Texture,
+    screen: Aabb2<f32>,
+    camera: Camera2d,
+    /// Position of the cursor in the room.
+    cursor: vec2<f32>,
+
+    dir: std::path::PathBuf,
+    level: DispatcherLevel,
+    side: DispatcherViewSide,
+    unsaved: bool,
+
+    /// Index of the selected item in the current view.
+    selected: Option<usize>,
+    /// Offset from the cursor to the anchor of the dragged item.
+    drag: Option<vec2<f32>>,
+    /// Kind of the next placed item.
+    item_kind: DispatcherItem,
+
+    announcement: Option<Announcement>,
+    transition: Option<geng::state::Transition>,
+}
+
+impl DispatcherEditor {
+    pub fn new(context: &Context) -> anyhow::Result<Self> {
+        // A pack without its own room starts from the shipped one
+        let (dir, level) = match &context.pack {
+            Some(pack) => match &pack.room {
+                Some(room) => (pack.room_dir(), room.clone()),
+                None => (
+                    pack.room_dir(),
+                    assets::load_room(&assets::default_room_dir())?,
+                ),
+            },
+            None => {
+                let dir = assets::default_room_dir();
+                let level = assets::load_room(&dir)?;
+                (dir, level)
+            }
+        };
+        Ok(Self {
+            context: context.clone(),
+            final_texture: geng_utils::texture::new_texture(context.geng.ugli(), SCREEN_SIZE),
+            screen: Aabb2::ZERO.extend_positive(vec2(1.0, 1.0)),
+            camera: Camera2d {
+                center: SCREEN_SIZE.as_f32() / 2.0,
+                rotation: Angle::ZERO,
+                fov: Camera2dFov::Vertical(SCREEN_SIZE.y as f32),
+            },
+            cursor: vec2::ZERO,
+
+            dir,
+            level,
+            side: DispatcherViewSide::Front,
+            unsaved: false,
+
+            selected: None,
+            drag: None,
+            item_kind: ITEMS[0],
+
+            announcement: None,
+            transition: None,
+        })
+    }
+
+    fn view(&self) -> &DispatcherView {
+        self.level.get_side(self.side)
+    }
+
+    /// Current view, marked as changed.
+    fn view_mut(&mut self) -> &mut DispatcherView {
+        self.unsaved = true;
+        self.level.get_side_mut(self.side)
+    }
+
+    fn save(&mut self) {
+        let config = ron::ser::PrettyConfig::default()
+            .extensions(ron::extensions::Extensions::IMPLICIT_SOME);
+        if let Err(error) = std::fs::create_dir_all(&self.dir) {
+            log::error!("Failed to create the directory {:?}: {error:?}", self.dir);
+            self.announcement = Some(Announcement::new("Не удалось сохранить комнату"));
+            return;
+        }
+        for side in DispatcherViewSide::ALL {
+            let path = self.dir.join(side.file_name());
+            if let Err(error) = save_ron(&path, self.level.get_side(side), config.clone()) {
+                log::error!("Failed to save the view {path:?}: {error:?}");
+                self.announcement = Some(Announcement::new("Не удалось сохранить комнату"));
+                return;
+            }
+        }
+        log::info!("Saved the dispatcher's room");
+        self.unsaved = false;
+        self.announcement = Some(Announcement::new("Комната сохранена"));
+    }
+
+    fn turn(&mut self, side: DispatcherViewSide) {
+        self.side = side;
+        self.selected = None;
+        self.drag = None;
+    }
+
+    fn item_aabb(&self, index: usize) -> Aabb2<f32> {
+        let (item, position) = &self.view().items[index];
+        let assets = self.context.assets.get();
+        let texture = assets.dispatcher.sprites.item_texture(*item);
+        position.aabb(texture.size().as_f32())
+    }
+
+    /// The topmost item under the cursor.
+    fn hovered(&self) -> Option<usize> {
+        (0..self.view().items.len())
+            .rev()
+            .find(|&i| self.item_aabb(i).contains(self.cursor))
+    }
+
+    fn cursor_press(&mut self) {
+        self.selected = self.hovered();
+        self.drag = self
+            .selected
+            .map(|i| self.view().items[i].1.anchor - self.cursor);
+    }
+
+    fn cursor_move(&mut self, position: vec2<f64>) {
+        let pos = (position.as_f32() - self.screen.bottom_left()) / self.screen.size()
+            * SCREEN_SIZE.as_f32();
+        self.cursor = self.camera.screen_to_world(SCREEN_SIZE.as_f32(), pos);
+        if let Some(i) = self.selected
+            && let Some(offset) = self.drag
+        {
+            let anchor = snap_to_grid(&self.context.geng, self.cursor + offset, GRID_STEP);
+            if anchor != self.view().items[i].1.anchor {
+                self.view_mut().items[i].1.anchor = anchor;
+            }
+        }
+    }
+
+    /// Resize the selected item, keeping its aspect ratio.
+    fn scroll(&mut self, delta: f64) {
+        let Some(i) = self.selected else {
+            return;
+        };
+        let scale = if delta > 0.0 { 1.1 } else { 1.0 / 1.1 };
+        let size = self.item_aabb(i).size() * scale;
+        self.view_mut().items[i].1.size = Some(size.map(f32::round));
+    }
+
+    fn reset_size(&mut self) {
+        if let Some(i) = self.selected {
+            self.view_mut().items[i].1.size = None;
+        }
+    }
+
+    fn add_item(&mut self) {
+        let position = DispatcherItemPosition {
+            anchor: snap_to_grid(&self.context.geng, self.cursor, GRID_STEP),
+            alignment: vec2(0.5, 0.5),
+            size: None,
+        };
+        let item = self.item_kind;
+        let items = &mut self.view_mut().items;
+        items.push((item, position));
+        let index = items.len() - 1;
+        self.selected = Some(index);
+    }
+
+    fn next_item_kind(&mut self) {
+        let i = ITEMS
+            .iter()
+            .position(|&item| item == self.item_kind)
+            .unwrap_or(0);
+        self.item_kind = ITEMS[(i + 1) % ITEMS.len()];
+    }
+
+    fn delete_selected(&mut self) {
+        if let Some(i) = self.selected.take() {
+            self.view_mut().items.remove(i);
+            self.drag = None;
+        }
+    }
+
+    fn draw_room(&mut self) {
+        let layout: Vec<Aabb2<f32>> = (0..self.view().items.len())
+            .map(|i| self.item_aabb(i))
+            .collect();
+        let framebuffer = &mut geng_utils::texture::attach_texture(
+            &mut self.final_texture,
+            self.context.geng.ugli(),
+        );
+        let assets = self.context.assets.get();
+        let geng = &self.context.geng;
+        let camera = &self.camera;
+        ugli::clear(framebuffer, Some(assets.palette.background), None, None);
+
+        let view = self.level.get_side(self.side);
+        for ((item, _), &aabb) in view.items.iter().zip(&layout) {
+            geng_utils::texture::DrawTexture::new(assets.dispatcher.sprites.item_texture(*item))
+                .fit(aabb, vec2(0.5, 0.5))
+                .draw(camera, geng, framebuffer);
+        }
+
+        // Hitboxes
+        for (i, &aabb) in layout.iter().enumerate() {
+            let (width, color) = if self.selected == Some(i) {
+                (4.0, Rgba::RED)
+            } else {
+                (2.0, Rgba::new(0.0, 0.5, 1.0, 0.8))
+            };
+            draw_outline(geng, camera, aabb, width, color, framebuffer);
+        }
+    }
+
+    fn help_lines(&self) -> Vec<String> {
+        let unsaved = if self.unsaved { " *" } else { "" };
+        let mut lines = vec![format!(
+            "Сторона {:?}{unsaved}, новый предмет: {:?}",
+            self.side, self.item_kind,
+        )];
+        if let Some(i) = self.selected {
+            let (item, position) = &self.view().items[i];
+            let size = self.item_aabb(i).size();
+            lines.push(format!(
+                "{item:?}: якорь ({}, {}), размер {}x{}",
+                position.anchor.x, position.anchor.y, size.x, size.y,
+            ));
+        }
+        lines.push(
+            "ЛКМ - перетащить, колесо - размер, R - размер текстуры, I - предмет, \
+             Tab - тип предмета, Delete - удалить"
+                .to_owned(),
+        );
+        lines.push("Стрелки - повернуться, Ctrl+S - сохранить, Esc - выйти".to_owned());
+        lines
+    }
+}
+
+impl geng::State for DispatcherEditor {
+    fn handle_event(&mut self, event: geng::Event) {
+        match event {
+            geng::Event::CursorMove { position } => self.cursor_move(position),
+            geng::Event::MousePress {
+                button: geng::MouseButton::Left,
+            } => self.cursor_press(),
+            geng::Event::MouseRelease {
+                button: geng::MouseButton::Left,
+            } => self.drag = None,
+            geng::Event::Wheel { delta } => self.scroll(delta),
+            geng::Event::KeyPress { key } => match key {
+                geng::Key::S if is_ctrl_pressed(&self.context.geng) => self.save(),
+                geng::Key::ArrowLeft => self.turn(self.side.cycle_left()),
+                geng::Key::ArrowRight => self.turn(self.side.cycle_right()),
+                geng::Key::R => self.reset_size(),
+                geng::Key::I => self.add_item(),
+                geng::Key::Tab => self.next_item_kind(),
+                geng::Key::Delete => self.delete_selected(),
+                geng::Key::Escape => {
+                    if can_discard(&self.context.geng, self.unsaved, &mut self.announcement) {
+                        self.transition = Some(geng::state::Transition::Pop);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::state::Transition> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Rgba::BLACK), None, None);
+        self.draw_room();
+        let draw = geng_utils::texture::DrawTexture::new(&self.final_texture)
+            .fit_screen(vec2(0.5, 0.5), framebuffer);
+        self.screen = draw.target;
+        draw.draw(&geng::PixelPerfectCamera, &self.context.geng, framebuffer);
+
+        draw_help(&self.context, &self.help_lines(), framebuffer);
+        draw_announcement(&self.context, &mut self.announcement, framebuffer);
+    }
+}
@@ -0,0 +1,93 @@
+mod dispatcher;
+mod solver;
+
+pub use self::{dispatcher::DispatcherEditor, solver::SolverEditor};
+
+use crate::{
+    context::Context,
+    game::{Announcement, draw_announcement},
+};
+
+use geng::prelude::*;
+
+/// Snap the position to the grid, unless Shift is held.
+fn snap_to_grid(geng: &Geng, pos: vec2<f32>, step: f32) -> vec2<f32> {
+    if is_shift_pressed(geng) {
+        return pos;
+    }
+    pos.map(|x| (x / step).round() * step)
+}
+
+fn is_shift_pressed(geng: &Geng) -> bool {
+    let window = geng.window();
+    window.is_key_pressed(geng::Key::ShiftLeft) || window.is_key_pressed(geng::Key::ShiftRight)
+}
+
+fn is_ctrl_pressed(geng: &Geng) -> bool {
+    let window = geng.window();
+    window.is_key_pressed(geng::Key::ControlLeft) || window.is_key_pressed(geng::Key::ControlRight)
+}
+
+/// Whether the unsaved changes may be thrown away, holding Shift confirms it.
+fn can_discard(geng: &Geng, unsaved: bool, announcement: &mut Option<Announcement>) -> bool {
+    if !unsaved || is_shift_pressed(geng) {
+        return true;
+    }
+    *announcement = Some(Announcement::new(
+        "Изменения не сохранены: Ctrl+S - сохранить, Shift - продолжить без сохранения",
+    ));
+    false
+}
+
+/// Write the value in the same layout as the hand-written asset files.
+fn save_ron<T: Serialize>(
+    path: &std::path::Path,
+    value: &T,
+    config: ron::ser::PrettyConfig,
+) -> anyhow::Result<()> {
+    let text = ron::ser::to_string_pretty(value, config)?;
+    std::fs::write(path, text + "\n")?;
+    Ok(())
+}
+
+/// Draw the outline of the box.
+fn draw_outline(
+    geng: &Geng,
+    camera: &impl geng::AbstractCamera2d,
+    aabb: Aabb2<f32>,
+    width: f32,
+    color: Rgba<f32>,
+    framebuffer: &mut ugli::Framebuffer,
+) {
+    let [a, b, c, d] = aabb.corners();
+    geng.draw2d().draw2d(
+        framebuffer,
+        camera,
+        &draw2d::Chain::new(vec![a, b, c, d, a], width, color, 0),
+    );
+}
+
+/// Draw lines of text over the bottom of the screen, one band per line.
+fn draw_help(context: &Context, lines: &[String], framebuffer: &mut ugli::Framebuffer) {
+    let camera = &geng::PixelPerfectCamera;
+    let screen = Aabb2::ZERO.extend_positive(framebuffer.size().map(|x| x as f32));
+    let line_height = screen.height() * 0.03;
+    let font = context.geng.default_font();
+    for (i, line) in lines.iter().enumerate() {
+        let band = Aabb2::point(screen.bottom_left())
+            .extend_right(screen.width())
+            .extend_up(line_height)
+            .translate(vec2(0.0, line_height * (lines.len() - 1 - i) as f32));
+        context.geng.draw2d().draw2d(
+            framebuffer,
+            camera,
+            &draw2d::Quad::new(band, Rgba::new(0.0, 0.0, 0.0, 0.7)),
+        );
+        let text = band.extend_symmetric(-band.size() * vec2(0.01, 0.1));
+        context.geng.draw2d().draw2d(
+            framebuffer,
+            camera,
+            &draw2d::Text::unit(&**font, line, Rgba::WHITE).fit_into(text),
+        );
+    }
+}
@@ -0,0 +1,582 @@
+use super::*;
+
+use crate::{
+    assets::{self, Platform, SolverItem, SolverItemKind, SolverLevel},
+    game::GameSolver,
+    model::{Collider, FCoord, LEVEL_SIZE, Shape},
+};
+
+use geng_utils::conversions::*;
+
+const SCREEN_SIZE: vec2<usize> = vec2(1920, 1080);
+/// Step of the grid that dragged objects snap to.
+const GRID_STEP: f32 = 0.25;
+const SPAWNPOINT_RADIUS: f32 = 0.3;
+/// Item kinds in the order `Tab` cycles through them.
+const ITEM_KINDS: [SolverItemKind; 7] = [
+    SolverItemKind::Fish,
+    SolverItemKind::CinderBlock,
+    SolverItemKind::Grandpa,
+    SolverItemKind::Grandson,
+    SolverItemKind::Trashcan,
+    SolverItemKind::Recycle,
+    SolverItemKind::BubbleCode,
+];
+
+/// Part of the level that can be selected and dragged around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Selection {
+    Spawnpoint,
+    Transition,
+    Platform(usize),
+    Item(usize),
+}
+
+/// Edits the solver's levels on the grid and saves them back to their files.
+pub struct SolverEditor {
+    context: Context,
+    final_texture: ugli::Texture,
+    screen: Aabb2<f32>,
+    camera: Camera2d,
+    /// Position of the cursor in the level.
+    cursor: vec2<f32>,
+
+    dir: std::path::PathBuf,
+    /// File names of the levels in the order they are played.
+    files: Vec<String>,
+    level_index: usize,
+    level: SolverLevel,
+    unsaved: bool,
+
+    selected: Option<Selection>,
+    /// Offset from the cursor to the position of the dragged selection.
+    drag: Option<vec2<f32>>,
+    /// Kind of the next placed item.
+    item_kind: SolverItemKind,
+
+    announcement: Option<Announcement>,
+    transition: Option<geng::state::Transition>,
+}
+
+impl SolverEditor {
+    pub fn new(context: &Context) -> anyhow::Result<Self> {
+        let dir = match &context.pack {
+            Some(pack) => pack.levels_dir(),
+            None => assets::default_levels_dir(),
+        };
+        let files = assets::level_names(&dir)?;
+        let Some(name) = files.first() else {
+            anyhow::bail!("no levels listed in {dir:?}");
+        };
+        let level = assets::load_level(&dir, name)?;
+        Ok(Self {
+            context: context.clone(),
+            final_texture: geng_utils::texture::new_texture(context.geng.ugli(), SCREEN_SIZE),
+            screen: Aabb2::ZERO.extend_positive(vec2(1.0, 1.0)),
+            camera: Camera2d {
+                center: LEVEL_SIZE / 2.0,
+                rotation: Angle::ZERO,
+                fov: Camera2dFov::Cover {
+                    width: LEVEL_SIZE.x,
+                    height: LEVEL_SIZE.y,
+                    scale: 1.0,
+                },
+            },
+            cursor: vec2::ZERO,
+
+            dir,
+            files,
+            level_index: 0,
+            level,
+            unsaved: false,
+
+            selected: None,
+            drag: None,
+            item_kind: ITEM_KINDS[0],
+
+            announcement: None,
+            transition: None,
+        })
+    }
+
+    fn announce(&mut self, text: impl Into<String>) {
+        self.announcement = Some(Announcement::new(text));
+    }
+
+    fn can_discard(&mut self) -> bool {
+        can_discard(&self.context.geng, self.unsaved, &mut self.announcement)
+    }
+
+    fn open_level(&mut self, index: usize) {
+        let Some(name) = self.files.get(index).cloned() else {
+            return;
+        };
+        if !self.can_discard() {
+            return;
+        }
+        match assets::load_level(&self.dir, &name) {
+            Ok(level) => {
+                self.level = level;
+                self.level_index = index;
+                self.unsaved = false;
+                self.selected = None;
+                self.drag = None;
+            }
+            Err(error) => {
+                log::error!("Failed to load the level {name}: {error:?}");
+                self.announce(format!("Не удалось открыть уровень {name}"));
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        let name = &self.files[self.level_index];
+        match save_ron(&self.dir.join(name), &self.level, default()) {
+            Ok(()) => {
+                log::info!("Saved the level {name}");
+                let text = format!("Уровень {name} сохранён");
+                self.unsaved = false;
+                self.announce(text);
+            }
+            Err(error) => {
+                log::error!("Failed to save the level {name}: {error:?}");
+                let text = format!("Не удалось сохранить уровень {name}");
+                self.announce(text);
+            }
+        }
+    }
+
+    fn test_play(&mut self) {
+        let game = GameSolver::test_level(&self.context, self.level_index, self.level.clone());
+        self.transition = Some(geng::state::Transition::Push(Box::new(game)));
+    }
+
+    fn edit_dispatcher(&mut self) {
+        match DispatcherEditor::new(&self.context) {
+            Ok(editor) => {
+                self.transition = Some(geng::state::Transition::Push(Box::new(editor)));
+            }
+            Err(error) => {
+                log::error!("Failed to open the dispatcher layout: {error:?}");
+                self.announce("Не удалось открыть комнату диспетчера");
+            }
+        }
+    }
+
+    fn platform_aspect(&self) -> FCoord {
+        self.context
+            .assets
+            .get()
+            .solver
+            .sprites
+            .platform
+            .size()
+            .as_f32()
+            .aspect()
+            .as_r32()
+    }
+
+    /// Part of the level under the cursor, the topmost one first.
+    fn hovered(&self) -> Option<Selection> {
+        let cursor = self.cursor.as_r32();
+        if (self.level.spawnpoint - cursor).len().as_f32() < SPAWNPOINT_RADIUS {
+            return Some(Selection::Spawnpoint);
+        }
+        let items = &self.level.items;
+        if let Some(i) = items
+            .iter()
+            .rposition(|item| item.collider.contains(cursor))
+        {
+            return Some(Selection::Item(i));
+        }
+        let aspect = self.platform_aspect();
+        if let Some(i) = self.level.platforms.iter().rposition(|platform| {
+            // Platforms are thin, so leave some room to grab them
+            platform
+                .aabb(aspect)
+                .extend_uniform(r32(0.1))
+                .contains(cursor)
+        }) {
+            return Some(Selection::Platform(i));
+        }
+        self.level
+            .transition
+            .contains(cursor)
+            .then_some(Selection::Transition)
+    }
+
+    /// Position that is moved when the selection is dragged.
+    fn anchor(&self, selection: Selection) -> vec2<FCoord> {
+        match selection {
+            Selection::Spawnpoint => self.level.spawnpoint,
+            Selection::Transition => self.level.transition.min,
+            Selection::Platform(i) => self.level.platforms[i].pos,
+            Selection::Item(i) => self.level.items[i].collider.position,
+        }
+    }
+
+    fn move_selection(&mut self, selection: Selection, pos: vec2<FCoord>) {
+        let level = &mut self.level;
+        match selection {
+            Selection::Spawnpoint => level.spawnpoint = pos,
+            Selection::Transition => {
+                level.transition = Aabb2::point(pos).extend_positive(level.transition.size());
+            }
+            Selection::Platform(i) => level.platforms[i].pos = pos,
+            Selection::Item(i) => level.items[i].collider.position = pos,
+        }
+        self.unsaved = true;
+    }
+
+    fn selection_aabb(&self, selection: Selection) -> Aabb2<f32> {
+        match selection {
+            Selection::Spawnpoint => {
+                Aabb2::point(self.level.spawnpoint.as_f32()).extend_uniform(SPAWNPOINT_RADIUS)
+            }
+            Selection::Transition => self.level.transition.as_f32(),
+            Selection::Platform(i) => self.level.platforms[i]
+                .aabb(self.platform_aspect())
+                .as_f32(),
+            Selection::Item(i) => self.level.items[i].collider.compute_aabb().as_f32(),
+        }
+    }
+
+    /// Position under the cursor snapped to the grid.
+    fn cursor_target(&self, offset: vec2<f32>) -> vec2<FCoord> {
+        snap_to_grid(&self.context.geng, self.cursor + offset, GRID_STEP).as_r32()
+    }
+
+    fn cursor_press(&mut self) {
+        self.selected = self.hovered();
+        self.drag = self
+            .selected
+            .map(|selection| self.anchor(selection).as_f32() - self.cursor);
+    }
+
+    fn cursor_move(&mut self, position: vec2<f64>) {
+        let pos = (position.as_f32() - self.screen.bottom_left()) / self.screen.size()
+            * SCREEN_SIZE.as_f32();
+        self.cursor = self.camera.screen_to_world(SCREEN_SIZE.as_f32(), pos);
+        if let Some(selection) = self.selected
+            && let Some(offset) = self.drag
+        {
+            let target = self.cursor_target(offset);
+            if target != self.anchor(selection) {
+                self.move_selection(selection, target);
+            }
+        }
+    }
+
+    /// Resize the selection.
+    fn scroll(&mut self, delta: f64) {
+        let grow = delta > 0.0;
+        match self.selected {
+            Some(Selection::Platform(i)) => {
+                let platform = &mut self.level.platforms[i];
+                let step = if grow { r32(0.1) } else { r32(-0.1) };
+                platform.width = (platform.width + step).max(r32(0.1));
+            }
+            Some(Selection::Item(i)) => {
+                let collider = &mut self.level.items[i].collider;
+                let scale = if grow { r32(1.1) } else { r32(1.0 / 1.1) };
+                collider.shape = collider.shape.scaled(scale);
+            }
+            Some(Selection::Spawnpoint | Selection::Transition) | None => return,
+        }
+        self.unsaved = true;
+    }
+
+    fn add_platform(&mut self) {
+        self.level
+            .platforms
+            .push(Platform::new(self.cursor_target(vec2::ZERO), r32(1.0)));
+        self.selected = Some(Selection::Platform(self.level.platforms.len() - 1));
+        self.unsaved = true;
+    }
+
+    fn add_item(&mut self) {
+        self.level.items.push(SolverItem {
+            kind: self.item_kind,
+            pushable: false,
+            can_pickup: false,
+            has_gravity: false,
+            shoots: false,
+            persistent: false,
+            collider: Collider::new(
+                self.cursor_target(vec2::ZERO),
+                Shape::rectangle(vec2(1.0, 1.0).as_r32()),
+            ),
+        });
+        self.selected = Some(Selection::Item(self.level.items.len() - 1));
+        self.unsaved = true;
+    }
+
+    fn next_item_kind(&mut self) {
+        let i = ITEM_KINDS
+            .iter()
+            .position(|&kind| kind == self.item_kind)
+            .unwrap_or(0);
+        self.item_kind = ITEM_KINDS[(i + 1) % ITEM_KINDS.len()];
+    }
+
+    fn delete_selected(&mut self) {
+        match self.selected.take() {
+            Some(Selection::Platform(i)) => {
+                self.level.platforms.remove(i);
+            }
+            Some(Selection::Item(i)) => {
+                self.level.items.remove(i);
+            }
+            Some(Selection::Spawnpoint | Selection::Transition) | None => return,
+        }
+        self.drag = None;
+        self.unsaved = true;
+    }
+
+    fn toggle_item(&mut self, property: impl FnOnce(&mut SolverItem) -> &mut bool) {
+        if let Some(Selection::Item(i)) = self.selected {
+            let value = property(&mut self.level.items[i]);
+            *value = !*value;
+            self.unsaved = true;
+        }
+    }
+
+    fn draw_level(&mut self) {
+        let aspect = self.platform_aspect();
+        let selection = self
+            .selected
+            .map(|selection| self.selection_aabb(selection));
+        let framebuffer = &mut geng_utils::texture::attach_texture(
+            &mut self.final_texture,
+            self.context.geng.ugli(),
+        );
+        let assets = self.context.assets.get();
+        let sprites = &assets.solver.sprites;
+        let geng = &self.context.geng;
+        let camera = &self.camera;
+        let level = &self.level;
+        ugli::clear(framebuffer, Some(assets.palette.background), None, None);
+
+        // Background
+        for decoration in &level.decorations {
+            geng_utils::texture::DrawTexture::new(sprites.level_sprite(decoration.sprite))
+                .fit(decoration.area, vec2(0.5, 0.5))
+                .draw(camera, geng, framebuffer);
+        }
+        if !level.hide_bounds {
+            geng_utils::texture::DrawTexture::new(&sprites.level_bounds)
+                .fit(Aabb2::ZERO.extend_positive(LEVEL_SIZE), vec2(0.5, 0.5))
+                .draw(camera, geng, framebuffer);
+        }
+        if let Some(tiles) = &level.tiles {
+            crate::render::util::draw_tiles(tiles, &sprites.tileset, geng, camera, framebuffer);
+        }
+
+        // Grid
+        let grid_color = Rgba::new(0.5, 0.5, 0.5, 0.3);
+        for x in 0..=LEVEL_SIZE.x as usize {
+            let x = x as f32;
+            let line = Segment(vec2(x, 0.0), vec2(x, LEVEL_SIZE.y));
+            geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Segment::new(line, 0.02, grid_color),
+            );
+        }
+        for y in 0..=LEVEL_SIZE.y as usize {
+            let y = y as f32;
+            let line = Segment(vec2(0.0, y), vec2(LEVEL_SIZE.x, y));
+            geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Segment::new(line, 0.02, grid_color),
+            );
+        }
+
+        // Transition
+        geng.draw2d().draw2d(
+            framebuffer,
+            camera,
+            &draw2d::Quad::new(level.transition.as_f32(), Rgba::new(0.0, 1.0, 0.0, 0.3)),
+        );
+
+        // Slopes and ramps
+        for collider in &level.geometry {
+            let mut outline: Vec<vec2<f32>> = collider
+                .outline()
+                .into_iter()
+                .map(|point| point.as_f32())
+                .collect();
+            if outline.len() > 2 {
+                outline.push(outline[0]);
+            }
+            geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Chain::new(outline, 0.05, Rgba::new(0.5, 0.5, 1.0, 0.8), 0),
+            );
+        }
+
+        // Hazards
+        for hazard in &level.hazards {
+            geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Quad::new(hazard.area.as_f32(), Rgba::new(1.0, 0.0, 0.0, 0.3)),
+            );
+        }
+
+        // Checkpoints
+        for checkpoint in &level.checkpoints {
+            geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Quad::new(checkpoint.area.as_f32(), Rgba::new(1.0, 1.0, 0.0, 0.2)),
+            );
+            geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Ellipse::circle(
+                    checkpoint.spawnpoint.as_f32(),
+                    SPAWNPOINT_RADIUS,
+                    Rgba::new(1.0, 1.0, 0.0, 0.8),
+                ),
+            );
+        }
+
+        // Platforms
+        for platform in &level.platforms {
+            geng_utils::texture::DrawTexture::new(&sprites.platform)
+                .fit_width(platform.aabb(aspect).as_f32(), 1.0)
+                .draw(camera, geng, framebuffer);
+        }
+
+        // Items
+        for item in &level.items {
+            let aabb = item.collider.compute_aabb().as_f32();
+            let mut transform = mat3::identity();
+            if let SolverItemKind::Fish = item.kind {
+                transform *= mat3::scale_uniform(5.0);
+            }
+            geng_utils::texture::DrawTexture::new(sprites.item_texture(item.kind))
+                .fit(aabb, vec2(0.5, 0.5))
+                .transformed(transform)
+                .draw(camera, geng, framebuffer);
+            draw_outline(
+                geng,
+                camera,
+                aabb,
+                0.03,
+                Rgba::new(0.0, 0.5, 1.0, 0.8),
+                framebuffer,
+            );
+        }
+
+        // Spawnpoint
+        geng.draw2d().draw2d(
+            framebuffer,
+            camera,
+            &draw2d::Ellipse::circle(
+                level.spawnpoint.as_f32(),
+                SPAWNPOINT_RADIUS,
+                Rgba::new(1.0, 0.5, 0.0, 0.8),
+            ),
+        );
+
+        // Selection
+        if let Some(aabb) = selection {
+            draw_outline(geng, camera, aabb, 0.05, Rgba::RED, framebuffer);
+        }
+    }
+
+    /// Describe the level and the selection, then list the controls.
+    fn help_lines(&self) -> Vec<String> {
+        let name = &self.files[self.level_index];
+        let unsaved = if self.unsaved { " *" } else { "" };
+        let mut lines = vec![format!(
+            "Уровень {name} ({}/{}){unsaved}, новый предмет: {:?}",
+            self.level_index + 1,
+            self.files.len(),
+            self.item_kind,
+        )];
+        if let Some(Selection::Item(i)) = self.selected {
+            let item = &self.level.items[i];
+            let yes_no = |value: bool| if value { "да" } else { "нет" };
+            lines.push(format!(
+                "{:?}: P - толкается: {}, C - можно поднять: {}, G - гравитация: {}, \
+                 K - остаётся после смерти: {}",
+                item.kind,
+                yes_no(item.pushable),
+                yes_no(item.can_pickup),
+                yes_no(item.has_gravity),
+                yes_no(item.persistent),
+            ));
+        }
+        lines.push(
+            "ЛКМ - перетащить, колесо - размер, N - платформа, I - предмет, Tab - тип предмета, \
+             Delete - удалить"
+                .to_owned(),
+        );
+        lines.push(
+            "F5 - играть, Ctrl+S - сохранить, PageUp/PageDown - другой уровень, \
+             F2 - комната диспетчера, Esc - выйти"
+                .to_owned(),
+        );
+        lines
+    }
+}
+
+impl geng::State for SolverEditor {
+    fn handle_event(&mut self, event: geng::Event) {
+        match event {
+            geng::Event::CursorMove { position } => self.cursor_move(position),
+            geng::Event::MousePress {
+                button: geng::MouseButton::Left,
+            } => self.cursor_press(),
+            geng::Event::MouseRelease {
+                button: geng::MouseButton::Left,
+            } => self.drag = None,
+            geng::Event::Wheel { delta } => self.scroll(delta),
+            geng::Event::KeyPress { key } => match key {
+                geng::Key::S if is_ctrl_pressed(&self.context.geng) => self.save(),
+                geng::Key::F5 => self.test_play(),
+                geng::Key::F2 => self.edit_dispatcher(),
+                geng::Key::PageUp if self.level_index > 0 => {
+                    self.open_level(self.level_index - 1);
+                }
+                geng::Key::PageDown => self.open_level(self.level_index + 1),
+                geng::Key::N => self.add_platform(),
+                geng::Key::I => self.add_item(),
+                geng::Key::Tab => self.next_item_kind(),
+                geng::Key::Delete => self.delete_selected(),
+                geng::Key::P => self.toggle_item(|item| &mut item.pushable),
+                geng::Key::C => self.toggle_item(|item| &mut item.can_pickup),
+                geng::Key::G => self.toggle_item(|item| &mut item.has_gravity),
+                geng::Key::K => self.toggle_item(|item| &mut item.persistent),
+                geng::Key::Escape => {
+                    if self.can_discard() {
+                        self.transition = Some(geng::state::Transition::Pop);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::state::Transition> {
+        self.transition.take()
+    }
+
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        ugli::clear(framebuffer, Some(Rgba::BLACK), None, None);
+        self.draw_level();
+        let draw = geng_utils::texture::DrawTexture::new(&self.final_texture)
+            .fit_screen(vec2(0.5, 0.5), framebuffer);
+        self.screen = draw.target;
+        draw.draw(&geng::PixelPerfectCamera, &self.context.geng, framebuffer);
+
+        draw_help(&self.context, &self.help_lines(), framebuffer);
+        draw_announcement(&self.context, &mut self.announcement, framebuffer);
+    }
+}
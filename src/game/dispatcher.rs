@@ -3,7 +3,7 @@ use super::*;
 use crate::{
     assets::*,
     interop::{ClientConnection, ClientMessage, ServerMessage},
-    model::{DispatcherState, FTime, Player, PlayerAnimationState, SolverState},
+    model::{DispatcherAction, FTime, Flag, Flags, Player, PlayerAnimationState, SolverState},
     ui::layout::AreaOps,
 };
 
@@ -33,10 +33,11 @@ pub struct GameDispatcher {
     cursor_position_game: vec2<f32>,
 
     client_state: DispatcherStateClient,
-    state: DispatcherState,
+    flags: Flags,
     solver_state: SolverState,
     solver_player: Option<Player>,
     ui: DispatcherUi,
+    announcement: Option<Announcement>,
 }
 
 struct DispatcherUi {
@@ -66,7 +67,6 @@ pub struct DispatcherStateClient {
     opened_meme: Option<usize>,
     bfb_pressed: Option<FTime>,
     buttons_pressed: HashMap<DispatcherItem, FTime>,
-    bubble_buttons: usize,
     explosion: Option<(vec2<f32>, FTime)>,
     novella: Option<NovellaState>,
 }
@@ -102,9 +102,22 @@ enum Focus {
 
 impl GameDispatcher {
     pub fn new(context: &Context, connection: ClientConnection, test: Option<usize>) -> Self {
-        let assets = context.assets.get();
-        context.music.play_music(&assets.sounds.dispatcher);
+        context
+            .music
+            .play_music(&context.assets.get().sounds.dispatcher);
+        Self::with_connection(context, connection, test)
+    }
+
+    /// Create a view of the dispatcher for spectators, leaving the music to the spectator.
+    pub fn spectate(context: &Context, connection: ClientConnection) -> Self {
+        Self::with_connection(context, connection, None)
+    }
 
+    fn with_connection(
+        context: &Context,
+        connection: ClientConnection,
+        test: Option<usize>,
+    ) -> Self {
         const TURN_BUTTON_SIZE: vec2<f32> = vec2(50.0, 50.0);
         let mut game = Self {
             context: context.clone(),
@@ -144,11 +157,10 @@ impl GameDispatcher {
                 opened_meme: None,
                 bfb_pressed: None,
                 buttons_pressed: HashMap::new(),
-                bubble_buttons: 0,
                 explosion: None,
                 novella: None,
             },
-            state: DispatcherState::new(),
+            flags: Flags::new(),
             solver_state: SolverState::new(),
             solver_player: None,
             ui: DispatcherUi {
@@ -173,12 +185,12 @@ impl GameDispatcher {
                 ))
                 .extend_symmetric(TURN_BUTTON_SIZE / 2.0),
             },
+            announcement: None,
         };
         if let Some(test) = test {
             game.solver_state.current_level = test;
             game.solver_state.levels_completed = test;
-            game.connection
-                .send(ClientMessage::SyncSolverState(game.solver_state.clone()));
+            game.connection.send(ClientMessage::SkipToLevel(test));
         }
         game
     }
@@ -198,7 +210,7 @@ impl GameDispatcher {
                 fov: Camera2dFov::Vertical(SCREEN_SIZE.y as f32),
             };
             let sprites = &assets.dispatcher.sprites.novella;
-            let text = &assets.dispatcher.novella;
+            let text = assets.content(self.context.pack.as_deref()).novella;
 
             let screen = Aabb2::ZERO.extend_positive(SCREEN_SIZE.as_f32());
 
@@ -237,8 +249,8 @@ impl GameDispatcher {
         let sprites = &assets.dispatcher.sprites;
 
         let level = assets
-            .dispatcher
-            .level
+            .content(self.context.pack.as_deref())
+            .room
             .get_side(self.client_state.active_side);
         let mut draw_monitor = false;
         for (item_index, (item, positioning)) in level.items.iter().enumerate() {
@@ -246,7 +258,7 @@ impl GameDispatcher {
 
             macro_rules! button {
                 ($color:literal) => {{
-                    if !self.state.button_station_open {
+                    if !self.flags.get(Flag::BUTTON_STATION_OPEN) {
                         continue;
                     }
                     color = Rgba::try_from($color).unwrap();
@@ -261,7 +273,7 @@ impl GameDispatcher {
             let texture = match item {
                 DispatcherItem::Door => &sprites.door,
                 DispatcherItem::DoorSign => {
-                    if self.state.door_sign_open {
+                    if self.flags.get(Flag::DOOR_SIGN_OPEN) {
                         &sprites.sign_open
                     } else {
                         &sprites.sign_closed
@@ -274,7 +286,7 @@ impl GameDispatcher {
                 DispatcherItem::Book => &sprites.book,
                 DispatcherItem::TheSock => &sprites.the_sock,
                 DispatcherItem::ButtonStation => {
-                    if self.state.button_station_open {
+                    if self.flags.get(Flag::BUTTON_STATION_OPEN) {
                         &sprites.button_station_open
                     } else {
                         &sprites.button_station_closed
@@ -298,11 +310,7 @@ impl GameDispatcher {
                 DispatcherItem::ButtonCyan => button!("#00EEEE"),
                 DispatcherItem::Tea => &sprites.tea,
             };
-            let size = positioning
-                .size
-                .unwrap_or(texture.size().as_f32() * self.texture_scaling);
-            let pos = Aabb2::point(positioning.anchor - size * positioning.alignment)
-                .extend_positive(size);
+            let pos = positioning.aabb(texture.size().as_f32() * self.texture_scaling);
             let mut draw = geng_utils::texture::DrawTexture::new(texture)
                 .colored(color)
                 .fit(pos, vec2(0.5, 0.5));
@@ -357,7 +365,13 @@ impl GameDispatcher {
                     file(vec2(415, 465)),
                 ];
 
-                if self.solver_state.current_level >= 2 {
+                if assets
+                    .content(self.context.pack.as_deref())
+                    .levels
+                    .iter()
+                    .take(self.solver_state.current_level + 1)
+                    .any(|level| level.unlocks_memes)
+                {
                     self.ui.meme_folder = Some(file(vec2(680, 540)).extend_uniform(15.0));
                 }
 
@@ -380,7 +394,7 @@ impl GameDispatcher {
                 && draw.target.contains(self.cursor_position_game)
                 && !(*item == DispatcherItem::Monitor && self.client_state.focus == Focus::Monitor)
                 && !(*item == DispatcherItem::ButtonStation
-                    && self.state.button_station_open
+                    && self.flags.get(Flag::BUTTON_STATION_OPEN)
                     && self
                         .ui
                         .button_station_inside
@@ -392,16 +406,7 @@ impl GameDispatcher {
                 draw.target = draw.target.extend_uniform(10.0);
             }
 
-            if let DispatcherItem::ButtonYellow
-            | DispatcherItem::ButtonGreen
-            | DispatcherItem::ButtonSalad
-            | DispatcherItem::ButtonPink
-            | DispatcherItem::ButtonBlue
-            | DispatcherItem::ButtonWhite
-            | DispatcherItem::ButtonPurple
-            | DispatcherItem::ButtonOrange
-            | DispatcherItem::ButtonCyan = item
-            {
+            if item.is_button() {
                 let mut draw_base = geng_utils::texture::DrawTexture::new(&sprites.button_base);
                 draw_base.target = draw.target;
                 draw_base.draw(&self.camera, &self.context.geng, framebuffer);
@@ -426,7 +431,7 @@ impl GameDispatcher {
         let monitor_focused = self.client_state.focus == Focus::Monitor;
         if draw_monitor {
             // Monitor
-            if self.state.monitor_unlocked {
+            if self.flags.get(Flag::MONITOR_UNLOCKED) {
                 // Workspace
                 geng_utils::texture::DrawTexture::new(&sprites.workspace)
                     .fit(self.ui.monitor_inside, vec2(0.5, 0.5))
@@ -466,7 +471,8 @@ impl GameDispatcher {
                     let window = draw.target;
                     draw.draw(&self.camera, &self.context.geng, framebuffer);
 
-                    if let Some(text) = assets.dispatcher.files.get(file) {
+                    let files = assets.content(self.context.pack.as_deref()).files;
+                    if let Some(text) = files.get(file) {
                         draw_text(
                             &assets.font,
                             text,
@@ -534,7 +540,7 @@ impl GameDispatcher {
         }
 
         // Player
-        if !self.solver_state.popped
+        if !self.flags.get(Flag::POPPED)
             && let Some(player) = &self.solver_player
             && let DispatcherViewSide::Front = self.client_state.active_side
         {
@@ -591,11 +597,11 @@ impl GameDispatcher {
                 .translate(book_pos.top_left());
 
             let font = self.context.geng.default_font();
+            let book_text = assets.content(self.context.pack.as_deref()).book_text;
             self.context.geng.draw2d().draw2d(
                 framebuffer,
                 &self.camera,
-                &draw2d::Text::unit(&**font, &assets.dispatcher.book_text, assets.palette.text)
-                    .fit_into(book_pos),
+                &draw2d::Text::unit(&**font, book_text, assets.palette.text).fit_into(book_pos),
             );
         }
 
@@ -655,8 +661,8 @@ impl GameDispatcher {
         }
 
         let level = assets
-            .dispatcher
-            .level
+            .content(self.context.pack.as_deref())
+            .room
             .get_side(self.client_state.active_side);
         for (item_index, (item, _)) in level.items.iter().enumerate() {
             let Some(&hitbox) = self
@@ -670,9 +676,9 @@ impl GameDispatcher {
                 match item {
                     DispatcherItem::DoorSign => {
                         assets.sounds.click.play();
-                        self.state.door_sign_open = !self.state.door_sign_open;
-                        self.connection
-                            .send(ClientMessage::SyncDispatcherState(self.state.clone()));
+                        self.connection.send(ClientMessage::DispatcherAction(
+                            DispatcherAction::ToggleDoorSign,
+                        ));
                     }
                     DispatcherItem::Monitor => {
                         assets.sounds.click.play();
@@ -681,16 +687,16 @@ impl GameDispatcher {
                         break;
                     }
                     DispatcherItem::ButtonStation
-                        if !self.state.button_station_open
+                        if !self.flags.get(Flag::BUTTON_STATION_OPEN)
                             || !self
                                 .ui
                                 .button_station_inside
                                 .contains(self.cursor_position_game) =>
                     {
                         assets.sounds.click.play();
-                        self.state.button_station_open = !self.state.button_station_open;
-                        self.connection
-                            .send(ClientMessage::SyncDispatcherState(self.state.clone()));
+                        self.connection.send(ClientMessage::DispatcherAction(
+                            DispatcherAction::ToggleButtonStation,
+                        ));
                     }
                     DispatcherItem::Bfb => {
                         assets.sounds.button.play();
@@ -707,7 +713,7 @@ impl GameDispatcher {
                     | DispatcherItem::ButtonPurple
                     | DispatcherItem::ButtonOrange
                     | DispatcherItem::ButtonCyan
-                        if self.state.button_station_open =>
+                        if self.flags.get(Flag::BUTTON_STATION_OPEN) =>
                     {
                         let sound = match item {
                             DispatcherItem::ButtonYellow => &assets.sounds.kick,
@@ -749,7 +755,7 @@ impl GameDispatcher {
 
         let assets = self.context.assets.get();
         if let Focus::Monitor = self.client_state.focus {
-            if self.state.monitor_unlocked {
+            if self.flags.get(Flag::MONITOR_UNLOCKED) {
                 if let Some(file) = self
                     .ui
                     .files
@@ -796,9 +802,8 @@ impl GameDispatcher {
                 self.solver_camera
                     .screen_to_world(SCREEN_SIZE.as_f32(), self.cursor_position_game),
             ) {
-                self.solver_state.popped = true;
                 self.connection
-                    .send(ClientMessage::SyncSolverState(self.solver_state.clone()));
+                    .send(ClientMessage::DispatcherAction(DispatcherAction::PopSolver));
                 let pos = match self
                     .solver_camera
                     .world_to_screen(SCREEN_SIZE.as_f32(), player.collider.position.as_f32())
@@ -829,7 +834,7 @@ impl GameDispatcher {
 
     fn press_digit(&mut self, digit: usize) {
         if self.client_state.focus == Focus::Monitor
-            && !self.state.monitor_unlocked
+            && !self.flags.get(Flag::MONITOR_UNLOCKED)
             && self.client_state.login_code.len() < 3
         {
             self.client_state.login_code.push(digit);
@@ -855,38 +860,50 @@ impl GameDispatcher {
     }
 
     fn press_backspace(&mut self) {
-        if self.client_state.focus == Focus::Monitor && !self.state.monitor_unlocked {
+        if self.client_state.focus == Focus::Monitor && !self.flags.get(Flag::MONITOR_UNLOCKED) {
             self.client_state.login_code.pop();
         }
     }
 
     fn press_enter(&mut self) {
-        if self.client_state.focus == Focus::Monitor && !self.state.monitor_unlocked {
-            if self.client_state.login_code == vec![6, 6, 6] {
-                self.unlock_monitor();
-            } else {
-                // TODO
-            }
+        if self.client_state.focus == Focus::Monitor && !self.flags.get(Flag::MONITOR_UNLOCKED) {
+            self.connection.send(ClientMessage::DispatcherAction(
+                DispatcherAction::EnterLoginCode(self.client_state.login_code.clone()),
+            ));
         }
     }
 
-    fn unlock_monitor(&mut self) {
-        self.state.monitor_unlocked = true;
-        self.connection
-            .send(ClientMessage::SyncDispatcherState(self.state.clone()));
-    }
-
-    fn handle_message(&mut self, message: ServerMessage) {
+    pub(super) fn handle_message(&mut self, message: ServerMessage) {
         match message {
-            ServerMessage::Ping
+            ServerMessage::Hello { .. }
+            | ServerMessage::Ping
             | ServerMessage::RoomJoined(..)
             | ServerMessage::StartGame(..)
+            | ServerMessage::SpectateGame(..)
             | ServerMessage::YourToken(_)
             | ServerMessage::SyncRoomPlayers(_) => {}
-            ServerMessage::Error(error) => log::error!("Server error: {error}"),
-            ServerMessage::SyncDispatcherState(dispatcher_state) => self.state = dispatcher_state,
-            ServerMessage::SyncSolverState(solver_state) => self.solver_state = solver_state,
+            ServerMessage::Error(error) => {
+                log::error!("Server error: {error}");
+                self.announcement = Some(Announcement::new(error.message()));
+            }
+            ServerMessage::Announcement(text) => self.announcement = Some(Announcement::new(text)),
+            ServerMessage::SyncFlags(flags) => self.flags = flags,
+            ServerMessage::SyncSolverState(solver_state) => {
+                if solver_state.current_level != self.solver_state.current_level {
+                    self.solver_player = None;
+                }
+                self.solver_state = solver_state;
+            }
             ServerMessage::SyncSolverPlayer(player) => self.solver_player = Some(player),
+            ServerMessage::SessionResumed(session) => {
+                self.flags = session.flags;
+                self.solver_state = session.solver;
+                if let Some((level, player)) = session.solver_player
+                    && level == self.solver_state.current_level
+                {
+                    self.solver_player = Some(player);
+                }
+            }
             ServerMessage::GameCrash(_) => {
                 // TODO
             }
@@ -906,51 +923,9 @@ impl GameDispatcher {
         for (item, time) in &mut self.client_state.buttons_pressed {
             *time += delta_time;
             if time.as_f32() > 1.0 {
-                if self.solver_state.levels_completed == 3 {
-                    self.client_state.bubble_buttons += 1;
-                    if self.client_state.bubble_buttons == 5 {
-                        self.solver_state.levels_completed += 1;
-                        self.connection
-                            .send(ClientMessage::SyncSolverState(self.solver_state.clone()));
-                    }
-                }
-
-                match item {
-                    DispatcherItem::ButtonSalad => {
-                        if self.state.monitor_unlocked && self.solver_state.levels_completed == 0 {
-                            self.connection.send(ClientMessage::CrashOther(
-                                "твой друг нажал на салатовую кнопку".into(),
-                            ));
-                        }
-                    }
-                    DispatcherItem::ButtonYellow => {
-                        if self.state.monitor_unlocked && self.solver_state.levels_completed == 0 {
-                            self.solver_state.levels_completed += 1;
-                            self.connection
-                                .send(ClientMessage::SyncSolverState(self.solver_state.clone()));
-                        }
-                    }
-                    DispatcherItem::ButtonGreen => {
-                        if self.solver_state.trashcan_evil
-                            && self.solver_state.current_level == 3
-                            && self.solver_state.levels_completed == 2
-                        {
-                            self.solver_state.trashcan_evil = false;
-                            self.connection
-                                .send(ClientMessage::SyncSolverState(self.solver_state.clone()));
-                        }
-                    }
-                    DispatcherItem::ButtonCyan => {
-                        if self.solver_state.current_level == 5
-                            && self.solver_state.levels_completed == 4
-                        {
-                            self.solver_state.levels_completed += 1;
-                            self.connection
-                                .send(ClientMessage::SyncSolverState(self.solver_state.clone()));
-                        }
-                    }
-                    _ => {}
-                }
+                self.connection.send(ClientMessage::DispatcherAction(
+                    DispatcherAction::PressButton(*item),
+                ));
             }
         }
         self.client_state
@@ -964,6 +939,9 @@ impl geng::State for GameDispatcher {
         if let Some(Ok(message)) = self.connection.try_recv() {
             self.handle_message(message);
         }
+        if self.connection.is_reconnecting() {
+            return;
+        }
 
         let delta_time = delta_time as f32;
         self.camera_fov.update(delta_time);
@@ -978,7 +956,7 @@ impl geng::State for GameDispatcher {
         if let Some((_, timer)) = &mut self.client_state.explosion {
             *timer += delta_time;
             if timer.as_f32() > 1.0 {
-                if self.solver_state.popped {
+                if self.flags.get(Flag::POPPED) {
                     panic!("тебе конец, и игре тоже");
                 }
 
@@ -989,7 +967,7 @@ impl geng::State for GameDispatcher {
         if let Some(novella) = &mut self.client_state.novella {
             let assets = self.context.assets.get();
             let sprites = &assets.dispatcher.sprites.novella;
-            let text = &assets.dispatcher.novella;
+            let text = assets.content(self.context.pack.as_deref()).novella;
             if let Some(line) = text.lines().nth(novella.line) {
                 match line {
                     "/спрайт_нейтральный" => {
@@ -1022,6 +1000,10 @@ impl geng::State for GameDispatcher {
     }
 
     fn handle_event(&mut self, event: geng::Event) {
+        if self.connection.is_reconnecting() {
+            return;
+        }
+
         match event {
             geng::Event::CursorMove { position } => {
                 self.cursor_position_raw = position;
@@ -1068,6 +1050,11 @@ impl geng::State for GameDispatcher {
             .fit_screen(vec2(0.5, 0.5), framebuffer);
         self.screen = draw.target;
         draw.draw(&geng::PixelPerfectCamera, &self.context.geng, framebuffer);
+
+        super::draw_announcement(&self.context, &mut self.announcement, framebuffer);
+        if self.connection.is_reconnecting() {
+            super::draw_reconnecting(&self.context, framebuffer);
+        }
     }
 }
 
@@ -1,8 +1,80 @@
 pub mod dispatcher;
+pub mod replay;
 pub mod solver;
+pub mod spectator;
 
-pub use self::{dispatcher::GameDispatcher, solver::GameSolver};
+pub use self::{dispatcher::GameDispatcher, solver::GameSolver, spectator::GameSpectator};
 
 use crate::context::Context;
 
 use geng::prelude::*;
+
+/// Dim the screen and tell the player that the connection is being restored.
+fn draw_reconnecting(context: &Context, framebuffer: &mut ugli::Framebuffer) {
+    let camera = &geng::PixelPerfectCamera;
+    let screen = Aabb2::ZERO.extend_positive(framebuffer.size().map(|x| x as f32));
+    context.geng.draw2d().draw2d(
+        framebuffer,
+        camera,
+        &draw2d::Quad::new(screen, Rgba::new(0.0, 0.0, 0.0, 0.7)),
+    );
+
+    let font = context.geng.default_font();
+    let text = screen.extend_symmetric(-screen.size() * vec2(0.3, 0.45));
+    context.geng.draw2d().draw2d(
+        framebuffer,
+        camera,
+        &draw2d::Text::unit(&**font, "Переподключение...", Rgba::WHITE).fit_into(text),
+    );
+}
+
+/// Message from the server shown on top of the game for a while.
+pub struct Announcement {
+    text: String,
+    timer: Timer,
+}
+
+impl Announcement {
+    /// Time in seconds the announcement stays on the screen.
+    const DURATION: f64 = 5.0;
+
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            timer: Timer::new(),
+        }
+    }
+}
+
+/// Draw the announcement at the top of the screen, clearing it once it has expired.
+pub fn draw_announcement(
+    context: &Context,
+    announcement: &mut Option<Announcement>,
+    framebuffer: &mut ugli::Framebuffer,
+) {
+    if announcement.as_ref().is_some_and(|announcement| {
+        announcement.timer.elapsed().as_secs_f64() > Announcement::DURATION
+    }) {
+        *announcement = None;
+    }
+    let Some(announcement) = announcement else {
+        return;
+    };
+
+    let camera = &geng::PixelPerfectCamera;
+    let screen = Aabb2::ZERO.extend_positive(framebuffer.size().map(|x| x as f32));
+    let band = screen.extend_down(-screen.height() * 0.85);
+    context.geng.draw2d().draw2d(
+        framebuffer,
+        camera,
+        &draw2d::Quad::new(band, Rgba::new(0.0, 0.0, 0.0, 0.7)),
+    );
+
+    let font = context.geng.default_font();
+    let text = band.extend_symmetric(-band.size() * vec2(0.05, 0.25));
+    context.geng.draw2d().draw2d(
+        framebuffer,
+        camera,
+        &draw2d::Text::unit(&**font, &announcement.text, Rgba::WHITE).fit_into(text),
+    );
+}
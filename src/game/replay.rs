@@ -0,0 +1,166 @@
+use crate::{
+    assets::SolverRules,
+    interop::{PROTOCOL_VERSION, ServerMessage},
+    model::{PlayerControl, SolverState},
+};
+
+use geng::prelude::*;
+
+use std::io::{BufRead, Write};
+
+/// Everything needed to set up the simulation of a recorded session.
+/// Written as the first line of the recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    /// Recordings contain server messages, so they only play back with the same protocol.
+    pub protocol_version: u32,
+    pub seed: u64,
+    pub rules: SolverRules,
+    pub state: SolverState,
+}
+
+/// Something that affected the simulation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordEntry {
+    /// Input used from this step on, recorded only when it changes.
+    Input(PlayerControl),
+    Message(ServerMessage),
+    ReloadLevel,
+    /// The session was still running, so a recording cut off by a crash plays back up to here.
+    Heartbeat,
+    /// The session has ended.
+    End,
+}
+
+/// Entry applied right before the step following `tick`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordLine {
+    tick: u64,
+    entry: RecordEntry,
+}
+
+/// Steps between the heartbeats, the file is flushed with each of them.
+const HEARTBEAT_TICKS: u64 = 60;
+
+/// Writes the inputs and messages of a solver session to a file, one JSON object per line.
+pub struct Recorder {
+    file: Option<std::io::BufWriter<std::fs::File>>,
+    last_control: Option<PlayerControl>,
+    last_heartbeat: u64,
+}
+
+impl Recorder {
+    pub fn create(path: &std::path::Path, header: &RecordingHeader) -> anyhow::Result<Self> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(&mut file, header)?;
+        writeln!(file)?;
+        log::info!("Recording the session to {path:?}");
+        Ok(Self {
+            file: Some(file),
+            last_control: None,
+            last_heartbeat: 0,
+        })
+    }
+
+    pub fn record(&mut self, tick: u64, entry: RecordEntry) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let line = RecordLine { tick, entry };
+        let result = serde_json::to_writer(&mut *file, &line)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(writeln!(file)?));
+        if let Err(error) = result {
+            log::error!("Failed to write the recording, stopping it: {error}");
+            self.file = None;
+        }
+    }
+
+    /// Record the input of the step following `tick`, with a heartbeat every few steps.
+    pub fn record_step(&mut self, tick: u64, control: &PlayerControl) {
+        if self.last_control.as_ref() != Some(control) {
+            self.last_control = Some(control.clone());
+            self.record(tick, RecordEntry::Input(control.clone()));
+        }
+        if tick >= self.last_heartbeat + HEARTBEAT_TICKS {
+            self.last_heartbeat = tick;
+            self.record(tick, RecordEntry::Heartbeat);
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(file) = &mut self.file
+            && let Err(error) = file.flush()
+        {
+            log::error!("Failed to write the recording, stopping it: {error}");
+            self.file = None;
+        }
+    }
+}
+
+/// Recorded session being played back.
+pub struct Replay {
+    pub header: RecordingHeader,
+    entries: std::collections::VecDeque<RecordLine>,
+    /// Tick of the last entry, a recording without the end is played up to the step following it.
+    last_tick: u64,
+    finished: bool,
+}
+
+impl Replay {
+    pub fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut lines = file.lines();
+        let Some(header) = lines.next() else {
+            anyhow::bail!("the recording is empty");
+        };
+        let header: RecordingHeader = serde_json::from_str(&header?)?;
+        if header.protocol_version != PROTOCOL_VERSION {
+            anyhow::bail!(
+                "recorded with protocol version {}, but the game uses version {PROTOCOL_VERSION}",
+                header.protocol_version
+            );
+        }
+        let lines: Vec<String> = lines.collect::<std::io::Result<_>>()?;
+        let mut entries = std::collections::VecDeque::new();
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<RecordLine>(line) {
+                Ok(line) => entries.push_back(line),
+                // A crash can cut the last line short
+                Err(error) if i + 1 == lines.len() => {
+                    log::warn!("Skipping the incomplete last line of the recording: {error}");
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(Self {
+            header,
+            last_tick: entries.back().map_or(0, |line| line.tick),
+            entries,
+            finished: false,
+        })
+    }
+
+    /// Take the entries to apply before the step following `tick`.
+    pub fn take_due(&mut self, tick: u64) -> Vec<RecordEntry> {
+        let mut due = Vec::new();
+        while let Some(line) = self.entries.front()
+            && line.tick <= tick
+        {
+            let entry = self.entries.pop_front().unwrap().entry;
+            if let RecordEntry::End = entry {
+                self.finished = true;
+            }
+            due.push(entry);
+        }
+        if self.entries.is_empty() && tick > self.last_tick {
+            self.finished = true;
+        }
+        due
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
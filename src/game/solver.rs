@@ -1,8 +1,8 @@
-use super::*;
+use super::{replay::*, *};
 
 use crate::{
-    assets::{SolverItem, SolverItemKind},
-    interop::{ClientConnection, ClientMessage, ServerMessage},
+    assets::{LevelMusic, SolverItemKind, SolverLevel},
+    interop::{ClientConnection, ClientMessage, PLAYER_SYNC_RATE, ServerMessage},
     model::*,
     ui::layout::AreaOps,
 };
@@ -10,121 +10,155 @@ use crate::{
 use geng_utils::conversions::*;
 
 const SCREEN_SIZE: vec2<usize> = vec2(1920, 1080);
-const LEVEL_SIZE: vec2<f32> = vec2(16.0, 9.0);
 
 pub struct GameSolver {
     context: Context,
     connection: ClientConnection,
     test: bool,
+    /// Only mirror the synced player without simulating or sending anything.
+    spectator: bool,
+    /// Music of the current level, left to the spectator view to play when spectating.
+    pub music: LevelMusic,
+    /// Time since the player was last streamed to the server.
+    player_sync: Timer,
+    /// Position of the spectated player when the last sync arrived and the time since,
+    /// to smooth out its movement.
+    spectated_from: vec2<FCoord>,
+    spectated_since: FTime,
 
     final_texture: ugli::Texture,
     framebuffer_size: vec2<usize>,
     screen: Aabb2<f32>,
 
-    client_state: SolverStateClient,
-    state: SolverState,
-    dispatcher_state: DispatcherState,
+    world: SolverWorld,
     camera: Camera2d,
 
     player_control: PlayerControl,
-}
-
-struct SolverStateClient {
-    time: FTime,
-    player: Player,
-    level_static_colliders: Vec<Collider>,
-    door_entrance: Collider,
-    door_exit: Collider,
-    platforms: Vec<Collider>,
-    bubble_balls: Vec<(Collider, usize)>,
-    items: Vec<SolverItem>,
-    picked_up_item: Option<SolverItem>,
-    explosion: Option<(vec2<FCoord>, FTime)>,
-    grandson_spin: Option<Angle<FCoord>>,
-    grandpa_drill: Option<FTime>,
     bubble_code: Vec<usize>,
-    interact_item: Option<usize>,
-    projectiles: Vec<Projectile>,
-    fish_cooldown: FTime,
+    announcement: Option<Announcement>,
+
+    recorder: Option<Recorder>,
+    /// Recorded session driving the simulation instead of the player and the server.
+    replay: Option<Replay>,
+    /// Level from the editor played instead of the level files.
+    test_level: Option<SolverLevel>,
+    /// Return to the previous state.
+    leave: bool,
 }
 
-struct Projectile {
-    pub collider: Collider,
-    pub velocity: vec2<FCoord>,
-}
+impl GameSolver {
+    pub fn new(context: &Context, connection: ClientConnection, test: Option<usize>) -> Self {
+        let mut state = SolverState::new();
+        if let Some(test) = test {
+            state.current_level = test;
+            state.levels_completed = test;
+            connection.send(ClientMessage::SkipToLevel(test));
+        }
+        let seed = thread_rng().r#gen();
+        let mut game = Self::with_world(context, connection, seed, state.clone(), false);
+        game.test = test.is_some();
+        game.reload_level();
 
-struct PlayerControl {
-    pub jump: bool,
-    pub hold_jump: bool,
-    pub move_dir: vec2<FCoord>,
-    pub pickup: bool,
-}
+        if let Some(path) = &context.record {
+            let header = RecordingHeader {
+                protocol_version: crate::interop::PROTOCOL_VERSION,
+                seed,
+                rules: game.world.rules.clone(),
+                state,
+            };
+            match Recorder::create(path, &header) {
+                Ok(recorder) => game.recorder = Some(recorder),
+                Err(error) => log::error!("Failed to start recording to {path:?}: {error}"),
+            }
+        }
+        game
+    }
 
-impl PlayerControl {
-    pub fn take(&mut self) -> Self {
-        std::mem::take(self)
+    /// Create a read-only view of the solver for spectators.
+    pub fn spectate(context: &Context, connection: ClientConnection) -> Self {
+        let mut game = Self::with_world(
+            context,
+            connection,
+            thread_rng().r#gen(),
+            SolverState::new(),
+            true,
+        );
+        game.reload_level();
+        game
     }
-}
 
-impl Default for PlayerControl {
-    fn default() -> Self {
-        Self {
-            jump: false,
-            hold_jump: false,
-            move_dir: vec2::ZERO,
-            pickup: false,
-        }
+    /// Play back a recorded session without a server.
+    pub fn replay(context: &Context, replay: Replay) -> Self {
+        let header = &replay.header;
+        let mut game = Self::with_world(
+            context,
+            ClientConnection::offline(),
+            header.seed,
+            header.state.clone(),
+            false,
+        );
+        game.world.rules = header.rules.clone();
+        game.replay = Some(replay);
+        game.reload_level();
+        game
     }
-}
 
-impl GameSolver {
-    pub fn new(context: &Context, connection: ClientConnection, test: Option<usize>) -> Self {
+    /// Play a level from the editor without a server.
+    /// Only the triggers handled by the client run.
+    pub fn test_level(context: &Context, index: usize, level: SolverLevel) -> Self {
+        let mut state = SolverState::new();
+        state.current_level = index;
+        state.levels_completed = index;
+        let mut game = Self::with_world(
+            context,
+            ClientConnection::offline(),
+            thread_rng().r#gen(),
+            state,
+            false,
+        );
+        game.test_level = Some(level);
+        game.reload_level();
+        game
+    }
+
+    /// Set up the game without loading the level, so that it is loaded once the game is configured.
+    fn with_world(
+        context: &Context,
+        connection: ClientConnection,
+        seed: u64,
+        state: SolverState,
+        spectator: bool,
+    ) -> Self {
         let assets = context.assets.get();
-        context.music.play_music(&assets.sounds.dispatcher);
+        if !spectator {
+            context.music.play_music(&assets.sounds.dispatcher);
+        }
+
+        let mut world = SolverWorld::new(assets.solver.rules.clone(), state).with_seed(seed);
+        world.platform_aspect = assets
+            .solver
+            .sprites
+            .platform
+            .size()
+            .as_f32()
+            .aspect()
+            .as_r32();
 
         let mut game = Self {
             context: context.clone(),
             connection,
-            test: test.is_some(),
+            test: false,
+            spectator,
+            music: LevelMusic::Dispatcher,
+            player_sync: Timer::new(),
+            spectated_from: vec2::ZERO,
+            spectated_since: FTime::ZERO,
 
             final_texture: geng_utils::texture::new_texture(context.geng.ugli(), SCREEN_SIZE),
             framebuffer_size: vec2(1, 1),
             screen: Aabb2::ZERO.extend_positive(vec2(1.0, 1.0)),
 
-            client_state: SolverStateClient {
-                time: FTime::ZERO,
-                player: Player {
-                    collider: Collider::aabb(
-                        Aabb2::point(vec2(0.0, 0.0))
-                            .extend_positive(vec2(1.0, 1.5))
-                            .as_r32(),
-                    ),
-                    velocity: vec2::ZERO,
-                    state: PlayerState::Airborn,
-                    control_timeout: None,
-                    facing_left: false,
-                    can_hold_jump: false,
-                    coyote_time: None,
-                    jump_buffer: None,
-                    animation_time: FTime::ZERO,
-                },
-                level_static_colliders: Vec::new(),
-                door_entrance: Collider::aabb(Aabb2::ZERO),
-                door_exit: Collider::aabb(Aabb2::ZERO),
-                platforms: Vec::new(),
-                bubble_balls: Vec::new(),
-                items: Vec::new(),
-                picked_up_item: None,
-                explosion: None,
-                grandson_spin: None,
-                grandpa_drill: None,
-                bubble_code: Vec::new(),
-                interact_item: None,
-                projectiles: Vec::new(),
-                fish_cooldown: FTime::new(1.0),
-            },
-            state: SolverState::new(),
-            dispatcher_state: DispatcherState::new(),
+            world,
             camera: Camera2d {
                 center: LEVEL_SIZE / 2.0,
                 rotation: Angle::ZERO,
@@ -136,47 +170,49 @@ impl GameSolver {
             },
 
             player_control: PlayerControl::default(),
-        };
-
-        if let Some(test) = test {
-            game.state.current_level = test;
-            game.state.levels_completed = test;
-            game.connection
-                .send(ClientMessage::SyncSolverState(game.state.clone()));
-        }
+            bubble_code: Vec::new(),
+            announcement: None,
 
-        game.reload_level();
+            recorder: None,
+            replay: None,
+            test_level: None,
+            leave: false,
+        };
+        drop(assets);
         game
     }
 
     fn reload_level(&mut self) {
-        if self.state.current_level == 4 {
+        let level = self.test_level.clone().or_else(|| {
             self.context
-                .music
-                .play_music(&self.context.assets.get().sounds.boss);
-        } else if self.state.current_level > 4 {
-            self.context
-                .music
-                .play_music(&self.context.assets.get().sounds.dispatcher);
-        }
-
-        self.client_state.level_static_colliders.clear();
-        self.client_state.platforms.clear();
-        self.client_state.bubble_balls.clear();
-        self.client_state.projectiles.clear();
+                .assets
+                .get()
+                .content(self.context.pack.as_deref())
+                .levels
+                .get(self.world.state.current_level)
+                .cloned()
+        });
+        self.world.load_level(level);
+        self.spectated_from = self.world.player.collider.position;
+    }
 
-        self.player_respawn();
-        self.update_level_colliders();
-        self.reset_items();
+    /// Mirror the synced player instead of simulating it.
+    fn update_spectated(&mut self, delta_time: FTime) {
+        self.world.time += delta_time;
+        self.world.player.animation_time += delta_time;
+        self.spectated_since += delta_time;
+        for event in self.world.take_events() {
+            self.handle_world_event(event);
+        }
     }
 
-    fn reset_items(&mut self) {
-        let assets = self.context.assets.get();
-        let Some(level) = assets.solver.levels.get(self.state.current_level) else {
-            return;
-        };
-        self.client_state.items = level.items.clone();
-        self.client_state.picked_up_item = None;
+    /// Spectated player moving from the previous synced position to the last one.
+    fn spectated_player(&self) -> Collider {
+        let t = (self.spectated_since.as_f32() * PLAYER_SYNC_RATE as f32).min(1.0);
+        let mut collider = self.world.player.collider.clone();
+        collider.position =
+            self.spectated_from + (collider.position - self.spectated_from) * r32(t);
+        collider
     }
 
     fn draw_game(&mut self) {
@@ -186,97 +222,142 @@ impl GameSolver {
         );
         let assets = self.context.assets.get();
         ugli::clear(framebuffer, Some(assets.palette.background), None, None);
-        let Some(level) = assets.solver.levels.get(self.state.current_level) else {
+        let Some(level) = &self.world.level else {
             return;
         };
 
         // Background
-        if self.state.current_level == 0 {
-            geng_utils::texture::DrawTexture::new(&assets.solver.sprites.level1)
-                .fit(Aabb2::ZERO.extend_positive(LEVEL_SIZE), vec2(0.5, 0.5))
-                .draw(&self.camera, &self.context.geng, framebuffer);
-        } else if self.state.current_level == 2 {
-            let texture = &assets.solver.sprites.green_hint;
-            geng_utils::texture::DrawTexture::new(texture)
-                .fit(
-                    Aabb2::point(LEVEL_SIZE * vec2(0.7, 0.5))
-                        .extend_symmetric(vec2(3.0, 3.0) / 2.0),
-                    vec2(0.5, 0.5),
-                )
-                .draw(&self.camera, &self.context.geng, framebuffer);
-        } else if self.state.current_level == 3 {
-            let texture = &assets.solver.sprites.bubble_tea;
-            geng_utils::texture::DrawTexture::new(texture)
-                .fit(Aabb2::ZERO.extend_positive(LEVEL_SIZE), vec2(0.5, 0.5))
-                .draw(&self.camera, &self.context.geng, framebuffer);
-        } else if self.state.current_level == 5 {
-            let texture = &assets.solver.sprites.dispatcher;
-            geng_utils::texture::DrawTexture::new(texture)
-                .fit(Aabb2::ZERO.extend_positive(LEVEL_SIZE), vec2(0.5, 0.5))
-                .draw(&self.camera, &self.context.geng, framebuffer);
+        for decoration in &level.decorations {
+            geng_utils::texture::DrawTexture::new(
+                assets.solver.sprites.level_sprite(decoration.sprite),
+            )
+            .fit(decoration.area, vec2(0.5, 0.5))
+            .draw(&self.camera, &self.context.geng, framebuffer);
         }
 
         // Bounds
-        if self.state.current_level != 5 {
+        if !level.hide_bounds {
             geng_utils::texture::DrawTexture::new(&assets.solver.sprites.level_bounds)
                 .fit(Aabb2::ZERO.extend_positive(LEVEL_SIZE), vec2(0.5, 0.5))
                 .draw(&self.camera, &self.context.geng, framebuffer);
         }
 
+        // Tiles
+        if let Some(tiles) = &level.tiles {
+            crate::render::util::draw_tiles(
+                tiles,
+                &assets.solver.sprites.tileset,
+                &self.context.geng,
+                &self.camera,
+                framebuffer,
+            );
+        }
+
         // Doors
         if level.door_entrance {
             geng_utils::texture::DrawTexture::new(&assets.solver.sprites.door_closed)
                 .transformed(mat3::scale(vec2(-1.0, 1.0)))
-                .fit_height(self.client_state.door_entrance.compute_aabb().as_f32(), 0.0)
+                .fit_height(self.world.door_entrance.compute_aabb().as_f32(), 0.0)
                 .draw(&self.camera, &self.context.geng, framebuffer);
         }
         if level.door_exit {
-            geng_utils::texture::DrawTexture::new(if self.state.is_exit_open() {
+            geng_utils::texture::DrawTexture::new(if self.world.state.is_exit_open() {
                 &assets.solver.sprites.door_open
             } else {
                 &assets.solver.sprites.door_closed
             })
-            .fit_height(self.client_state.door_exit.compute_aabb().as_f32(), 1.0)
+            .fit_height(self.world.door_exit.compute_aabb().as_f32(), 1.0)
             .draw(&self.camera, &self.context.geng, framebuffer);
         }
 
-        if self.state.current_level == 3 {
-            // Bubble door
-            if !self.state.solved_bubble_code
-                && let Some(door) = self.client_state.level_static_colliders.last()
-            {
-                geng_utils::texture::DrawTexture::new(&assets.solver.sprites.bubble_door)
-                    .fit_height(door.compute_aabb().as_f32(), 0.5)
+        // Walls
+        for wall in &self.world.walls {
+            if let Some(sprite) = wall.sprite {
+                geng_utils::texture::DrawTexture::new(assets.solver.sprites.level_sprite(sprite))
+                    .fit_height(wall.collider.compute_aabb().as_f32(), 0.5)
                     .draw(&self.camera, &self.context.geng, framebuffer);
             }
         }
 
         // Platforms
-        for platform in &self.client_state.platforms {
+        for platform in &self.world.platforms {
+            // Crumbling platforms shake
+            let shake = platform
+                .crumbling
+                .map_or(0.0, |time| (time.as_f32() * 60.0).sin() * 0.03);
             geng_utils::texture::DrawTexture::new(&assets.solver.sprites.platform)
-                .fit_width(platform.compute_aabb().as_f32(), 1.0)
+                .fit_width(platform.collider.compute_aabb().as_f32(), 1.0)
+                .transformed(mat3::translate(vec2(shake, 0.0)))
                 .draw(&self.camera, &self.context.geng, framebuffer);
         }
 
+        // Slopes and ramps
+        for collider in &level.geometry {
+            let outline: Vec<vec2<f32>> = collider
+                .outline()
+                .into_iter()
+                .map(|point| point.as_f32())
+                .collect();
+            if let Shape::Segment { .. } = collider.shape {
+                self.context.geng.draw2d().draw2d(
+                    framebuffer,
+                    &self.camera,
+                    &draw2d::Segment::new(
+                        Segment(outline[0], outline[1]),
+                        0.05,
+                        assets.palette.text,
+                    ),
+                );
+            } else {
+                self.context.geng.draw2d().draw2d(
+                    framebuffer,
+                    &self.camera,
+                    &draw2d::Polygon::new(outline, assets.palette.text),
+                );
+            }
+        }
+
+        // Spikes
+        for hazard in level.hazards.iter().filter(|hazard| hazard.spikes) {
+            let area = hazard.area.as_f32();
+            let count = (area.width() / area.height()).round().max(1.0) as usize;
+            let width = area.width() / count as f32;
+            for i in 0..count {
+                let left = area.min.x + width * i as f32;
+                self.context.geng.draw2d().draw2d(
+                    framebuffer,
+                    &self.camera,
+                    &draw2d::Polygon::new(
+                        vec![
+                            vec2(left, area.min.y),
+                            vec2(left + width, area.min.y),
+                            vec2(left + width / 2.0, area.max.y),
+                        ],
+                        assets.palette.text,
+                    ),
+                );
+            }
+        }
+
         // Items
-        for item in &self.client_state.items {
+        for item in &self.world.items {
             let texture = if let SolverItemKind::Recycle = item.kind
-                && self.state.trashcan_evil
+                && self.world.flags.get(Flag::TRASHCAN_EVIL)
             {
                 continue;
             } else if let SolverItemKind::Trashcan = item.kind
-                && self.state.trashcan_evil
+                && self.world.flags.get(Flag::TRASHCAN_EVIL)
             {
                 &assets.solver.sprites.trashcan_evil
             } else if let SolverItemKind::Fish = item.kind {
                 let frames = &assets.solver.sprites.fish;
-                let frame = ((self.client_state.time.as_f32() / 1.5).fract() * frames.len() as f32)
-                    .floor() as usize;
+                let frame = ((self.world.time.as_f32() / 1.5).fract() * frames.len() as f32).floor()
+                    as usize;
                 frames.get(frame).unwrap_or(&frames[0])
             } else if let SolverItemKind::CinderBlock = item.kind {
                 let frames = &assets.solver.sprites.excalibur;
-                let frame = ((self.client_state.time.as_f32() / 0.7).fract() * frames.len() as f32)
-                    .floor() as usize;
+                let frame = ((self.world.time.as_f32() / 0.7).fract() * frames.len() as f32).floor()
+                    as usize;
                 frames.get(frame).unwrap_or(&frames[0])
             } else {
                 assets.solver.sprites.item_texture(item.kind)
@@ -287,12 +368,12 @@ impl GameSolver {
                 transform *= mat3::scale_uniform(5.0);
             }
             if let SolverItemKind::Grandson = item.kind
-                && let Some(spin) = self.client_state.grandson_spin
+                && let Some(spin) = self.world.grandson_spin
             {
                 transform *= mat3::rotate(spin.as_f32());
             }
             if let SolverItemKind::Grandpa = item.kind
-                && let Some(time) = self.client_state.grandpa_drill
+                && let Some(time) = self.world.grandpa_drill
             {
                 let spin =
                     Angle::from_degrees(180.0 * crate::util::smoothstep(time.as_f32().min(1.0)));
@@ -310,11 +391,7 @@ impl GameSolver {
                 let code = target;
                 let code = code.extend_symmetric(-code.size() * vec2(0.1, 0.15));
                 let font = self.context.geng.default_font();
-                for (pos, &digit) in code
-                    .split_columns(4)
-                    .into_iter()
-                    .zip(&self.client_state.bubble_code)
-                {
+                for (pos, &digit) in code.split_columns(4).into_iter().zip(&self.bubble_code) {
                     self.context.geng.draw2d().draw2d(
                         framebuffer,
                         &self.camera,
@@ -326,7 +403,7 @@ impl GameSolver {
         }
 
         // Balls
-        for (ball, texture_i) in &self.client_state.bubble_balls {
+        for (ball, texture_i) in &self.world.bubble_balls {
             let texture = assets
                 .solver
                 .sprites
@@ -339,7 +416,7 @@ impl GameSolver {
         }
 
         // Projectile
-        for projectile in &self.client_state.projectiles {
+        for projectile in &self.world.projectiles {
             let texture = &assets.solver.sprites.projectile;
             geng_utils::texture::DrawTexture::new(texture)
                 .fit(projectile.collider.compute_aabb().as_f32(), vec2(0.5, 0.5))
@@ -347,8 +424,13 @@ impl GameSolver {
         }
 
         // Player
-        if !self.state.popped {
-            let player = &self.client_state.player;
+        let player_collider = if self.spectator {
+            self.spectated_player()
+        } else {
+            self.world.interpolated_player()
+        };
+        if !self.world.flags.get(Flag::POPPED) {
+            let player = &self.world.player;
             let animation = |frames: &[Rc<crate::assets::PixelTexture>], frame_time: f32| {
                 let frame_time = r32(frame_time);
                 let frame = (player.animation_time / frame_time)
@@ -375,29 +457,29 @@ impl GameSolver {
             let flip = !player.facing_left;
             geng_utils::texture::DrawTexture::new(&texture)
                 .transformed(mat3::scale(vec2(if flip { -1.0 } else { 1.0 }, 1.0)))
-                .fit_width(player.collider.compute_aabb().as_f32(), 0.0)
+                .fit_width(player_collider.compute_aabb().as_f32(), 0.0)
                 .draw(&self.camera, &self.context.geng, framebuffer);
         }
 
         // Held item
-        if let Some(item) = &self.client_state.picked_up_item {
+        if let Some(item) = &self.world.picked_up_item {
             let texture = assets.solver.sprites.item_texture(item.kind);
             let mut collider = item.collider.clone();
-            let dir = if self.client_state.player.facing_left {
+            let dir = if self.world.player.facing_left {
                 vec2(-1.0, 0.0)
             } else {
                 vec2(1.0, 0.0)
             }
             .as_r32();
-            collider.position = self.client_state.player.collider.position + dir * r32(0.5);
+            collider.position = player_collider.position + dir * r32(0.5);
             geng_utils::texture::DrawTexture::new(texture)
                 .fit(collider.compute_aabb().as_f32(), vec2(0.5, 0.5))
                 .draw(&self.camera, &self.context.geng, framebuffer);
         }
 
         // Interact hint
-        if let Some(i) = self.client_state.interact_item
-            && let Some(item) = self.client_state.items.get(i)
+        if let Some(i) = self.world.interact_item
+            && let Some(item) = self.world.items.get(i)
         {
             let pos = item.collider.compute_aabb().top_right();
             let pos = Aabb2::point(pos.as_f32()).extend_positive(vec2(1.0, 1.0));
@@ -407,7 +489,7 @@ impl GameSolver {
         }
 
         // Explosion
-        if let Some((pos, time)) = self.client_state.explosion {
+        if let Some((pos, time)) = self.world.explosion {
             let frames = &assets.solver.sprites.explosion;
             let frame = (time.as_f32() * frames.len() as f32).floor() as usize;
             if let Some(frame) = frames.get(frame) {
@@ -421,682 +503,175 @@ impl GameSolver {
         }
     }
 
-    fn player_respawn(&mut self) {
-        self.client_state.level_static_colliders.clear();
-        let assets = self.context.assets.get();
-        let Some(level) = assets.solver.levels.get(self.state.current_level) else {
-            return;
-        };
-
-        let player = &mut self.client_state.player;
-        player.collider.position =
-            level.spawnpoint + vec2(r32(0.0), player.collider.compute_aabb().height() / r32(2.0));
-        player.velocity = vec2::ZERO;
-    }
-
-    fn update_level_colliders(&mut self) {
-        let assets = self.context.assets.get();
-        let Some(level) = assets.solver.levels.get(self.state.current_level) else {
-            return;
-        };
-
-        let wall_thickness = r32(1.0);
-        let door_height = r32(2.0);
-
-        // Floor
-        self.client_state
-            .level_static_colliders
-            .push(Collider::aabb(
-                Aabb2::ZERO
-                    .extend_right(r32(LEVEL_SIZE.x))
-                    .extend_up(wall_thickness),
-            ));
-        // Left wall
-        self.client_state
-            .level_static_colliders
-            .push(Collider::aabb(
-                Aabb2::point(vec2(r32(0.0), door_height + wall_thickness))
-                    .extend_up(r32(LEVEL_SIZE.y))
-                    .extend_right(wall_thickness),
-            ));
-        // Right wall
-        self.client_state
-            .level_static_colliders
-            .push(Collider::aabb(
-                Aabb2::point(vec2(LEVEL_SIZE.x.as_r32(), door_height + wall_thickness))
-                    .extend_up(r32(LEVEL_SIZE.y))
-                    .extend_left(wall_thickness),
-            ));
-        // Ceiling
-        self.client_state
-            .level_static_colliders
-            .push(Collider::aabb(
-                Aabb2::point(vec2(0.0, LEVEL_SIZE.y).as_r32())
-                    .extend_right(r32(LEVEL_SIZE.x))
-                    .extend_down(wall_thickness),
-            ));
-
-        let door_width = r32(0.3);
-
-        // Entrance door
-        self.client_state.door_entrance = Collider::aabb(
-            Aabb2::point(vec2(0.0.as_r32(), wall_thickness))
-                .extend_up(door_height)
-                .extend_right(door_width),
-        );
-
-        // Exit door
-        self.client_state.door_exit = Collider::aabb(
-            Aabb2::point(vec2(LEVEL_SIZE.x.as_r32(), wall_thickness))
-                .extend_up(door_height)
-                .extend_left(door_width),
-        );
-
-        // Platforms
-        let platform_size = assets.solver.sprites.platform.size().as_f32();
-        self.client_state.platforms = level
-            .platforms
-            .iter()
-            .map(|platform| {
-                let size = vec2(
-                    platform.width,
-                    platform.width / platform_size.aspect().as_r32(),
-                );
-                Collider::aabb(
-                    Aabb2::point(platform.pos)
-                        .extend_symmetric(vec2(size.x, r32(0.0) / r32(2.0)))
-                        .extend_down(size.y),
-                )
-            })
-            .collect();
-
-        if self.state.current_level == 3 {
-            // Bubble tea walls
-            self.client_state.level_static_colliders.push(Collider {
-                position: vec2(5.0, 4.5).as_r32(),
-                rotation: Angle::from_degrees(15.0).as_r32(),
-                shape: Shape::Rectangle {
-                    width: r32(0.07),
-                    height: r32(9.0),
-                },
-            });
-            self.client_state.level_static_colliders.push(Collider {
-                position: vec2(11.0, 9.0 - 3.3).as_r32(),
-                rotation: Angle::from_degrees(-15.0).as_r32(),
-                shape: Shape::Rectangle {
-                    width: r32(0.07),
-                    height: r32(4.5),
-                },
-            });
-            // Door
-            self.client_state.level_static_colliders.push(Collider {
-                position: vec2(10.15, 9.0 - 7.05).as_r32(),
-                rotation: Angle::from_degrees(-15.0).as_r32(),
-                shape: Shape::Rectangle {
-                    width: r32(0.07),
-                    height: r32(2.4),
-                },
-            });
-
-            // Bubbles
-            let ball = &Collider::circle(vec2::ZERO, r32(0.5));
-            self.client_state.bubble_balls = (0..4)
-                .flat_map(|x| {
-                    (0..6).map(move |y| {
-                        let mut rng = thread_rng();
-                        let mut ball = ball.clone();
-                        ball.position = vec2(6.5, 1.5).as_r32()
-                            + vec2(x, y).as_r32()
-                            + vec2(rng.gen_range(-0.01..=0.01), rng.gen_range(-0.01..=0.01))
-                                .as_r32();
-                        (ball, rng.gen_range(0..=3))
-                    })
-                })
-                .collect();
-        }
-    }
-
-    fn update_projectiles(&mut self, delta_time: FTime) {
-        let mut remove_projs = Vec::new();
-        for (proj_i, proj) in self.client_state.projectiles.iter_mut().enumerate() {
-            proj.collider.position += proj.velocity * delta_time;
-            if self
-                .client_state
-                .level_static_colliders
-                .iter()
-                .any(|col| proj.collider.check(col))
-            {
-                remove_projs.push(proj_i);
-            } else if proj.collider.check(&self.client_state.player.collider) {
-                self.game_crash("ты попался карасю");
-                return;
-                // remove_projs.push(proj_i);
-            }
-        }
-
-        for i in remove_projs.into_iter().rev() {
-            self.client_state.projectiles.swap_remove(i);
-        }
-    }
-
-    fn update_balls(&mut self, delta_time: FTime) {
-        for (ball, _) in &mut self.client_state.bubble_balls {
-            let mut any_collision = false;
-            for static_col in self
-                .client_state
-                .level_static_colliders
-                .iter()
-                .chain(&self.client_state.platforms)
-            {
-                if let Some(collision) = ball.collide(static_col) {
-                    ball.position -= collision.normal * collision.penetration;
-                    any_collision = true;
+    pub(super) fn handle_message(&mut self, message: ServerMessage) {
+        match message {
+            ServerMessage::Hello { .. }
+            | ServerMessage::Ping
+            | ServerMessage::RoomJoined(..)
+            | ServerMessage::StartGame(..)
+            | ServerMessage::SpectateGame(..)
+            | ServerMessage::YourToken(_)
+            | ServerMessage::SyncRoomPlayers(_) => {}
+            ServerMessage::SyncSolverPlayer(player) => {
+                if self.spectator {
+                    self.spectated_from = self.spectated_player().position;
+                    self.spectated_since = FTime::ZERO;
+                    self.world.set_player(player);
                 }
             }
-            if !any_collision {
-                ball.position += vec2(0.0, -2.0).as_r32() * delta_time;
+            ServerMessage::Error(error) => {
+                log::error!("Server error: {error}");
+                self.announcement = Some(Announcement::new(error.message()));
             }
-        }
-
-        let items_count = self.client_state.bubble_balls.len();
-        for i in 0..items_count {
-            for j in i + 1..items_count {
-                if let Ok([(ball, _), (other, _)]) =
-                    self.client_state.bubble_balls.get_disjoint_mut([i, j])
-                    && let Some(collision) = ball.collide(other)
+            ServerMessage::Announcement(text) => self.announcement = Some(Announcement::new(text)),
+            ServerMessage::SyncFlags(flags) => self.sync_flags(flags),
+            ServerMessage::SyncSolverState(solver_state) => self.sync_state(solver_state),
+            ServerMessage::GameCrash(message) => self.game_crash(message),
+            ServerMessage::SessionResumed(session) => {
+                self.world.sync_flags(session.flags);
+                self.sync_state(session.solver);
+                // After the state, since a level reload respawns the player
+                if let Some((level, player)) = session.solver_player
+                    && level == self.world.state.current_level
                 {
-                    let offset = collision.normal * collision.penetration * r32(0.5);
-                    ball.position -= offset;
-                    other.position += offset;
+                    self.world.set_player(player);
                 }
             }
         }
     }
 
-    fn update_items(&mut self, delta_time: FTime) {
-        // Item movement
-        for item in &mut self.client_state.items {
-            if item.has_gravity {
-                let collision = self
-                    .client_state
-                    .level_static_colliders
-                    .iter()
-                    .chain(&self.client_state.platforms)
-                    .filter_map(|static_col| item.collider.collide(static_col))
-                    .max_by_key(|col| col.penetration);
-                match collision {
-                    None => {
-                        item.collider.position += vec2(0.0, -5.0).as_r32() * delta_time;
-                    }
-                    Some(collision) => {
-                        item.collider.position -= collision.normal * collision.penetration;
-                    }
-                }
-            }
-
-            if self.state.current_level == 4
-                && self.state.levels_completed <= 4
-                && item.kind == SolverItemKind::Fish
-            {
-                // Shoot projectiles
-                self.client_state.fish_cooldown -= delta_time;
-                if self.client_state.fish_cooldown.as_f32() <= 0.0 {
-                    self.client_state.fish_cooldown += FTime::new(0.7);
-                    let position = item.collider.position;
-                    self.client_state.projectiles.push(Projectile {
-                        collider: Collider::circle(position, r32(0.2)),
-                        velocity: (self.client_state.player.collider.position - position)
-                            .normalize_or_zero()
-                            * r32(5.0),
-                    });
-                }
-            }
-        }
-
-        // Item collision
-        let items_count = self.client_state.items.len();
-        let mut remove_items = Vec::new();
-        for i in 0..items_count {
-            for j in i + 1..items_count {
-                if let Ok([item, other]) = self.client_state.items.get_disjoint_mut([i, j]) {
-                    let check_combination = |a, b| {
-                        item.kind == a && other.kind == b || item.kind == b && other.kind == a
-                    };
-
-                    use crate::assets::SolverItemKind::*;
-                    if check_combination(Fish, CinderBlock) && item.collider.check(&other.collider)
-                    {
-                        // Explosion
-                        remove_items.extend([i, j]);
-                        self.client_state.explosion = Some((item.collider.position, FTime::ZERO));
-                    }
-                }
-            }
-        }
-        remove_items.sort();
-        for i in remove_items.into_iter().rev() {
-            self.client_state.items.swap_remove(i);
+    fn sync_state(&mut self, solver_state: SolverState) {
+        if self.world.sync_state(solver_state) {
+            self.reload_level();
         }
     }
 
-    fn update_player(&mut self, delta_time: FTime) {
-        let anim_state = self.client_state.player.animation_state();
-
-        {
-            let state = &mut self.client_state;
-            let rules = &self.context.assets.get().solver.rules;
-            state.player.update_timers(delta_time);
-
-            // Update Jump Buffer
-            if self.player_control.jump {
-                state.player.jump_buffer = Some(rules.buffer_time);
-            }
-
-            // Update Jump Hold
-            if state.player.can_hold_jump && !self.player_control.hold_jump {
-                state.player.can_hold_jump = false;
-            }
-
-            // Update look direction
-            let player = &mut state.player;
-            if player.facing_left && player.velocity.x > FCoord::ZERO
-                || !player.facing_left && player.velocity.x < FCoord::ZERO
-            {
-                player.facing_left = !player.facing_left;
-            }
-
-            // Apply gravity
-            state.player.velocity += rules.gravity * delta_time;
+    fn sync_flags(&mut self, flags: Flags) {
+        let opens_bubble_door =
+            !self.world.flags.get(Flag::SOLVED_BUBBLE_CODE) && flags.get(Flag::SOLVED_BUBBLE_CODE);
+        self.world.sync_flags(flags);
+        if opens_bubble_door {
+            self.context.geng.window().stop_text_edit();
         }
+    }
 
-        if self.player_control.pickup {
-            if let Some(mut item) = self.client_state.picked_up_item.take() {
-                // Drop item
-                let dir = if self.client_state.player.facing_left {
-                    vec2(-1.0, 0.0)
-                } else {
-                    vec2(1.0, 0.0)
-                }
-                .as_r32();
-                item.collider.position =
-                    self.client_state.player.collider.position + dir * r32(0.5);
-
-                let mut disappear = false;
-                for other in &self.client_state.items {
-                    let check = |a, b| {
-                        item.kind == a && other.kind == b && item.collider.check(&other.collider)
-                    };
-
-                    use SolverItemKind::*;
-                    if check(Recycle, Grandson) {
-                        self.client_state.grandson_spin = Some(Angle::ZERO);
-                    } else if check(Recycle, Grandpa) {
-                        self.client_state.grandpa_drill = Some(FTime::ZERO);
-                    } else if check(Grandpa, Trashcan) {
-                        self.game_crash("ДЕДЭНД: вспомни с кем честь имеешь, скорлупа");
-                        return;
-                    } else if check(Grandson, Trashcan) {
-                        disappear = true;
-                    }
-                }
+    fn game_crash(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        log::info!("Game restart: {message}");
+        self.world.respawn();
+    }
 
-                if !disappear {
-                    self.client_state.items.push(item);
+    /// React to the outcome of a simulation step.
+    fn handle_world_event(&mut self, event: SolverEvent) {
+        match event {
+            SolverEvent::Action(action) => {
+                if !self.spectator {
+                    self.connection.send(ClientMessage::SolverAction(action));
                 }
-            } else if let Some(i) = self.client_state.interact_item
-                && let Some(item) = self.client_state.items.get(i)
-            {
-                if item.kind == SolverItemKind::BubbleCode {
-                } else {
-                    // Pick up an item
-                    self.client_state.picked_up_item = Some(self.client_state.items.swap_remove(i));
+            }
+            SolverEvent::Crash(message) => log::info!("Game restart: {message}"),
+            SolverEvent::PlayMusic(music) => {
+                self.music = music;
+                if !self.spectator {
+                    let assets = self.context.assets.get();
+                    self.context
+                        .music
+                        .play_music(assets.sounds.level_music(music));
                 }
             }
-        }
-
-        self.player_variable_jump(delta_time);
-        self.player_horizontal_control(delta_time);
-        self.player_jump(delta_time);
-
-        self.player_move(delta_time);
-        self.player_update_state();
-
-        if anim_state != self.client_state.player.animation_state() {
-            self.client_state.player.animation_time = FTime::ZERO;
-        }
-
-        self.check_transition();
-        self.check_out_of_bounds();
-
-        self.client_state.interact_item = self.client_state.items.iter().position(|item| {
-            (item.can_pickup || item.kind == SolverItemKind::BubbleCode)
-                && item.collider.check(&self.client_state.player.collider)
-                && !(self.state.trashcan_evil && matches!(item.kind, SolverItemKind::Recycle))
-        });
-
-        if self.state.current_level == 5 {
-            self.connection.send(ClientMessage::SyncSolverPlayer(
-                self.client_state.player.clone(),
-            ));
-        }
-
-        self.player_control.take();
-    }
-
-    fn player_variable_jump(&mut self, delta_time: FTime) {
-        let state = &mut self.client_state;
-        let rules = &self.context.assets.get().solver.rules;
-
-        // Variable jump height
-        if state.player.velocity.y < FCoord::ZERO {
-            // Faster drop
-            state.player.velocity.y +=
-                rules.gravity.y * (rules.fall_multiplier - FCoord::ONE) * delta_time;
-            let cap = rules.free_fall_speed;
-            state.player.velocity.y = state.player.velocity.y.clamp_abs(cap);
-        } else if state.player.velocity.y > FCoord::ZERO
-            && !(self.player_control.hold_jump && state.player.can_hold_jump)
-        {
-            // Low jump
-            state.player.velocity.y +=
-                rules.gravity.y * (rules.low_multiplier - FCoord::ONE) * delta_time;
-        }
-    }
-
-    fn player_horizontal_control(&mut self, delta_time: FTime) {
-        let state = &mut self.client_state;
-        let rules = &self.context.assets.get().solver.rules;
-
-        if state.player.control_timeout.is_some() {
-            return;
-        }
-
-        // Horizontal speed control
-        let current = state.player.velocity.x;
-        let max_speed = rules.move_speed;
-        let target = self.player_control.move_dir.x * max_speed;
-
-        let mut acc = FCoord::ZERO;
-
-        // Acceleration
-        let is_grounded = matches!(state.player.state, PlayerState::Grounded);
-        if target == FCoord::ZERO
-            || target.signum() != current.signum()
-            || target.abs() > current.abs()
-        {
-            // Accelerate towards target
-            acc += if is_grounded {
-                rules.acceleration_ground
-            } else {
-                rules.acceleration_air
-            };
-        } else {
-            // Target is aligned with current velocity and is higher
-            // Decelerate
-            acc += if is_grounded {
-                rules.deceleration_ground
-            } else {
-                rules.deceleration_air
-            };
-        }
-
-        state.player.velocity.x += (target - current).clamp_abs(acc * delta_time);
-    }
-
-    fn player_jump(&mut self, _delta_time: FTime) {
-        let state = &mut self.client_state;
-        let rules = &self.context.assets.get().solver.rules;
-
-        if state.player.jump_buffer.is_none() {
-            return;
-        }
-
-        // Try jump
-        let jump = match state.player.state {
-            PlayerState::Grounded => true,
-            PlayerState::Airborn => state.player.coyote_time.is_some(),
-        };
-        if !jump {
-            return;
-        }
-
-        // Use jump
-        state.player.coyote_time = None;
-        state.player.jump_buffer = None;
-        state.player.can_hold_jump = true;
-        let player = &mut state.player;
-        let push = if self.player_control.move_dir.x == FCoord::ZERO {
-            FCoord::ZERO
-        } else {
-            rules.jump_push * self.player_control.move_dir.x.signum()
-        };
-        let jump_vel = vec2(player.velocity.x + push, rules.jump_strength);
-        player.velocity = jump_vel;
-        player.state = PlayerState::Airborn;
-        // self.world.assets.sounds.jump.play();
-        // self.spawn_particles(ParticleSpawn {
-        //     lifetime: Time::ONE,
-        //     position: actor.collider.feet(),
-        //     velocity: vec2(Coord::ZERO, Coord::ONE),
-        //     amount: 3,
-        //     color: Rgba::WHITE,
-        //     radius: Coord::new(0.1),
-        //     ..Default::default()
-        // });
-    }
-
-    fn player_check_ground(&mut self) {
-        let rules = &self.context.assets.get().solver.rules;
-        let player = &mut self.client_state.player;
-        let was_grounded = matches!(player.state, PlayerState::Grounded);
-        if was_grounded {
-            player.state = PlayerState::Airborn;
-        }
-        let update_state = (matches!(player.state, PlayerState::Airborn) || was_grounded)
-            && player.velocity.y <= FCoord::ZERO;
-
-        if update_state {
-            let collider = player.feet_collider();
-
-            if self
-                .check_ground_collision(&self.client_state.player.collider, &collider)
-                .is_some()
-            {
-                let player = &mut self.client_state.player;
-                player.state = PlayerState::Grounded;
-                player.coyote_time = Some(rules.coyote_time);
-
-                // if !was_grounded {
-                //     // Just landed
-                //     let spawn = ParticleSpawn {
-                //         lifetime: Time::ONE,
-                //         position: actor.collider.feet(),
-                //         velocity: vec2(Coord::ZERO, Coord::ONE) * Coord::new(0.5),
-                //         amount: 3,
-                //         color: Rgba::WHITE,
-                //         radius: Coord::new(0.1),
-                //         ..Default::default()
-                //     };
-                //     self.spawn_particles(spawn);
-                // }
+            SolverEvent::BubblesPopped => {
+                self.context
+                    .assets
+                    .get()
+                    .sounds
+                    .pop
+                    .choose(&mut thread_rng())
+                    .unwrap()
+                    .play();
             }
         }
     }
 
-    fn player_move(&mut self, delta_time: FTime) {
-        let player = &mut self.client_state.player;
-        player.collider.position += player.velocity * delta_time;
-
-        let fix_collision = |player: &mut Player, collision: &Collision| {
-            player.collider.position -= collision.normal * collision.penetration;
-            player.velocity -= collision.normal * vec2::dot(player.velocity, collision.normal);
-        };
-        let collide_with = |player: &mut Player, other: &Collider| {
-            if let Some(collision) = player.collider.collide(other) {
-                fix_collision(player, &collision);
+    /// Simulate a single step with the current input.
+    /// Returns `false` once the replay has finished.
+    fn step(&mut self) -> bool {
+        if let Some(replay) = &mut self.replay {
+            for entry in replay.take_due(self.world.tick) {
+                self.apply_recorded(entry);
             }
-        };
-
-        // Bubbles
-        let player = &mut self.client_state.player;
-        let mut remove_balls = Vec::new();
-        for (ball_i, (ball, _)) in self.client_state.bubble_balls.iter().enumerate() {
-            if let Some(collision) = player.collider.collide(ball) {
-                let velocity_offset =
-                    collision.normal * vec2::dot(player.velocity, collision.normal);
-                if velocity_offset.len() > r32(7.0) {
-                    remove_balls.push(ball_i);
-                }
-                player.collider.position -= collision.normal * collision.penetration.min(r32(0.5));
-                player.velocity -= velocity_offset;
+            if self.replay.as_ref().is_some_and(Replay::is_finished) {
+                return false;
             }
         }
-        if !remove_balls.is_empty() {
-            self.context
-                .assets
-                .get()
-                .sounds
-                .pop
-                .choose(&mut thread_rng())
-                .unwrap()
-                .play();
-        }
-        for i in remove_balls.into_iter().rev() {
-            self.client_state.bubble_balls.swap_remove(i);
-        }
-
-        // Static colliders
-        for static_col in &self.client_state.level_static_colliders {
-            collide_with(player, static_col);
-        }
-
-        // Doors
-        collide_with(player, &self.client_state.door_entrance);
-        if !self.state.is_exit_open() {
-            collide_with(player, &self.client_state.door_exit);
-        }
-
-        // Platforms
-        if player.velocity.y.as_f32() <= 0.0 {
-            let collider = player.feet_collider();
-            for platform in &self.client_state.platforms {
-                if let Some(collision) = collider.collide(platform) {
-                    fix_collision(player, &collision);
-                }
-            }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_step(self.world.tick, &self.player_control);
         }
 
-        // Items
-        for item in &mut self.client_state.items {
-            if item.pushable
-                && let Some(collision) = player.collider.collide(&item.collider)
-            {
-                let offset = collision.normal * collision.penetration;
-                // let velocity_offset =
-                //     collision.normal * vec2::dot(player.velocity, collision.normal);
-                player.collider.position -= offset * r32(0.5);
-                // player.velocity -= velocity_offset * r32(0.5);
-                item.collider.position += vec2::UNIT_X * vec2::dot(vec2::UNIT_X, offset) * r32(0.5);
-            }
+        let events = self.world.step(self.player_control.clone());
+        if self.replay.is_none() {
+            // Presses only last for a single step
+            self.player_control.jump = false;
+            self.player_control.pickup = false;
         }
-    }
-
-    fn player_update_state(&mut self) {
-        self.player_check_ground();
-    }
-
-    fn check_ground_collision(
-        &self,
-        collider: &Collider,
-        feet_collider: &Collider,
-    ) -> Option<Collision> {
-        self.client_state
-            .level_static_colliders
-            .iter()
-            .chain(&self.client_state.platforms)
-            .filter_map(|static_col| feet_collider.collide(static_col))
-            .chain(
-                self.client_state
-                    .bubble_balls
-                    .iter()
-                    .map(|(ball, _)| ball)
-                    .filter_map(|col| collider.collide(col)),
-            )
-            .max_by_key(|col| col.penetration)
-    }
-
-    fn check_transition(&mut self) {
-        let assets = self.context.assets.get();
-        let Some(level) = assets.solver.levels.get(self.state.current_level) else {
-            return;
-        };
-        let player = &self.client_state.player;
-        if self.state.is_exit_open() && player.collider.check(&Collider::aabb(level.transition)) {
-            self.state.current_level += 1;
-            self.connection
-                .send(ClientMessage::SyncSolverState(self.state.clone()));
-            drop(assets);
-            self.reload_level();
+        for event in events {
+            self.handle_world_event(event);
         }
+        true
     }
 
-    fn check_out_of_bounds(&mut self) {
-        let player = &self.client_state.player;
-        if player.collider.position.y < r32(-50.0) {
-            self.player_respawn();
+    fn record(&mut self, entry: RecordEntry) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.world.tick, entry);
         }
     }
 
-    fn handle_message(&mut self, message: ServerMessage) {
-        match message {
-            ServerMessage::Ping
-            | ServerMessage::RoomJoined(..)
-            | ServerMessage::StartGame(..)
-            | ServerMessage::SyncSolverPlayer(_)
-            | ServerMessage::YourToken(_)
-            | ServerMessage::SyncRoomPlayers(_) => {}
-            ServerMessage::Error(error) => log::error!("Server error: {error}"),
-            ServerMessage::SyncDispatcherState(dispatcher_state) => {
-                self.dispatcher_state = dispatcher_state
+    fn apply_recorded(&mut self, entry: RecordEntry) {
+        match entry {
+            RecordEntry::Input(control) => self.player_control = control,
+            RecordEntry::Message(message) => self.handle_message(message),
+            RecordEntry::ReloadLevel => self.reload_level(),
+            RecordEntry::Heartbeat => {}
+            RecordEntry::End => {
+                log::info!("Replay finished at tick {}", self.world.tick);
+                self.announcement = Some(Announcement::new("Запись закончилась"));
             }
-            ServerMessage::SyncSolverState(solver_state) => self.state = solver_state,
-            ServerMessage::GameCrash(message) => self.game_crash(message),
         }
     }
 
-    fn game_crash(&mut self, message: impl Into<String>) {
-        let message = message.into();
-        log::info!("Game restart: {message}");
-        self.reload_level();
+    /// Whether the level has a bubble code panel that accepts input.
+    fn bubble_code_active(&self) -> bool {
+        !self.world.flags.get(Flag::SOLVED_BUBBLE_CODE)
+            && self
+                .world
+                .items
+                .iter()
+                .any(|item| item.kind == SolverItemKind::BubbleCode)
     }
 
     fn press_enter(&mut self) {
-        if self.state.current_level == 3
-            && !self.state.solved_bubble_code
-            && self.client_state.bubble_code == vec![4, 2, 1, 3]
-        {
-            self.context.geng.window().stop_text_edit();
-            self.state.solved_bubble_code = true;
-            self.client_state.level_static_colliders.pop();
+        if self.bubble_code_active() {
             self.connection
-                .send(ClientMessage::SyncSolverState(self.state.clone()));
+                .send(ClientMessage::SolverAction(SolverAction::EnterBubbleCode(
+                    self.bubble_code.clone(),
+                )));
         }
     }
 
-    fn press_escape(&mut self) {}
+    fn press_escape(&mut self) {
+        if self.test_level.is_some() {
+            self.leave = true;
+        }
+    }
 
     fn press_backspace(&mut self) {
-        if self.state.current_level == 3 && !self.state.solved_bubble_code {
-            self.client_state.bubble_code.pop();
+        if self.bubble_code_active() {
+            self.bubble_code.pop();
         }
     }
 
     fn press_digit(&mut self, digit: usize) {
-        if self.state.current_level == 3
-            && !self.state.solved_bubble_code
-            && self.client_state.bubble_code.len() < 4
-        {
-            self.client_state.bubble_code.push(digit);
+        if self.bubble_code_active() && self.bubble_code.len() < 4 {
+            self.bubble_code.push(digit);
         }
     }
 }
@@ -1104,84 +679,79 @@ impl GameSolver {
 impl geng::State for GameSolver {
     fn update(&mut self, delta_time: f64) {
         if let Some(Ok(message)) = self.connection.try_recv() {
+            self.record(RecordEntry::Message(message.clone()));
             self.handle_message(message);
         }
+        if self.connection.is_reconnecting() {
+            return;
+        }
 
         let delta_time = FTime::new(delta_time as f32);
-        self.client_state.time += delta_time;
+        if self.spectator {
+            self.update_spectated(delta_time);
+            return;
+        }
 
-        {
+        if self.replay.is_none() {
             let window = self.context.geng.window();
-            let controls = &self.context.assets.get().solver.controls;
+            let assets = self.context.assets.get();
+            let controls = &assets.solver.controls;
+            let mut move_dir = vec2::ZERO;
             if geng_utils::key::is_key_pressed(window, &controls.move_left) {
-                self.player_control.move_dir += vec2(-1.0, 0.0).as_r32();
+                move_dir += vec2(-1.0, 0.0).as_r32();
             }
             if geng_utils::key::is_key_pressed(window, &controls.move_right) {
-                self.player_control.move_dir += vec2(1.0, 0.0).as_r32();
+                move_dir += vec2(1.0, 0.0).as_r32();
             }
-            if geng_utils::key::is_key_pressed(window, &controls.jump) {
-                self.player_control.hold_jump = true;
+            if geng_utils::key::is_key_pressed(window, &controls.move_down) {
+                move_dir += vec2(0.0, -1.0).as_r32();
             }
-        }
-
-        if self.state.popped && self.client_state.explosion.is_none() {
-            self.client_state.explosion =
-                Some((self.client_state.player.collider.position, FTime::ZERO));
-        }
 
-        if let Some(spin) = &mut self.client_state.grandson_spin {
-            *spin += Angle::from_degrees(r32(360.0) * delta_time);
-            if spin.as_degrees().as_f32() > 360.0 {
-                self.client_state.grandson_spin = None;
+            let mut gamepads = self.context.gamepads.borrow_mut();
+            gamepads.poll();
+            let pad = &controls.gamepad;
+            let stick = gamepads.max_value(&pad.move_right, pad.deadzone)
+                - gamepads.max_value(&pad.move_left, pad.deadzone);
+            move_dir.x = (move_dir.x + r32(stick)).clamp(-FCoord::ONE, FCoord::ONE);
+            if move_dir.y == FCoord::ZERO && gamepads.is_pressed(&pad.move_down, pad.deadzone) {
+                move_dir.y = -FCoord::ONE;
             }
-        }
-        if let Some(time) = &mut self.client_state.grandpa_drill {
-            let t = *time;
-            *time += delta_time;
-            if t.as_f32() <= 1.0 && time.as_f32() > 1.0 {
-                self.client_state.platforms.clear();
-                self.client_state.level_static_colliders.swap_remove(0);
-                if self.state.levels_completed == 2 {
-                    self.state.levels_completed += 1;
-                    self.connection
-                        .send(ClientMessage::SyncSolverState(self.state.clone()));
-                }
+            if gamepads.is_just_pressed(&pad.jump, pad.deadzone) {
+                self.player_control.jump = true;
+            }
+            if gamepads.is_just_pressed(&pad.pickup, pad.deadzone) {
+                self.player_control.pickup = true;
             }
-        }
-
-        if let Some((pos, timer)) = &mut self.client_state.explosion {
-            *timer += delta_time;
-            if timer.as_f32() > 1.0 {
-                if self.state.popped {
-                    self.game_crash("тебе конец, и игре тоже");
-                    return;
-                }
 
-                if (self.client_state.player.collider.position - *pos)
-                    .len()
-                    .as_f32()
-                    < 1.5
-                {
-                    self.game_crash("ты взорвался");
-                    return;
-                }
+            self.player_control.move_dir = move_dir;
+            self.player_control.hold_jump = geng_utils::key::is_key_pressed(window, &controls.jump)
+                || gamepads.is_pressed(&pad.jump, pad.deadzone);
+            // Pick up hot-reloaded rules
+            self.world.rules = assets.solver.rules.clone();
+        }
 
-                self.client_state.explosion = None;
-                if self.state.current_level == 1 && !self.state.is_exit_open() {
-                    self.state.levels_completed += 1;
-                    self.connection
-                        .send(ClientMessage::SyncSolverState(self.state.clone()));
-                }
+        for _ in 0..self.world.steps_due(delta_time) {
+            if !self.step() {
+                break;
             }
         }
 
-        self.update_projectiles(delta_time);
-        self.update_player(delta_time);
-        self.update_items(delta_time);
-        self.update_balls(delta_time);
+        // Stream at a fixed rate for the spectators and the dispatcher's monitor
+        if self.replay.is_none()
+            && self.test_level.is_none()
+            && self.player_sync.elapsed().as_secs_f64() >= 1.0 / PLAYER_SYNC_RATE
+        {
+            self.player_sync.reset();
+            self.connection
+                .send(ClientMessage::SyncSolverPlayer(self.world.player.clone()));
+        }
     }
 
     fn handle_event(&mut self, event: geng::Event) {
+        if self.connection.is_reconnecting() || self.replay.is_some() {
+            return;
+        }
+
         let assets = self.context.assets.get();
         let controls = &assets.solver.controls;
         if geng_utils::key::is_event_press(&event, &controls.jump) {
@@ -1196,19 +766,20 @@ impl geng::State for GameSolver {
         if let geng::Event::KeyPress { key } = event {
             match key {
                 geng::Key::F5 => {
+                    self.record(RecordEntry::ReloadLevel);
                     self.reload_level();
                 }
                 geng::Key::F4 if self.test => {
                     // Prev level
-                    self.state.current_level = self.state.current_level.saturating_sub(1);
-                    self.state.levels_completed = self.state.current_level;
-                    self.reload_level();
+                    self.connection.send(ClientMessage::SkipToLevel(
+                        self.world.state.current_level.saturating_sub(1),
+                    ));
                 }
                 geng::Key::F6 if self.test => {
                     // Next level
-                    self.state.current_level += 1;
-                    self.state.levels_completed = self.state.current_level;
-                    self.reload_level();
+                    self.connection.send(ClientMessage::SkipToLevel(
+                        self.world.state.current_level + 1,
+                    ));
                 }
                 geng::Key::Escape => self.press_escape(),
                 geng::Key::Backspace => self.press_backspace(),
@@ -1228,6 +799,10 @@ impl geng::State for GameSolver {
         }
     }
 
+    fn transition(&mut self) -> Option<geng::state::Transition> {
+        std::mem::take(&mut self.leave).then_some(geng::state::Transition::Pop)
+    }
+
     fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
         self.framebuffer_size = framebuffer.size();
         ugli::clear(framebuffer, Some(Rgba::BLACK), None, None);
@@ -1236,57 +811,16 @@ impl geng::State for GameSolver {
             .fit_screen(vec2(0.5, 0.5), framebuffer);
         self.screen = draw.target;
         draw.draw(&geng::PixelPerfectCamera, &self.context.geng, framebuffer);
-    }
-}
 
-impl Player {
-    fn update_timers(&mut self, delta_time: FTime) {
-        self.animation_time += delta_time;
-
-        // Coyote Time
-        if let Some(time) = &mut self.coyote_time {
-            *time -= delta_time;
-            if *time <= FTime::ZERO {
-                self.coyote_time = None;
-            }
-        }
-
-        // Jump Buffer
-        if let Some(time) = &mut self.jump_buffer {
-            *time -= delta_time;
-            if *time <= FTime::ZERO {
-                self.jump_buffer = None;
-            }
+        super::draw_announcement(&self.context, &mut self.announcement, framebuffer);
+        if self.connection.is_reconnecting() {
+            super::draw_reconnecting(&self.context, framebuffer);
         }
-
-        // Control timeout
-        if let Some(time) = &mut self.control_timeout {
-            // No horizontal control
-            *time -= delta_time;
-            if *time <= FTime::ZERO {
-                self.control_timeout = None;
-            }
-        }
-    }
-
-    fn feet_collider(&self) -> Collider {
-        let aabb = self.collider.compute_aabb();
-        Collider::aabb(
-            aabb.extend_symmetric(-vec2(aabb.width() * r32(0.05), r32(0.0)))
-                .extend_up(-aabb.height() * r32(0.8)),
-        )
     }
+}
 
-    pub fn animation_state(&self) -> PlayerAnimationState {
-        match self.state {
-            PlayerState::Grounded => {
-                if self.velocity.x.abs() > r32(0.01) {
-                    PlayerAnimationState::Running
-                } else {
-                    PlayerAnimationState::Idle
-                }
-            }
-            PlayerState::Airborn => PlayerAnimationState::Jumping,
-        }
+impl Drop for GameSolver {
+    fn drop(&mut self) {
+        self.record(RecordEntry::End);
     }
 }
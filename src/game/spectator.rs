@@ -0,0 +1,129 @@
+use super::*;
+
+use crate::{
+    assets::LevelMusic,
+    interop::{ClientConnection, ClientMessage, RoomInfo},
+    model::GameRole,
+};
+
+/// Read-only view of an ongoing game, switching between the solver and the dispatcher.
+pub struct GameSpectator {
+    context: Context,
+    connection: ClientConnection,
+    room: RoomInfo,
+    view: GameRole,
+    solver: GameSolver,
+    dispatcher: GameDispatcher,
+    /// Music currently playing, following the view.
+    music: Option<LevelMusic>,
+    /// Return to the main menu.
+    leave: bool,
+}
+
+impl GameSpectator {
+    pub fn new(context: &Context, connection: ClientConnection, room: RoomInfo) -> Self {
+        log::info!("Spectating room {}", room.code);
+        connection.enable_spectator_reconnect(&room.code);
+        // The views only get the messages forwarded here, so that neither takes them from the other
+        let mut spectator = Self {
+            context: context.clone(),
+            solver: GameSolver::spectate(context, ClientConnection::offline()),
+            dispatcher: GameDispatcher::spectate(context, ClientConnection::offline()),
+            connection,
+            room,
+            view: GameRole::Solver,
+            music: None,
+            leave: false,
+        };
+        spectator.update_music();
+        spectator
+    }
+
+    /// Play the music of the current view, unless it is already playing.
+    fn update_music(&mut self) {
+        let music = match self.view {
+            GameRole::Solver => self.solver.music,
+            GameRole::Dispatcher => LevelMusic::Dispatcher,
+        };
+        if self.music != Some(music) {
+            self.music = Some(music);
+            let assets = self.context.assets.get();
+            self.context
+                .music
+                .play_music(assets.sounds.level_music(music));
+        }
+    }
+
+    fn switch_view(&mut self) {
+        self.view = match self.view {
+            GameRole::Solver => GameRole::Dispatcher,
+            GameRole::Dispatcher => GameRole::Solver,
+        };
+    }
+}
+
+impl geng::State for GameSpectator {
+    fn update(&mut self, delta_time: f64) {
+        while let Some(message) = self.connection.try_recv() {
+            match message {
+                Ok(message) => {
+                    self.solver.handle_message(message.clone());
+                    self.dispatcher.handle_message(message);
+                }
+                Err(error) => log::error!("Spectator connection error: {error}"),
+            }
+        }
+        if self.connection.is_reconnecting() {
+            return;
+        }
+        geng::State::update(&mut self.solver, delta_time);
+        geng::State::update(&mut self.dispatcher, delta_time);
+        self.update_music();
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        if let geng::Event::KeyPress { key } = event {
+            match key {
+                geng::Key::Tab => {
+                    self.switch_view();
+                    self.update_music();
+                }
+                geng::Key::Escape => {
+                    self.connection.send(ClientMessage::LeaveRoom);
+                    self.leave = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn transition(&mut self) -> Option<geng::state::Transition> {
+        std::mem::take(&mut self.leave).then_some(geng::state::Transition::Pop)
+    }
+
+    fn draw(&mut self, framebuffer: &mut ugli::Framebuffer) {
+        match self.view {
+            GameRole::Solver => geng::State::draw(&mut self.solver, framebuffer),
+            GameRole::Dispatcher => geng::State::draw(&mut self.dispatcher, framebuffer),
+        }
+
+        let view = match self.view {
+            GameRole::Solver => "решатель",
+            GameRole::Dispatcher => "диспетчер",
+        };
+        let text = format!("Комната {}: {view} (Tab), выход (Esc)", self.room.code);
+        let screen = Aabb2::ZERO.extend_positive(framebuffer.size().map(|x| x as f32));
+        let label = screen
+            .extend_right(-screen.width() * 0.7)
+            .extend_down(-screen.height() * 0.93);
+        let font = self.context.geng.default_font();
+        self.context.geng.draw2d().draw2d(
+            framebuffer,
+            &geng::PixelPerfectCamera,
+            &draw2d::Text::unit(&**font, text, Rgba::WHITE).fit_into(label),
+        );
+        if self.connection.is_reconnecting() {
+            super::draw_reconnecting(&self.context, framebuffer);
+        }
+    }
+}
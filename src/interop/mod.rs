@@ -4,27 +4,283 @@ use geng::prelude::*;
 
 pub type ClientId = i64;
 
+/// Version of the network protocol, bumped on every incompatible change to the messages.
+pub const PROTOCOL_VERSION: u32 = 10;
+/// Identifier of the build, reported during the handshake for diagnostics.
+pub const BUILD: &str = env!("CARGO_PKG_VERSION");
+/// Times per second the solver streams its player to the server.
+pub const PLAYER_SYNC_RATE: f64 = 30.0;
+
 // pub type ClientConnection = geng::net::client::Connection<ServerMessage, ClientMessage>;
 
+type Connection = geng::net::client::Connection<ServerMessage, ClientMessage>;
+
+/// Time without any messages from the server after which the connection is considered lost.
+const CONNECTION_TIMEOUT: f64 = 3.0;
+/// Delay before the first reconnection attempt, doubled after each failed attempt.
+const RECONNECT_DELAY_MIN: f64 = 0.5;
+const RECONNECT_DELAY_MAX: f64 = 10.0;
+
 #[derive(Clone)]
 pub struct ClientConnection {
-    inner: Rc<RefCell<geng::net::client::Connection<ServerMessage, ClientMessage>>>,
+    inner: Rc<RefCell<ConnectionState>>,
+}
+
+struct ConnectionState {
+    addr: String,
+    connection: Option<Connection>,
+    /// Last token assigned by the server, used to resume the session.
+    token: Option<String>,
+    /// Whether to reconnect and resume the session when the connection is lost.
+    reconnect: bool,
+    /// Code of the room watched as a spectator, watched again instead of resuming a session.
+    spectating: Option<String>,
+    /// Waiting for the server to confirm the resumed session.
+    resuming: bool,
+    last_message: Timer,
+    attempt: Option<ReconnectAttempt>,
+}
+
+struct ReconnectAttempt {
+    /// Number of failed attempts so far.
+    failures: u32,
+    timer: Timer,
+    future: Option<Pin<Box<dyn Future<Output = anyhow::Result<Connection>>>>>,
 }
 
+/// The server speaks a different version of the protocol than the client.
+#[derive(Debug)]
+pub struct ProtocolMismatch {
+    /// Protocol version of the server, if its handshake could be decoded at all.
+    pub server_version: Option<u32>,
+    pub server_build: Option<String>,
+}
+
+impl fmt::Display for ProtocolMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "client protocol version {PROTOCOL_VERSION} (build {BUILD}) "
+        )?;
+        match (&self.server_version, &self.server_build) {
+            (Some(version), Some(build)) => {
+                write!(
+                    f,
+                    "is incompatible with server version {version} (build {build})"
+                )
+            }
+            _ => write!(f, "is incompatible with an unknown server version"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolMismatch {}
+
 impl ClientConnection {
+    /// Connect to the server and check that it speaks the same protocol version.
     pub async fn connect(addr: &str) -> anyhow::Result<Self> {
         let conn = geng::net::client::connect(addr).await?;
-        Ok(Self {
-            inner: Rc::new(RefCell::new(conn)),
-        })
+        let mut connection = Self {
+            inner: Rc::new(RefCell::new(ConnectionState {
+                addr: addr.to_owned(),
+                connection: Some(conn),
+                token: None,
+                reconnect: false,
+                spectating: None,
+                resuming: false,
+                last_message: Timer::new(),
+                attempt: None,
+            })),
+        };
+        connection.send(ClientMessage::hello());
+        match connection.next().await {
+            Some(Ok(ServerMessage::Hello {
+                protocol_version,
+                build,
+            })) => {
+                if protocol_version != PROTOCOL_VERSION {
+                    return Err(ProtocolMismatch {
+                        server_version: Some(protocol_version),
+                        server_build: Some(build),
+                    }
+                    .into());
+                }
+            }
+            Some(Ok(message)) => anyhow::bail!("unexpected handshake message: {message:?}"),
+            Some(Err(error)) => {
+                log::error!("Failed to decode the server handshake: {error}");
+                return Err(ProtocolMismatch {
+                    server_version: None,
+                    server_build: None,
+                }
+                .into());
+            }
+            None => anyhow::bail!("connection closed during the handshake"),
+        }
+        Ok(connection)
+    }
+
+    /// Connection that never reaches a server, for playing back recordings.
+    pub fn offline() -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(ConnectionState {
+                addr: String::new(),
+                connection: None,
+                token: None,
+                reconnect: false,
+                spectating: None,
+                resuming: false,
+                last_message: Timer::new(),
+                attempt: None,
+            })),
+        }
+    }
+
+    /// Automatically reconnect and resume the game session if the connection is lost.
+    pub fn enable_reconnect(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.reconnect = true;
+        inner.last_message.reset();
+    }
+
+    /// Automatically reconnect and watch the room again if the connection is lost.
+    pub fn enable_spectator_reconnect(&self, code: &str) {
+        self.inner.borrow_mut().spectating = Some(code.to_owned());
+        self.enable_reconnect();
+    }
+
+    /// Whether the connection has been lost and the session is not yet resumed.
+    pub fn is_reconnecting(&self) -> bool {
+        let inner = self.inner.borrow();
+        inner.reconnect && (inner.connection.is_none() || inner.resuming)
     }
 
     pub fn send(&self, message: ClientMessage) {
-        self.inner.borrow_mut().send(message);
+        let mut inner = self.inner.borrow_mut();
+        match &mut inner.connection {
+            Some(connection) => connection.send(message),
+            None => log::debug!("Dropped a message while disconnected: {message:?}"),
+        }
     }
 
     pub fn try_recv(&self) -> Option<anyhow::Result<ServerMessage>> {
-        self.inner.borrow_mut().try_recv()
+        let mut inner = self.inner.borrow_mut();
+        inner.update();
+        let message = inner.connection.as_mut()?.try_recv()?;
+        match &message {
+            Ok(message) => inner.observe(message),
+            Err(error) => {
+                if inner.reconnect {
+                    inner.disconnect(error);
+                }
+            }
+        }
+        Some(message)
+    }
+}
+
+impl ConnectionState {
+    fn observe(&mut self, message: &ServerMessage) {
+        self.last_message.reset();
+        match message {
+            ServerMessage::YourToken(token) => self.token = Some(token.clone()),
+            ServerMessage::SessionResumed(_) => {
+                log::info!("Session resumed");
+                self.resuming = false;
+            }
+            ServerMessage::SpectateGame(_) if self.resuming => {
+                log::info!("Spectating again");
+                self.resuming = false;
+            }
+            ServerMessage::Hello {
+                protocol_version,
+                build,
+            } if *protocol_version != PROTOCOL_VERSION => {
+                log::error!(
+                    "Server has been updated to protocol version {protocol_version} (build {build}), cannot resume the session"
+                );
+                self.resuming = false;
+                self.reconnect = false;
+            }
+            ServerMessage::Error(error) if error.is_fatal() => {
+                log::error!("Disconnected by the server: {error}");
+                self.connection = None;
+                self.resuming = false;
+                self.reconnect = false;
+            }
+            ServerMessage::Error(error) if self.resuming => {
+                log::error!("Failed to resume the session: {error}");
+                self.resuming = false;
+                self.reconnect = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self, reason: impl fmt::Display) {
+        log::warn!("Lost connection to the server: {reason}");
+        self.connection = None;
+        self.resuming = false;
+        self.attempt = Some(ReconnectAttempt {
+            failures: 0,
+            timer: Timer::new(),
+            future: None,
+        });
+    }
+
+    fn update(&mut self) {
+        if self.connection.is_some() {
+            if self.reconnect && self.last_message.elapsed().as_secs_f64() > CONNECTION_TIMEOUT {
+                self.disconnect("timed out");
+            }
+            return;
+        }
+
+        let Some(attempt) = &mut self.attempt else {
+            return;
+        };
+        if attempt.future.is_none() {
+            let delay = (RECONNECT_DELAY_MIN * 2.0_f64.powi(attempt.failures as i32))
+                .min(RECONNECT_DELAY_MAX);
+            if attempt.timer.elapsed().as_secs_f64() < delay {
+                return;
+            }
+            log::info!("Reconnecting to {}...", self.addr);
+            let addr = self.addr.clone();
+            attempt.future =
+                Some(async move { geng::net::client::connect(&addr).await }.boxed_local());
+        }
+
+        let Some(future) = &mut attempt.future else {
+            return;
+        };
+        let std::task::Poll::Ready(result) = future.as_mut().poll(
+            &mut std::task::Context::from_waker(futures::task::noop_waker_ref()),
+        ) else {
+            return;
+        };
+        attempt.future = None;
+        match result {
+            Ok(mut connection) => {
+                log::info!("Reconnected to the server");
+                connection.send(ClientMessage::hello());
+                if let Some(code) = &self.spectating {
+                    connection.send(ClientMessage::SpectateRoom(code.clone()));
+                    self.resuming = true;
+                } else if let Some(token) = &self.token {
+                    connection.send(ClientMessage::ResumeSession(token.clone()));
+                    self.resuming = true;
+                }
+                self.connection = Some(connection);
+                self.attempt = None;
+                self.last_message.reset();
+            }
+            Err(error) => {
+                log::warn!("Failed to reconnect: {error}");
+                attempt.failures += 1;
+                attempt.timer.reset();
+            }
+        }
     }
 }
 
@@ -34,46 +290,194 @@ impl Stream for ClientConnection {
         self: Pin<&mut Self>,
         cx: &mut std::task::Context,
     ) -> std::task::Poll<Option<Self::Item>> {
-        Stream::poll_next(
-            unsafe {
-                self.map_unchecked_mut(|pin| {
-                    &mut *((&mut *pin.inner.borrow_mut()) as *mut _) as &mut _
-                })
-            },
-            cx,
-        )
+        let mut inner = self.inner.borrow_mut();
+        inner.update();
+        let Some(connection) = &mut inner.connection else {
+            return std::task::Poll::Pending;
+        };
+        // SAFETY: the connection is never moved out of the state, only dropped in place.
+        let poll = Stream::poll_next(unsafe { Pin::new_unchecked(connection) }, cx);
+        if let std::task::Poll::Ready(Some(Ok(message))) = &poll {
+            inner.observe(message);
+        }
+        poll
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ServerMessage {
+    /// Sent right after connecting.
+    /// Must stay the first variant and keep its shape, so that every protocol version can decode it.
+    Hello {
+        protocol_version: u32,
+        build: String,
+    },
     Ping,
-    Error(String),
+    Error(ServerError),
     YourToken(String),
     RoomJoined(RoomInfo),
     SyncRoomPlayers(usize),
     StartGame(GameRole),
-    SyncDispatcherState(DispatcherState),
+    SyncFlags(Flags),
     SyncSolverState(SolverState),
     SyncSolverPlayer(Player),
     GameCrash(String),
+    SessionResumed(SessionInfo),
+    /// The game in the spectated room has started, followed by its current state.
+    SpectateGame(RoomInfo),
+    /// Message from the server admin.
+    Announcement(String),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ClientMessage {
+    /// Must be the first message sent after connecting.
+    /// Must stay the first variant and keep its shape, so that every protocol version can decode it.
+    Hello {
+        protocol_version: u32,
+        build: String,
+    },
     Pong,
     Login(String),
-    CreateRoom,
+    /// Rejoin the ongoing game of the player with the given token.
+    ResumeSession(String),
+    /// Create a room playing the level pack with the given name, or the shipped levels.
+    CreateRoom(Option<String>),
     JoinRoom(String),
+    /// Watch the game in the room with the given code without taking part in it.
+    SpectateRoom(String),
+    /// Leave the room, or stop spectating it.
+    LeaveRoom,
     SelectRole(GameRole),
-    SyncDispatcherState(DispatcherState),
-    SyncSolverState(SolverState),
+    SolverAction(SolverAction),
+    DispatcherAction(DispatcherAction),
+    /// Only accepted by servers running in test mode.
+    SkipToLevel(usize),
     SyncSolverPlayer(Player),
     CrashOther(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Reasons the server refused a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerError {
+    /// The client speaks a different version of the protocol, contains the server's version.
+    IncompatibleProtocol {
+        protocol_version: u32,
+        build: String,
+    },
+    RoomNotFound,
+    /// The level pack of the room is not installed.
+    UnknownLevelPack,
+    RoomFull,
+    GameInProgress,
+    SessionNotFound,
+    /// Removed from the room by the server admin.
+    Kicked,
+    /// The room has been closed by the server admin.
+    RoomClosed,
+    /// Disconnected for sending too many messages.
+    TooManyRequests,
+    /// Disconnected for sending a message exceeding the size limits.
+    MessageTooLong,
+    /// A player with the same token is already in the room, usually from another tab.
+    AlreadyInRoom,
+}
+
+impl ServerError {
+    /// The server stops talking to the client after sending the error.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::TooManyRequests | Self::MessageTooLong)
+    }
+
+    /// Localized message to show to the player.
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::IncompatibleProtocol { .. } => {
+                "Игра обновилась. Обновите страницу, чтобы продолжить."
+            }
+            Self::RoomNotFound => "Комнаты с таким кодом нет. Проверьте код.",
+            Self::UnknownLevelPack => "Набор уровней этой комнаты не установлен.",
+            Self::RoomFull => "Комната уже заполнена. Создайте новую.",
+            Self::GameInProgress => "В этой комнате уже идёт игра. Создайте новую.",
+            Self::SessionNotFound => "Игра уже закончилась. Вернитесь в главное меню.",
+            Self::Kicked => "Вас исключили из комнаты. Вернитесь в главное меню.",
+            Self::RoomClosed => "Комната закрыта сервером. Вернитесь в главное меню.",
+            Self::TooManyRequests | Self::MessageTooLong => {
+                "Соединение разорвано сервером. Обновите страницу."
+            }
+            Self::AlreadyInRoom => "Вы уже играете в этой комнате в другой вкладке.",
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncompatibleProtocol {
+                protocol_version,
+                build,
+            } => write!(
+                f,
+                "incompatible protocol, server uses version {protocol_version} (build {build})"
+            ),
+            Self::RoomNotFound => write!(f, "non-existent room code"),
+            Self::UnknownLevelPack => write!(f, "unknown level pack"),
+            Self::RoomFull => write!(f, "room already full"),
+            Self::GameInProgress => write!(f, "cannot join an ongoing game"),
+            Self::SessionNotFound => write!(f, "session not found"),
+            Self::Kicked => write!(f, "kicked from the room"),
+            Self::RoomClosed => write!(f, "room closed"),
+            Self::TooManyRequests => write!(f, "too many requests"),
+            Self::MessageTooLong => write!(f, "message too long"),
+            Self::AlreadyInRoom => write!(f, "token already in the room"),
+        }
+    }
+}
+
+impl ClientMessage {
+    pub fn hello() -> Self {
+        Self::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            build: BUILD.to_owned(),
+        }
+    }
+
+    /// Name of the message type, used for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Hello { .. } => "hello",
+            Self::Pong => "pong",
+            Self::Login(_) => "login",
+            Self::ResumeSession(_) => "resume_session",
+            Self::CreateRoom(_) => "create_room",
+            Self::JoinRoom(_) => "join_room",
+            Self::SpectateRoom(_) => "spectate_room",
+            Self::LeaveRoom => "leave_room",
+            Self::SelectRole(_) => "select_role",
+            Self::SolverAction(_) => "solver_action",
+            Self::DispatcherAction(_) => "dispatcher_action",
+            Self::SkipToLevel(_) => "skip_to_level",
+            Self::SyncSolverPlayer(_) => "sync_solver_player",
+            Self::CrashOther(_) => "crash_other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomInfo {
     pub code: String,
     pub players: usize,
+    /// Level pack the room is played with, `None` for the shipped levels.
+    pub pack: Option<String>,
+}
+
+/// Everything a client needs to continue an ongoing game after reconnecting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub room: RoomInfo,
+    pub role: GameRole,
+    pub solver: SolverState,
+    pub flags: Flags,
+    /// Last synced player of the solver and the level it is on.
+    pub solver_player: Option<(usize, Player)>,
 }
@@ -1,4 +1,6 @@
 #[cfg(not(target_arch = "wasm32"))]
+mod editor;
+#[cfg(not(target_arch = "wasm32"))]
 mod server;
 
 mod assets;
@@ -25,6 +27,55 @@ struct Args {
     pub geng: geng::CliArgs,
     #[clap(long)]
     pub test: Option<usize>,
+    /// Directory for the server to persist ongoing games in.
+    #[clap(long)]
+    pub data_dir: Option<std::path::PathBuf>,
+    /// Seconds without any connected players after which the server closes a room.
+    #[clap(long, default_value_t = 3600)]
+    pub room_ttl: u64,
+    /// File for the server to periodically dump its metrics into.
+    #[clap(long)]
+    pub metrics_file: Option<std::path::PathBuf>,
+    /// File for the server to append room events to.
+    #[clap(long)]
+    pub event_log: Option<std::path::PathBuf>,
+    /// Instead of opening the game, play with this many fake clients against the server.
+    #[clap(long)]
+    pub load_test: Option<usize>,
+    /// Duration of the load test in seconds.
+    #[clap(long, default_value_t = 30)]
+    pub load_test_duration: u64,
+    /// File to record the inputs and server messages of the solver's session into.
+    #[clap(long)]
+    pub record: Option<std::path::PathBuf>,
+    /// Play back a recorded solver session without connecting to a server.
+    #[clap(long)]
+    pub replay: Option<std::path::PathBuf>,
+    /// Directory of a level pack to play instead of the shipped levels.
+    #[clap(long)]
+    pub levels: Option<std::path::PathBuf>,
+    /// Check the assets and the level pack, if any, report all problems and exit.
+    #[clap(long)]
+    pub validate_assets: bool,
+    /// Convert a map exported from Tiled as JSON into a tile map for a level, print it and exit.
+    #[clap(long)]
+    pub import_tiled: Option<std::path::PathBuf>,
+}
+
+impl Args {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn server_config(&self) -> server::ServerConfig {
+        server::ServerConfig {
+            test: self.test.is_some(),
+            data_dir: self.data_dir.clone(),
+            room_ttl: std::time::Duration::from_secs(self.room_ttl),
+            // Only a dedicated server owns the terminal
+            console: self.connect.is_none(),
+            metrics_file: self.metrics_file.clone(),
+            event_log: self.event_log.clone(),
+            level_packs: assets::pack_dirs(self.levels.as_deref()),
+        }
+    }
 }
 
 fn main() {
@@ -33,6 +84,18 @@ fn main() {
 
     let mut args: Args = cli::parse();
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if args.validate_assets {
+        let valid = validate_assets(args.levels.as_deref());
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &args.import_tiled {
+        import_tiled(path);
+        return;
+    }
+
     if cfg!(debug_assertions) {
         args.geng.hot_reload = Some(true);
     }
@@ -41,6 +104,23 @@ fn main() {
     geng_options.window.title = "Ded End".to_string();
     geng_options.with_cli(&args.geng);
 
+    if let Some(path) = &args.replay {
+        let replay = match game::replay::Replay::load(path) {
+            Ok(replay) => replay,
+            Err(error) => {
+                log::error!("Failed to load the replay {path:?}: {error}");
+                return;
+            }
+        };
+        let levels = args.levels.clone();
+        Geng::run_with(&geng_options, move |geng| async move {
+            let context = load_context(&geng, None, levels).await;
+            let state = game::GameSolver::replay(&context, replay);
+            geng.run_state(state).await;
+        });
+        return;
+    }
+
     if args.connect.is_none() && args.server.is_none() {
         #[cfg(target_arch = "wasm32")]
         {
@@ -74,7 +154,7 @@ fn main() {
         #[cfg(not(target_arch = "wasm32"))]
         {
             let server = geng::net::Server::new(
-                server::App::new(args.test.is_some()),
+                server::App::new(args.server_config()),
                 args.server.as_deref().unwrap(),
             );
             let server_handle = server.handle();
@@ -84,7 +164,7 @@ fn main() {
     } else {
         #[cfg(not(target_arch = "wasm32"))]
         let server = if let Some(addr) = &args.server {
-            let server = geng::net::Server::new(server::App::new(args.test.is_some()), addr);
+            let server = geng::net::Server::new(server::App::new(args.server_config()), addr);
             let server_handle = server.handle();
             let server_thread = std::thread::spawn(move || {
                 server.run();
@@ -94,16 +174,19 @@ fn main() {
             None
         };
 
-        Geng::run_with(&geng_options, move |geng| async move {
-            let manager = geng.asset_manager();
-            let assets: Hot<assets::Assets> =
-                geng::asset::Load::load(manager, &run_dir().join("assets"), &())
-                    .await
-                    .unwrap();
-            let context = Context::new(geng.clone(), Rc::new(assets));
-            let state = menu::main::MainMenu::new(&context, args.connect, args.test).await;
-            geng.run_state(state).await;
-        });
+        match args.load_test {
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(clients) => server::load_test::run(
+                args.connect.as_deref().unwrap(),
+                clients,
+                std::time::Duration::from_secs(args.load_test_duration),
+            ),
+            _ => Geng::run_with(&geng_options, move |geng| async move {
+                let context = load_context(&geng, args.record, args.levels).await;
+                let state = menu::main::MainMenu::new(&context, args.connect, args.test).await;
+                geng.run_state(state).await;
+            }),
+        }
 
         #[cfg(not(target_arch = "wasm32"))]
         if let Some((server_handle, server_thread)) = server {
@@ -112,3 +195,79 @@ fn main() {
         }
     }
 }
+
+async fn load_context(
+    geng: &Geng,
+    record: Option<std::path::PathBuf>,
+    levels: Option<std::path::PathBuf>,
+) -> Context {
+    let manager = geng.asset_manager();
+    let assets: Hot<assets::Assets> =
+        geng::asset::Load::load(manager, &run_dir().join("assets"), &())
+            .await
+            .expect("failed to load the assets, run with --validate-assets to find the problems");
+    let mut context = Context::new(geng.clone(), Rc::new(assets));
+    context.record = record;
+    if let Some(dir) = levels {
+        context.pack = load_pack(&dir).map(Rc::new);
+    }
+    context
+}
+
+/// Returns whether no problems have been found.
+#[cfg(not(target_arch = "wasm32"))]
+fn validate_assets(pack: Option<&std::path::Path>) -> bool {
+    let dir = run_dir().join("assets");
+    let mut errors = assets::validate_assets(&dir);
+    if let Some(pack) = pack {
+        errors.extend(assets::validate_pack(pack));
+    }
+
+    for error in &errors {
+        log::error!("{error}");
+    }
+    if errors.is_empty() {
+        log::info!("The assets are valid");
+    } else {
+        log::error!("Found {} problems in the assets", errors.len());
+    }
+    errors.is_empty()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn import_tiled(path: &std::path::Path) {
+    let tiles = std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| assets::TileMap::from_tiled(&json));
+    match tiles {
+        Ok(tiles) => match ron::ser::to_string_pretty(&tiles, ron::ser::PrettyConfig::default()) {
+            Ok(tiles) => println!("{tiles}"),
+            Err(error) => log::error!("Failed to serialize the tile map: {error}"),
+        },
+        Err(error) => log::error!("Failed to import the Tiled map {path:?}: {error:#}"),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_pack(dir: &std::path::Path) -> Option<assets::LevelPack> {
+    match assets::LevelPack::load(dir) {
+        Ok(pack) => {
+            log::info!(
+                "Playing the level pack {} with {} levels",
+                pack.name,
+                pack.levels.len()
+            );
+            Some(pack)
+        }
+        Err(error) => {
+            log::error!("Failed to load the level pack from {dir:?}: {error:#}");
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn load_pack(dir: &std::path::Path) -> Option<assets::LevelPack> {
+    log::error!("Level packs are not supported on the web, ignoring {dir:?}");
+    None
+}
@@ -1,6 +1,6 @@
 use crate::{
     context::Context,
-    interop::{ClientConnection, ClientMessage, RoomInfo, ServerMessage},
+    interop::{ClientConnection, ClientMessage, RoomInfo, ServerError, ServerMessage},
     model::GameRole,
     render::{mask::MaskedStack, util::UtilRender},
     ui::{layout::AreaOps, *},
@@ -25,6 +25,10 @@ pub struct LobbyState {
     connection: ClientConnection,
     room_info: RoomInfo,
     selected_role: Option<GameRole>,
+    /// Last error reported by the server.
+    error: Option<ServerError>,
+    /// Last message from the server admin.
+    announcement: Option<String>,
 }
 
 pub struct LobbyUi {}
@@ -49,6 +53,8 @@ impl Lobby {
                 connection,
                 room_info,
                 selected_role: None,
+                error: None,
+                announcement: None,
             },
             test,
         }
@@ -56,9 +62,15 @@ impl Lobby {
 
     fn handle_server_message(&mut self, message: ServerMessage) {
         match message {
+            ServerMessage::Hello { .. } => {}
+            ServerMessage::Announcement(text) => {
+                log::info!("Announcement: {text}");
+                self.state.announcement = Some(text);
+            }
             ServerMessage::Ping => self.state.connection.send(ClientMessage::Pong),
             ServerMessage::Error(error) => {
                 log::error!("Error: {}", error);
+                self.state.error = Some(error);
             }
             ServerMessage::YourToken(token) => {
                 preferences::save("usertoken", &token);
@@ -69,6 +81,7 @@ impl Lobby {
             }
             ServerMessage::StartGame(game_role) => {
                 log::info!("Starting game as {:?}", game_role);
+                self.state.connection.enable_reconnect();
                 let state: Box<dyn geng::State> = match game_role {
                     GameRole::Dispatcher => Box::new(crate::game::GameDispatcher::new(
                         &self.context,
@@ -83,10 +96,12 @@ impl Lobby {
                 };
                 self.transition = Some(geng::state::Transition::Switch(state));
             }
-            ServerMessage::SyncDispatcherState(_)
+            ServerMessage::SyncFlags(_)
             | ServerMessage::SyncSolverState(_)
             | ServerMessage::SyncSolverPlayer(_)
-            | ServerMessage::GameCrash(_) => {}
+            | ServerMessage::GameCrash(_)
+            | ServerMessage::SessionResumed(_)
+            | ServerMessage::SpectateGame(_) => {}
         }
     }
 }
@@ -205,5 +220,25 @@ impl LobbyUi {
         if button.state.mouse_left.clicked {
             state.select_role(GameRole::Solver);
         }
+
+        if let Some(error) = &state.error {
+            let message = context.state.get_root_or(|| TextWidget::new(""));
+            message.text = error.message().into();
+            message.options.color = assets.palette.error;
+            message.update(
+                screen.align_aabb(vec2(1200.0, 80.0), vec2(0.5, 0.45)),
+                context,
+            );
+        }
+
+        if let Some(text) = &state.announcement {
+            let message = context.state.get_root_or(|| TextWidget::new(""));
+            message.text = text.clone().into();
+            message.options.color = assets.palette.text;
+            message.update(
+                screen.align_aabb(vec2(1200.0, 80.0), vec2(0.5, 0.38)),
+                context,
+            );
+        }
     }
 }
@@ -1,6 +1,6 @@
 use crate::{
     context::Context,
-    interop::{ClientMessage, ServerMessage},
+    interop::{ClientConnection, ClientMessage, ProtocolMismatch, ServerError, ServerMessage},
     menu::loading_screen::LoadingScreen,
     render::{mask::MaskedStack, util::UtilRender},
     ui::{layout::AreaOps, *},
@@ -18,16 +18,23 @@ pub struct MainMenu {
 
     connect: Option<String>,
     test: Option<usize>,
+    /// Set by the connection futures when the server refuses the request.
+    error: Rc<RefCell<Option<ServerError>>>,
     state: MainMenuState,
 }
 
 pub struct MainMenuState {
     action: Option<Action>,
+    /// Last error reported by the server, shown until the next action.
+    error: Option<ServerError>,
 }
 
 enum Action {
     CreateRoom,
     Join(String),
+    Spectate(String),
+    #[cfg(not(target_arch = "wasm32"))]
+    EditLevels,
 }
 
 pub struct MainMenuUi {}
@@ -44,7 +51,11 @@ impl MainMenu {
 
             connect,
             test,
-            state: MainMenuState { action: None },
+            error: Rc::new(RefCell::new(None)),
+            state: MainMenuState {
+                action: None,
+                error: None,
+            },
         }
     }
 }
@@ -53,6 +64,9 @@ impl geng::State for MainMenu {
     fn update(&mut self, delta_time: f64) {
         let delta_time = delta_time as f32;
         self.ui_context.update(delta_time);
+        if let Some(error) = self.error.borrow_mut().take() {
+            self.state.error = Some(error);
+        }
     }
 
     fn handle_event(&mut self, event: geng::Event) {
@@ -80,12 +94,17 @@ impl geng::State for MainMenu {
                     let context = self.context.clone();
                     let connect = self.connect.clone();
                     let test = self.test;
+                    let error = self.error.clone();
                     let future = async move {
-                        let mut connection =
-                            crate::interop::ClientConnection::connect(&connect.unwrap())
-                                .await
-                                .unwrap();
-                        connection.send(ClientMessage::CreateRoom);
+                        let mut connection = connect_to_server(connect, &error).await?;
+
+                        // Log in first so that the room is created with the saved token
+                        if let Some(token) = preferences::load("usertoken") {
+                            connection.send(ClientMessage::Login(token));
+                        }
+
+                        let pack = context.pack.as_ref().map(|pack| pack.name.clone());
+                        connection.send(ClientMessage::CreateRoom(pack));
                         let mut new_token = None;
                         let room_info = loop {
                             let message = connection.next().await.unwrap().unwrap();
@@ -93,8 +112,9 @@ impl geng::State for MainMenu {
                                 ServerMessage::Ping => connection.send(ClientMessage::Pong),
                                 ServerMessage::YourToken(token) => new_token = Some(token),
                                 ServerMessage::RoomJoined(room_info) => break room_info,
-                                ServerMessage::Error(error) => {
-                                    log::error!("Failed to create a room: {error}");
+                                ServerMessage::Error(server_error) => {
+                                    log::error!("Failed to create a room: {server_error}");
+                                    *error.borrow_mut() = Some(server_error);
                                     return None;
                                 }
                                 _ => {
@@ -104,12 +124,11 @@ impl geng::State for MainMenu {
                             }
                         };
 
-                        if let Some(token) = preferences::load("usertoken") {
-                            connection.send(ClientMessage::Login(token));
-                        } else if let Some(token) = new_token {
+                        if let Some(token) = new_token {
                             preferences::save("usertoken", &token);
                         }
 
+                        let context = room_context(&context, &room_info.pack, &error)?;
                         Some(
                             crate::menu::lobby::Lobby::new(&context, connection, room_info, test)
                                 .await,
@@ -127,11 +146,9 @@ impl geng::State for MainMenu {
                     let context = self.context.clone();
                     let connect = self.connect.clone();
                     let test = self.test;
+                    let error = self.error.clone();
                     let future = async move {
-                        let mut connection =
-                            crate::interop::ClientConnection::connect(&connect.unwrap())
-                                .await
-                                .unwrap();
+                        let mut connection = connect_to_server(connect, &error).await?;
 
                         if let Some(token) = preferences::load("usertoken") {
                             connection.send(ClientMessage::Login(token));
@@ -145,8 +162,9 @@ impl geng::State for MainMenu {
                                 ServerMessage::Ping => connection.send(ClientMessage::Pong),
                                 ServerMessage::YourToken(token) => new_token = Some(token),
                                 ServerMessage::RoomJoined(room_info) => break room_info,
-                                ServerMessage::Error(error) => {
-                                    log::error!("Failed to join the room: {error}");
+                                ServerMessage::Error(server_error) => {
+                                    log::error!("Failed to join the room: {server_error}");
+                                    *error.borrow_mut() = Some(server_error);
                                     return None;
                                 }
                                 _ => {
@@ -160,6 +178,7 @@ impl geng::State for MainMenu {
                             preferences::save("usertoken", &token);
                         }
 
+                        let context = room_context(&context, &room_info.pack, &error)?;
                         Some(
                             crate::menu::lobby::Lobby::new(&context, connection, room_info, test)
                                 .await,
@@ -172,6 +191,51 @@ impl geng::State for MainMenu {
                         future,
                     ))
                 }
+                Action::Spectate(code) => {
+                    log::info!("Spectating room {code}...");
+                    let context = self.context.clone();
+                    let connect = self.connect.clone();
+                    let error = self.error.clone();
+                    let future = async move {
+                        let mut connection = connect_to_server(connect, &error).await?;
+                        connection.send(ClientMessage::SpectateRoom(code));
+                        let room_info = loop {
+                            let message = connection.next().await.unwrap().unwrap();
+                            match message {
+                                ServerMessage::Ping => connection.send(ClientMessage::Pong),
+                                ServerMessage::RoomJoined(_) => {
+                                    log::info!("Waiting for the game to start...");
+                                }
+                                ServerMessage::SpectateGame(room_info) => break room_info,
+                                ServerMessage::Error(server_error) => {
+                                    log::error!("Failed to spectate the room: {server_error}");
+                                    *error.borrow_mut() = Some(server_error);
+                                    return None;
+                                }
+                                _ => {}
+                            }
+                        };
+
+                        let context = room_context(&context, &room_info.pack, &error)?;
+                        Some(crate::game::GameSpectator::new(
+                            &context, connection, room_info,
+                        ))
+                    }
+                    .boxed_local();
+                    Box::new(LoadingScreen::new(
+                        &self.context.geng,
+                        geng::EmptyLoadingScreen::new(&self.context.geng),
+                        future,
+                    ))
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                Action::EditLevels => match crate::editor::SolverEditor::new(&self.context) {
+                    Ok(editor) => Box::new(editor),
+                    Err(error) => {
+                        log::error!("Failed to open the level editor: {error:?}");
+                        return None;
+                    }
+                },
             };
             self.context.geng.window().stop_text_edit();
             return Some(geng::state::Transition::Push(state));
@@ -213,6 +277,60 @@ impl geng::State for MainMenu {
     }
 }
 
+async fn connect_to_server(
+    addr: Option<String>,
+    server_error: &RefCell<Option<ServerError>>,
+) -> Option<ClientConnection> {
+    let addr = addr.expect("no server address to connect to");
+    match ClientConnection::connect(&addr).await {
+        Ok(connection) => Some(connection),
+        Err(error) => {
+            log::error!("Failed to connect to the server: {error}");
+            if let Some(mismatch) = error.downcast_ref::<ProtocolMismatch>() {
+                *server_error.borrow_mut() = Some(ServerError::IncompatibleProtocol {
+                    protocol_version: mismatch.server_version.unwrap_or_default(),
+                    build: mismatch.server_build.clone().unwrap_or_default(),
+                });
+            }
+            None
+        }
+    }
+}
+
+/// Context playing the level pack of the room, `None` if it is not installed.
+fn room_context(
+    context: &Context,
+    pack: &Option<String>,
+    server_error: &RefCell<Option<ServerError>>,
+) -> Option<Context> {
+    let current = context.pack.as_ref().map(|pack| &pack.name);
+    if current == pack.as_ref() {
+        return Some(context.clone());
+    }
+    let Some(name) = pack else {
+        return Some(Context {
+            pack: None,
+            ..context.clone()
+        });
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    match crate::assets::LevelPack::find(name) {
+        Ok(pack) => {
+            log::info!("Loaded the level pack {name} of the room");
+            return Some(Context {
+                pack: Some(Rc::new(pack)),
+                ..context.clone()
+            });
+        }
+        Err(error) => log::error!("Failed to load the level pack {name}: {error:#}"),
+    }
+
+    log::error!("The room uses the level pack {name} that is not installed");
+    *server_error.borrow_mut() = Some(ServerError::UnknownLevelPack);
+    None
+}
+
 impl MainMenuUi {
     pub fn new() -> Self {
         Self {}
@@ -239,6 +357,17 @@ impl MainMenuUi {
             .get_root_or(|| IconWidget::new(atlas.menu()))
             .update(screen, context);
 
+        if let Some(error @ ServerError::IncompatibleProtocol { .. }) = &state.error {
+            let message = context.state.get_root_or(|| TextWidget::new(""));
+            message.text = error.message().into();
+            message.options.color = assets.palette.text;
+            message.update(
+                screen.extend_symmetric(vec2(-0.1, -0.4) * screen.size()),
+                context,
+            );
+            return;
+        }
+
         let mk_button = |pos: vec2<f32>, size| {
             Aabb2::point(screen.align_pos(vec2(pos.x, 1080.0 - pos.y) / vec2(1920.0, 1080.0)))
                 .extend_symmetric(size * screen_ratio / 2.0)
@@ -257,6 +386,7 @@ impl MainMenuUi {
         button.update(create, context);
         if button.state.mouse_left.clicked {
             state.action = Some(Action::CreateRoom);
+            state.error = None;
         }
 
         let mut join = mk_button(vec2(320.0, 620.0), vec2(483.0, 118.0));
@@ -281,7 +411,10 @@ impl MainMenuUi {
             .state
             .get_root_or(|| InputWidget::new("").max_len(4).uppercase());
         code_input.update(code, context);
-        code_input.name.options.color = assets.palette.text;
+        code_input.name.options.color = match state.error {
+            Some(ServerError::RoomNotFound) => assets.palette.error,
+            _ => assets.palette.text,
+        };
         context
             .state
             .get_root_or(|| IconWidget::new(atlas.code_background()))
@@ -294,6 +427,49 @@ impl MainMenuUi {
 
         if join_button.state.mouse_left.clicked {
             state.action = Some(Action::Join(code_input.raw.clone()));
+            state.error = None;
+        }
+
+        let mut spectate = mk_button(vec2(320.0, 787.0), vec2(483.0, 118.0));
+        let spectate_button = context
+            .state
+            .get_root_or(|| ButtonWidget::new(atlas.button_background()).with_text("Смотреть"));
+        spectate_button.text.options.color = assets.palette.text;
+        if spectate.contains(context.cursor.position) {
+            spectate = spectate.extend_symmetric(
+                vec2(atlas.button_background().size().as_f32().aspect(), 1.0) * 10.0,
+            );
+        }
+        spectate_button.update(spectate, context);
+        if spectate_button.state.mouse_left.clicked {
+            state.action = Some(Action::Spectate(code_input.raw.clone()));
+            state.error = None;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut edit = mk_button(vec2(1700.0, 80.0), vec2(362.0, 88.0));
+            let edit_button = context
+                .state
+                .get_root_or(|| ButtonWidget::new(atlas.button_background()).with_text("Редактор"));
+            edit_button.text.options.color = assets.palette.text;
+            if edit.contains(context.cursor.position) {
+                edit = edit.extend_symmetric(
+                    vec2(atlas.button_background().size().as_f32().aspect(), 1.0) * 10.0,
+                );
+            }
+            edit_button.update(edit, context);
+            if edit_button.state.mouse_left.clicked {
+                state.action = Some(Action::EditLevels);
+                state.error = None;
+            }
+        }
+
+        if let Some(error) = &state.error {
+            let message = context.state.get_root_or(|| TextWidget::new(""));
+            message.text = error.message().into();
+            message.options.color = assets.palette.error;
+            message.update(mk_button(vec2(560.0, 920.0), vec2(1000.0, 80.0)), context);
         }
     }
 }
@@ -0,0 +1,31 @@
+use super::*;
+
+use crate::assets::DispatcherItem;
+
+/// Intents sent by the solver, validated by the server against the current level.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SolverAction {
+    /// The player has reached the transition area of the given level.
+    ExitLevel(usize),
+    /// The player has entered the checkpoint of the given level.
+    ReachCheckpoint { level: usize, checkpoint: usize },
+    /// A code has been entered into the bubble code panel.
+    EnterBubbleCode(Vec<usize>),
+    /// The fish and the cinder block have exploded.
+    Explosion,
+    /// Grandpa has drilled through the floor.
+    GrandpaDrilled,
+}
+
+/// Intents sent by the dispatcher, validated by the server against the current level.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DispatcherAction {
+    ToggleDoorSign,
+    ToggleButtonStation,
+    /// A code has been entered on the monitor login screen.
+    EnterLoginCode(Vec<usize>),
+    /// A button on the button station has finished its press animation.
+    PressButton(DispatcherItem),
+    /// The solver has been clicked on the monitor.
+    PopSolver,
+}
@@ -0,0 +1,146 @@
+use super::*;
+
+use crate::assets::{SolverLevel, SolverRules};
+
+use geng::prelude::rand::{SeedableRng, rngs::StdRng};
+use std::time::{Duration, Instant};
+
+/// Size of the square tiles the benchmark level is built from.
+const TILE_SIZE: f32 = 0.25;
+/// Number of projectiles kept flying through the level.
+const PROJECTILES: usize = 200;
+/// Number of random colliders checked against the level geometry.
+const PROBES: usize = 10_000;
+/// Number of simulated steps.
+const STEPS: usize = 10_000;
+
+/// Simulate the solver on a level made of hundreds of tiles and print the timings.
+/// Run with `cargo test --release -- --ignored --nocapture collisions`.
+#[test]
+#[ignore = "benchmark"]
+fn collisions() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("assets")
+        .join("solver")
+        .join("rules.ron");
+    let rules: SolverRules = ron::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let level = dense_level();
+    println!(
+        "Simulating {STEPS} steps on a level with {} tiles and {PROJECTILES} projectiles...",
+        level.geometry.len()
+    );
+    let mut world = SolverWorld::new(rules, SolverState::new()).with_seed(0);
+    world.load_level(Some(level));
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let mut step_times = Vec::with_capacity(STEPS);
+    for tick in 0..STEPS {
+        while world.projectiles.len() < PROJECTILES {
+            let position = vec2(rng.gen_range(1.5..14.5), rng.gen_range(2.5..8.0));
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            world.projectiles.push(Projectile {
+                collider: Collider::circle(position.map(r32), r32(0.2)),
+                velocity: vec2(angle.cos(), angle.sin()).map(r32) * r32(5.0),
+            });
+        }
+        // Run back and forth, jumping over the tiles
+        let direction = if tick / 180 % 2 == 0 { 1.0 } else { -1.0 };
+        let control = PlayerControl {
+            jump: tick % 45 == 0,
+            hold_jump: tick % 45 < 10,
+            move_dir: vec2(r32(direction), FCoord::ZERO),
+            pickup: false,
+        };
+        let start = Instant::now();
+        world.step(control);
+        step_times.push(start.elapsed());
+    }
+
+    step_times.sort();
+    let total: Duration = step_times.iter().sum();
+    let percentile = |p: f64| {
+        let i = ((step_times.len() as f64 * p) as usize).min(step_times.len().saturating_sub(1));
+        step_times.get(i).copied().unwrap_or_default()
+    };
+    println!("Total: {:.2}ms", total.as_secs_f64() * 1000.0);
+    println!(
+        "Step: mean {:.3}ms, median {:.3}ms, 99th percentile {:.3}ms",
+        total.as_secs_f64() * 1000.0 / STEPS as f64,
+        percentile(0.5).as_secs_f64() * 1000.0,
+        percentile(0.99).as_secs_f64() * 1000.0,
+    );
+
+    // Same queries with and without the broad phase
+    let probes: Vec<CachedCollider> = (0..PROBES)
+        .map(|_| {
+            let position = vec2(rng.gen_range(0.0..16.0), rng.gen_range(0.0..9.0));
+            Collider::circle(position.map(r32), r32(0.3)).cached()
+        })
+        .collect();
+    let start = Instant::now();
+    let grid_hits: usize = probes
+        .iter()
+        .map(|probe| world.level_static_colliders.collisions(probe).count())
+        .sum();
+    let grid_time = start.elapsed();
+    let start = Instant::now();
+    let all_hits: usize = probes
+        .iter()
+        .map(|probe| {
+            world
+                .level_static_colliders
+                .iter()
+                .filter(|collider| probe.collider().collide(collider).is_some())
+                .count()
+        })
+        .sum();
+    let all_time = start.elapsed();
+    println!(
+        "{PROBES} probes: grid {:.2}ms ({grid_hits} hits), every collider {:.2}ms ({all_hits} hits)",
+        grid_time.as_secs_f64() * 1000.0,
+        all_time.as_secs_f64() * 1000.0,
+    );
+    assert_eq!(grid_hits, all_hits, "the grid has missed some collisions");
+}
+
+/// Bumpy ground and floating blocks, all made of small tiles.
+fn dense_level() -> SolverLevel {
+    let columns = (14.0 / TILE_SIZE) as usize;
+    let tile = |x: usize, y: f32| {
+        let min = vec2(1.0 + x as f32 * TILE_SIZE, y);
+        Collider::aabb(
+            Aabb2::point(min)
+                .extend_positive(vec2::splat(TILE_SIZE))
+                .map(r32),
+        )
+    };
+    let mut geometry = Vec::new();
+    for x in 0..columns {
+        let height = 1 + ((x as f32 * 0.4).sin() * 1.5 + 1.5).round() as usize;
+        geometry.extend((0..height).map(|y| tile(x, 1.0 + y as f32 * TILE_SIZE)));
+        if x / 4 % 2 == 0 {
+            for y in [4.0, 6.5] {
+                geometry.extend((0..2).map(|i| tile(x, y + i as f32 * TILE_SIZE)));
+            }
+        }
+    }
+
+    SolverLevel {
+        door_entrance: false,
+        door_exit: false,
+        spawnpoint: vec2(2.0, 3.0).map(r32),
+        transition: Aabb2::point(vec2(20.0, 20.0)).extend_uniform(0.5).map(r32),
+        platforms: Vec::new(),
+        geometry,
+        tiles: None,
+        hazards: Vec::new(),
+        items: Vec::new(),
+        decorations: Vec::new(),
+        hide_bounds: false,
+        drillable_floor: false,
+        stream_player: false,
+        unlocks_memes: false,
+        triggers: Vec::new(),
+        checkpoints: Vec::new(),
+    }
+}
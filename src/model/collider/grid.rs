@@ -0,0 +1,148 @@
+use super::*;
+
+/// Size of the cells the static colliders are sorted into.
+const CELL_SIZE: f32 = 1.0;
+
+/// Colliders that do not move, sorted into a uniform grid so that only the nearby ones are checked.
+#[derive(Clone, Default)]
+pub struct StaticColliders {
+    colliders: Vec<CachedCollider>,
+    cells: HashMap<vec2<i32>, Vec<usize>>,
+}
+
+impl StaticColliders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.colliders.clear();
+        self.cells.clear();
+    }
+
+    pub fn push(&mut self, collider: Collider) {
+        self.insert(CachedCollider::new(collider));
+    }
+
+    pub fn extend(&mut self, colliders: impl IntoIterator<Item = Collider>) {
+        for collider in colliders {
+            self.push(collider);
+        }
+    }
+
+    fn insert(&mut self, collider: CachedCollider) {
+        let index = self.colliders.len();
+        for cell in cells(collider.compute_aabb()) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+        self.colliders.push(collider);
+    }
+
+    /// Keep only the colliders matching the predicate.
+    pub fn retain(&mut self, mut f: impl FnMut(&Collider) -> bool) {
+        let colliders = std::mem::take(&mut self.colliders);
+        self.cells.clear();
+        for collider in colliders {
+            if f(collider.collider()) {
+                self.insert(collider);
+            }
+        }
+    }
+
+    /// Remove the collider, replacing it with the last one.
+    pub fn swap_remove(&mut self, index: usize) -> Collider {
+        let removed = self.colliders.swap_remove(index);
+        let last = self.colliders.len();
+        for cell in cells(removed.compute_aabb()) {
+            if let Some(indices) = self.cells.get_mut(&cell) {
+                indices.retain(|&i| i != index);
+            }
+        }
+        // The last collider has taken the place of the removed one
+        if index < last {
+            for cell in cells(self.colliders[index].compute_aabb()) {
+                if let Some(indices) = self.cells.get_mut(&cell) {
+                    for i in indices.iter_mut().filter(|i| **i == last) {
+                        *i = index;
+                    }
+                }
+            }
+        }
+        removed.collider
+    }
+
+    /// Remove the collider equal to the given one, if there is any.
+    pub fn remove(&mut self, collider: &Collider) -> Option<Collider> {
+        let index = self.iter().position(|other| other == collider)?;
+        Some(self.swap_remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Collider> {
+        self.colliders.iter().map(|collider| collider.collider())
+    }
+
+    /// Colliders whose bounding boxes might intersect the area.
+    pub fn near(&self, area: Aabb2<FCoord>) -> impl Iterator<Item = &CachedCollider> {
+        let mut indices: Vec<usize> = cells(area)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices.into_iter().map(move |i| &self.colliders[i])
+    }
+
+    /// Check whether the collider intersects any of the static ones.
+    pub fn check(&self, collider: &CachedCollider) -> bool {
+        self.near(collider.compute_aabb())
+            .any(|other| collider.check(other))
+    }
+
+    /// Collisions of the collider with the static ones.
+    pub fn collisions<'a>(
+        &'a self,
+        collider: &'a CachedCollider,
+    ) -> impl Iterator<Item = Collision> + 'a {
+        self.near(collider.compute_aabb())
+            .filter_map(move |other| collider.collide(other))
+    }
+}
+
+/// Cells of the grid covering the area.
+fn cells(area: Aabb2<FCoord>) -> impl Iterator<Item = vec2<i32>> {
+    let cell = |x: FCoord| (x.as_f32() / CELL_SIZE).floor() as i32;
+    let (min_x, min_y) = (cell(area.min.x), cell(area.min.y));
+    let (max_x, max_y) = (cell(area.max.x), cell(area.max.y));
+    (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| vec2(x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32) -> Collider {
+        Collider::aabb(
+            Aabb2::point(vec2(x, 0.0))
+                .extend_positive(vec2(0.5, 0.5))
+                .map(r32),
+        )
+    }
+
+    #[test]
+    fn swap_remove_keeps_the_grid_in_sync() {
+        let mut colliders = StaticColliders::new();
+        colliders.extend([square(0.0), square(5.0), square(10.0)]);
+        assert_eq!(colliders.swap_remove(0), square(0.0));
+
+        let near = |colliders: &StaticColliders, x: f32| -> Vec<Collider> {
+            colliders
+                .near(square(x).compute_aabb())
+                .map(|collider| collider.collider().clone())
+                .collect()
+        };
+        assert!(near(&colliders, 0.0).is_empty());
+        assert_eq!(near(&colliders, 5.0), [square(5.0)]);
+        assert_eq!(near(&colliders, 10.0), [square(10.0)]);
+    }
+}
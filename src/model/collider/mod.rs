@@ -1,6 +1,7 @@
+mod grid;
 mod shape;
 
-pub use self::shape::*;
+pub use self::{grid::*, shape::*};
 
 use super::*;
 
@@ -86,6 +87,16 @@ impl Collider {
         mat3::translate(self.position) * mat3::rotate(self.rotation)
     }
 
+    /// Outline of the shape in world coordinates, see [`Shape::outline`].
+    pub fn outline(&self) -> Vec<vec2<FCoord>> {
+        let transform = self.transform_mat();
+        self.shape
+            .outline()
+            .into_iter()
+            .map(|point| (transform * point.extend(FCoord::ONE)).into_2d())
+            .collect()
+    }
+
     pub fn compute_aabb(&self) -> Aabb2<FCoord> {
         let (iso, shape) = self.to_parry();
         let parry2d::bounding_volume::Aabb { mins, maxs } = shape.compute_aabb(&iso);
@@ -117,42 +128,109 @@ impl Collider {
 
     /// Check whether two colliders are intersecting.
     pub fn check(&self, other: &Self) -> bool {
-        let delta = (other.position - self.position).as_f32();
+        check_shapes(
+            self,
+            &*self.shape.to_parry(),
+            other,
+            &*other.shape.to_parry(),
+        )
+    }
 
-        let self_angle = self.rotation.as_radians().as_f32();
-        let self_iso = parry2d::math::Isometry::rotation(self_angle);
-        let self_shape = self.shape.to_parry();
+    /// Return the collision info if the two colliders are intersecting.
+    pub fn collide(&self, other: &Self) -> Option<Collision> {
+        collide_shapes(
+            self,
+            &*self.shape.to_parry(),
+            other,
+            &*other.shape.to_parry(),
+        )
+    }
 
-        let other_angle = other.rotation.as_radians().as_f32();
-        let other_iso =
-            parry2d::math::Isometry::new(parry2d::na::Vector2::new(delta.x, delta.y), other_angle);
-        let other_shape = other.shape.to_parry();
+    /// Build the parry shape once to check the collider against many others.
+    pub fn cached(&self) -> CachedCollider {
+        CachedCollider::new(self.clone())
+    }
+}
 
-        parry2d::query::intersection_test(&self_iso, &*self_shape, &other_iso, &*other_shape)
-            .unwrap()
+/// Collider with its parry shape built in advance.
+#[derive(Clone)]
+pub struct CachedCollider {
+    collider: Collider,
+    shape: Arc<dyn parry2d::shape::Shape>,
+}
+
+impl CachedCollider {
+    pub fn new(collider: Collider) -> Self {
+        Self {
+            shape: collider.shape.to_parry().into(),
+            collider,
+        }
+    }
+
+    pub fn collider(&self) -> &Collider {
+        &self.collider
+    }
+
+    /// Move the collider, which keeps the shape valid.
+    pub fn set_position(&mut self, position: vec2<FCoord>) {
+        self.collider.position = position;
+    }
+
+    pub fn compute_aabb(&self) -> Aabb2<FCoord> {
+        let parry2d::bounding_volume::Aabb { mins, maxs } =
+            self.shape.compute_aabb(&self.collider.get_iso());
+        Aabb2 {
+            min: vec2(mins.x, mins.y).as_r32(),
+            max: vec2(maxs.x, maxs.y).as_r32(),
+        }
+    }
+
+    /// Check whether two colliders are intersecting.
+    pub fn check(&self, other: &Self) -> bool {
+        check_shapes(&self.collider, &*self.shape, &other.collider, &*other.shape)
     }
 
     /// Return the collision info if the two colliders are intersecting.
     pub fn collide(&self, other: &Self) -> Option<Collision> {
-        let delta = (other.position - self.position).as_f32();
-
-        let self_angle = self.rotation.as_radians().as_f32();
-        let self_iso = parry2d::math::Isometry::rotation(self_angle);
-        let self_shape = self.shape.to_parry();
-
-        let other_angle = other.rotation.as_radians().as_f32();
-        let other_iso =
-            parry2d::math::Isometry::new(parry2d::na::Vector2::new(delta.x, delta.y), other_angle);
-        let other_shape = other.shape.to_parry();
-
-        let prediction = 0.0;
-        parry2d::query::contact(
-            &self_iso,
-            &*self_shape,
-            &other_iso,
-            &*other_shape,
-            prediction,
-        )
+        collide_shapes(&self.collider, &*self.shape, &other.collider, &*other.shape)
+    }
+}
+
+/// Positions of the two colliders relative to the first one, to keep the precision far from the origin.
+fn relative_isos(
+    a: &Collider,
+    b: &Collider,
+) -> (parry2d::math::Isometry<f32>, parry2d::math::Isometry<f32>) {
+    let delta = (b.position - a.position).as_f32();
+
+    let a_angle = a.rotation.as_radians().as_f32();
+    let a_iso = parry2d::math::Isometry::rotation(a_angle);
+
+    let b_angle = b.rotation.as_radians().as_f32();
+    let b_iso = parry2d::math::Isometry::new(parry2d::na::Vector2::new(delta.x, delta.y), b_angle);
+
+    (a_iso, b_iso)
+}
+
+fn check_shapes(
+    a: &Collider,
+    a_shape: &dyn parry2d::shape::Shape,
+    b: &Collider,
+    b_shape: &dyn parry2d::shape::Shape,
+) -> bool {
+    let (a_iso, b_iso) = relative_isos(a, b);
+    parry2d::query::intersection_test(&a_iso, a_shape, &b_iso, b_shape).unwrap()
+}
+
+fn collide_shapes(
+    a: &Collider,
+    a_shape: &dyn parry2d::shape::Shape,
+    b: &Collider,
+    b_shape: &dyn parry2d::shape::Shape,
+) -> Option<Collision> {
+    let (a_iso, b_iso) = relative_isos(a, b);
+    let prediction = 0.0;
+    parry2d::query::contact(&a_iso, a_shape, &b_iso, b_shape, prediction)
         .unwrap()
         .map(|contact| {
             let normal = contact.normal1.into_inner();
@@ -163,5 +241,4 @@ impl Collider {
                 penetration: FCoord::new(-contact.dist),
             }
         })
-    }
 }
@@ -2,10 +2,38 @@ use geng_utils::conversions::Vec2RealConversions;
 
 use super::*;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+/// Number of points approximating round parts of the shapes in their outlines.
+const ROUND_RESOLUTION: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Shape {
-    Circle { radius: FCoord },
-    Rectangle { width: FCoord, height: FCoord },
+    Circle {
+        radius: FCoord,
+    },
+    Rectangle {
+        width: FCoord,
+        height: FCoord,
+    },
+    /// Convex hull of the points, relative to the collider's position.
+    Polygon {
+        points: Vec<vec2<FCoord>>,
+    },
+    /// Segment with rounded ends, relative to the collider's position.
+    Capsule {
+        start: vec2<FCoord>,
+        end: vec2<FCoord>,
+        radius: FCoord,
+    },
+    /// Thin line, relative to the collider's position.
+    Segment {
+        start: vec2<FCoord>,
+        end: vec2<FCoord>,
+    },
+}
+
+fn parry_point(point: vec2<FCoord>) -> parry2d::math::Point<f32> {
+    let vec2(x, y) = point.as_f32();
+    parry2d::math::Point::new(x, y)
 }
 
 impl Shape {
@@ -20,14 +48,14 @@ impl Shape {
         }
     }
 
-    pub fn to_parry(self) -> Box<dyn parry2d::shape::Shape> {
+    pub fn to_parry(&self) -> Box<dyn parry2d::shape::Shape> {
         match self {
             Shape::Circle { radius } => Box::new(parry2d::shape::Ball::new(radius.as_f32())),
             Shape::Rectangle { width, height } => {
-                if width == R32::ZERO || height == R32::ZERO {
+                if *width == R32::ZERO || *height == R32::ZERO {
                     return Box::new(parry2d::shape::Ball::new(0.0));
                 }
-                let aabb = Aabb2::ZERO.extend_symmetric(vec2(width, height).as_f32() / 2.0);
+                let aabb = Aabb2::ZERO.extend_symmetric(vec2(*width, *height).as_f32() / 2.0);
                 let points = aabb.corners().map(|p| {
                     let vec2(x, y) = p;
                     parry2d::math::Point::new(x, y)
@@ -37,18 +65,91 @@ impl Shape {
                     None => Box::new(parry2d::shape::Ball::new(0.0)),
                 }
             }
+            Shape::Polygon { points } => {
+                let points: Vec<_> = points.iter().copied().map(parry_point).collect();
+                match parry2d::shape::ConvexPolygon::from_convex_hull(&points) {
+                    Some(shape) => Box::new(shape),
+                    None => Box::new(parry2d::shape::Ball::new(0.0)),
+                }
+            }
+            Shape::Capsule { start, end, radius } => Box::new(parry2d::shape::Capsule::new(
+                parry_point(*start),
+                parry_point(*end),
+                radius.as_f32(),
+            )),
+            Shape::Segment { start, end } => Box::new(parry2d::shape::Segment::new(
+                parry_point(*start),
+                parry_point(*end),
+            )),
         }
     }
 
-    pub fn scaled(self, scale: FCoord) -> Self {
+    pub fn scaled(&self, scale: FCoord) -> Self {
         match self {
             Shape::Circle { radius } => Shape::Circle {
-                radius: radius * scale,
+                radius: *radius * scale,
             },
             Shape::Rectangle { width, height } => Shape::Rectangle {
-                width: width * scale,
-                height: height * scale,
+                width: *width * scale,
+                height: *height * scale,
+            },
+            Shape::Polygon { points } => Shape::Polygon {
+                points: points.iter().map(|&point| point * scale).collect(),
             },
+            Shape::Capsule { start, end, radius } => Shape::Capsule {
+                start: *start * scale,
+                end: *end * scale,
+                radius: *radius * scale,
+            },
+            Shape::Segment { start, end } => Shape::Segment {
+                start: *start * scale,
+                end: *end * scale,
+            },
+        }
+    }
+
+    /// Points along the border of the shape in counter-clockwise order, relative to its position.
+    /// Round parts are approximated, segments are left as their two ends.
+    pub fn outline(&self) -> Vec<vec2<FCoord>> {
+        let arc = |center: vec2<FCoord>, radius: FCoord, from: f32, points: usize| {
+            (0..=points).map(move |i| {
+                let angle = from + std::f32::consts::PI * i as f32 / points as f32;
+                center + vec2(angle.cos(), angle.sin()).map(r32) * radius
+            })
+        };
+        match self {
+            Shape::Circle { radius } => (0..ROUND_RESOLUTION)
+                .map(|i| {
+                    let angle = std::f32::consts::TAU * i as f32 / ROUND_RESOLUTION as f32;
+                    vec2(angle.cos(), angle.sin()).map(r32) * *radius
+                })
+                .collect(),
+            Shape::Rectangle { width, height } => Aabb2::ZERO
+                .extend_symmetric(vec2(*width, *height) / r32(2.0))
+                .corners()
+                .to_vec(),
+            Shape::Polygon { points } => {
+                let points: Vec<_> = points.iter().copied().map(parry_point).collect();
+                parry2d::transformation::convex_hull(&points)
+                    .into_iter()
+                    .map(|point| vec2(point.x, point.y).map(r32))
+                    .collect()
+            }
+            Shape::Capsule { start, end, radius } => {
+                let direction = (*end - *start).as_f32();
+                let angle = direction.y.atan2(direction.x);
+                let half = ROUND_RESOLUTION / 2;
+                // Half circles around the ends, each facing away from the other one
+                arc(*end, *radius, angle - std::f32::consts::FRAC_PI_2, half)
+                    .chain(arc(
+                        *start,
+                        *radius,
+                        angle + std::f32::consts::FRAC_PI_2,
+                        half,
+                    ))
+                    .collect()
+            }
+            Shape::Segment { start, end } => vec![*start, *end],
         }
     }
 }
@@ -0,0 +1,141 @@
+use super::*;
+
+use std::{collections::BTreeMap, marker::PhantomData};
+
+/// Value of a puzzle flag.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FlagValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+}
+
+/// Named puzzle flags shared by both roles and synchronized by the server.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flags {
+    values: BTreeMap<String, FlagValue>,
+}
+
+/// Name of a flag together with the type of its value.
+pub struct Flag<T> {
+    pub name: &'static str,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Flag<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Flag<T> {}
+
+impl<T> Flag<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl Flag<bool> {
+    pub const TRASHCAN_EVIL: Self = Self::new("trashcan_evil");
+    pub const SOLVED_BUBBLE_CODE: Self = Self::new("solved_bubble_code");
+    /// The dispatcher has clicked the solver on the monitor.
+    pub const POPPED: Self = Self::new("popped");
+    pub const BUTTON_STATION_OPEN: Self = Self::new("button_station_open");
+    pub const DOOR_SIGN_OPEN: Self = Self::new("door_sign_open");
+    pub const MONITOR_UNLOCKED: Self = Self::new("monitor_unlocked");
+}
+
+/// Types that can be stored in a flag.
+pub trait FlagType: Sized + Default {
+    fn from_value(value: &FlagValue) -> Option<Self>;
+    fn into_value(self) -> FlagValue;
+}
+
+impl FlagType for bool {
+    fn from_value(value: &FlagValue) -> Option<Self> {
+        match value {
+            FlagValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> FlagValue {
+        FlagValue::Bool(self)
+    }
+}
+
+impl FlagType for i64 {
+    fn from_value(value: &FlagValue) -> Option<Self> {
+        match value {
+            FlagValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> FlagValue {
+        FlagValue::Int(self)
+    }
+}
+
+impl FlagType for String {
+    fn from_value(value: &FlagValue) -> Option<Self> {
+        match value {
+            FlagValue::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> FlagValue {
+        FlagValue::String(self)
+    }
+}
+
+impl Flags {
+    /// Flags at the start of a game.
+    pub fn new() -> Self {
+        let mut flags = Self::default();
+        flags.set(Flag::TRASHCAN_EVIL, true);
+        flags
+    }
+
+    /// Value of the flag, or the default of its type if it is unset or has another type.
+    pub fn get<T: FlagType>(&self, flag: Flag<T>) -> T {
+        self.values
+            .get(flag.name)
+            .and_then(T::from_value)
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if the value has changed.
+    pub fn set<T: FlagType>(&mut self, flag: Flag<T>, value: T) -> bool {
+        self.set_value(flag.name, value.into_value())
+    }
+
+    pub fn toggle(&mut self, flag: Flag<bool>) {
+        let value = !self.get(flag);
+        self.set(flag, value);
+    }
+
+    /// Untyped access for the level scripts.
+    pub fn value(&self, name: &str) -> Option<&FlagValue> {
+        self.values.get(name)
+    }
+
+    /// Returns `true` if the value has changed.
+    pub fn set_value(&mut self, name: &str, value: FlagValue) -> bool {
+        if self.values.get(name) == Some(&value) {
+            return false;
+        }
+        self.values.insert(name.to_owned(), value);
+        true
+    }
+
+    /// Whether the flag is set to `true`.
+    pub fn is_set(&self, name: &str) -> bool {
+        self.value(name) == Some(&FlagValue::Bool(true))
+    }
+}
@@ -1,6 +1,13 @@
+#[cfg(test)]
+mod bench;
+
+mod action;
 mod collider;
+mod flags;
+mod trigger;
+mod world;
 
-pub use self::collider::*;
+pub use self::{action::*, collider::*, flags::*, trigger::*, world::*};
 
 use geng::prelude::*;
 
@@ -13,20 +20,13 @@ pub enum GameRole {
     Solver,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DispatcherState {
-    pub button_station_open: bool,
-    pub door_sign_open: bool,
-    pub monitor_unlocked: bool,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SolverState {
     pub current_level: usize,
     pub levels_completed: usize,
-    pub trashcan_evil: bool,
-    pub solved_bubble_code: bool,
-    pub popped: bool,
+    /// Last checkpoint reached in the current level.
+    #[serde(default)]
+    pub checkpoint: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,24 +55,12 @@ pub enum PlayerAnimationState {
     Jumping,
 }
 
-impl DispatcherState {
-    pub fn new() -> Self {
-        Self {
-            button_station_open: false,
-            door_sign_open: false,
-            monitor_unlocked: false,
-        }
-    }
-}
-
 impl SolverState {
     pub fn new() -> Self {
         Self {
             current_level: 0,
             levels_completed: 0,
-            trashcan_evil: true,
-            solved_bubble_code: false,
-            popped: false,
+            checkpoint: None,
         }
     }
 
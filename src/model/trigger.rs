@@ -0,0 +1,96 @@
+use super::*;
+
+use crate::assets::{DispatcherItem, LevelMusic, LevelSprite};
+
+/// Reaction of a level to something happening in it, declared in the level file.
+/// Progress and flags are applied by the server, walls and music by the solver's client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelTrigger {
+    pub on: TriggerEvent,
+    /// All of the conditions have to hold for the trigger to fire.
+    #[serde(default)]
+    pub when: Vec<TriggerCondition>,
+    pub then: Vec<TriggerAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TriggerEvent {
+    /// The level has been loaded.
+    Enter,
+    /// The fish and the cinder block have exploded.
+    Explosion,
+    /// Grandpa has drilled through the floor.
+    GrandpaDrilled,
+    /// The given code has been entered into the bubble code panel.
+    BubbleCode(Vec<usize>),
+    /// The dispatcher has pressed the given button.
+    ButtonPressed(DispatcherItem),
+    /// The dispatcher has pressed this many buttons since the level started.
+    ButtonPresses(usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TriggerCondition {
+    /// The flag is set to `true`.
+    Flag(String),
+    NotFlag(String),
+    Equals(String, FlagValue),
+    LevelsCompleted(usize),
+    ExitOpen,
+    ExitClosed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TriggerAction {
+    /// Let the solver through the exit door of the current level.
+    OpenExit,
+    /// Set the flag to `true`.
+    SetFlag(String),
+    ClearFlag(String),
+    SetValue(String, FlagValue),
+    /// Restart the solver's level with the given message.
+    CrashSolver(String),
+    PlayMusic(LevelMusic),
+    SpawnWall {
+        position: vec2<FCoord>,
+        size: vec2<FCoord>,
+        /// Counter-clockwise rotation in degrees.
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default)]
+        sprite: Option<LevelSprite>,
+        /// Remove the wall once the flag is set.
+        #[serde(default)]
+        until: Option<String>,
+    },
+    /// Fill an area with bubbles, starting from its bottom left corner.
+    SpawnBubbles {
+        origin: vec2<FCoord>,
+        columns: usize,
+        rows: usize,
+    },
+}
+
+impl TriggerCondition {
+    pub fn holds(&self, solver: &SolverState, flags: &Flags) -> bool {
+        match self {
+            Self::Flag(name) => flags.is_set(name),
+            Self::NotFlag(name) => !flags.is_set(name),
+            Self::Equals(name, value) => flags.value(name) == Some(value),
+            Self::LevelsCompleted(levels) => solver.levels_completed == *levels,
+            Self::ExitOpen => solver.is_exit_open(),
+            Self::ExitClosed => !solver.is_exit_open(),
+        }
+    }
+}
+
+impl LevelTrigger {
+    /// Whether the trigger fires on the event in the given state.
+    pub fn fires(&self, event: &TriggerEvent, solver: &SolverState, flags: &Flags) -> bool {
+        self.on == *event
+            && self
+                .when
+                .iter()
+                .all(|condition| condition.holds(solver, flags))
+    }
+}
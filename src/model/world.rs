@@ -0,0 +1,1257 @@
+use super::*;
+
+use crate::assets::{
+    LevelMusic, LevelSprite, Platform, SolverItem, SolverItemKind, SolverLevel, SolverRules,
+};
+
+use geng::prelude::rand::{SeedableRng, rngs::StdRng};
+
+pub const LEVEL_SIZE: vec2<f32> = vec2(16.0, 9.0);
+
+/// Aspect ratio of the platform sprite, which defines the thickness of the platforms.
+pub const PLATFORM_ASPECT: f32 = 165.0 / 31.0;
+/// Steepest slope in degrees the player can stand on without sliding down.
+const MAX_SLOPE_DEGREES: f32 = 50.0;
+/// Most steps simulated in a single frame, so that a long freeze does not stall the game.
+const MAX_STEPS_PER_FRAME: usize = 10;
+
+/// Simulation of the solver's level, independent of the rendering and the network.
+pub struct SolverWorld {
+    pub rules: SolverRules,
+    /// `None` past the last level.
+    pub level: Option<SolverLevel>,
+    pub state: SolverState,
+    /// Last known puzzle flags, checked by the level triggers.
+    pub flags: Flags,
+    pub platform_aspect: FCoord,
+    rng: StdRng,
+    control: PlayerControl,
+    events: Vec<SolverEvent>,
+    /// Frame time that has not been simulated yet.
+    accumulator: FTime,
+    /// Number of steps simulated so far.
+    pub tick: u64,
+    /// Whether the exit of the current level has already been requested.
+    requested_exit: bool,
+
+    pub time: FTime,
+    pub player: Player,
+    /// Position of the player before the last step, to interpolate the rendering.
+    previous_position: vec2<FCoord>,
+    pub level_static_colliders: StaticColliders,
+    /// Floor of the level, also present in the static colliders.
+    floor: Collider,
+    /// Walls spawned by the level triggers, also present in the static colliders.
+    pub walls: Vec<Wall>,
+    pub door_entrance: Collider,
+    pub door_exit: Collider,
+    pub platforms: Vec<LevelPlatform>,
+    pub bubble_balls: Vec<(Collider, usize)>,
+    pub items: Vec<SolverItem>,
+    pub picked_up_item: Option<SolverItem>,
+    pub explosion: Option<(vec2<FCoord>, FTime)>,
+    pub grandson_spin: Option<Angle<FCoord>>,
+    pub grandpa_drill: Option<FTime>,
+    pub interact_item: Option<usize>,
+    pub projectiles: Vec<Projectile>,
+    fish_cooldown: FTime,
+}
+
+pub struct Wall {
+    pub collider: Collider,
+    pub sprite: Option<LevelSprite>,
+    pub until: Option<String>,
+}
+
+/// Platform of the current level, moving and crumbling as the player uses it.
+pub struct LevelPlatform {
+    pub platform: Platform,
+    pub collider: Collider,
+    /// Distance travelled along the path.
+    travelled: FCoord,
+    /// Time since the player has stepped onto the crumbling platform.
+    pub crumbling: Option<FTime>,
+}
+
+pub struct Projectile {
+    pub collider: Collider,
+    pub velocity: vec2<FCoord>,
+}
+
+/// Input of the player for a single update.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerControl {
+    pub jump: bool,
+    pub hold_jump: bool,
+    pub move_dir: vec2<FCoord>,
+    pub pickup: bool,
+}
+
+/// Outcome of an update that the game has to react to.
+#[derive(Debug, Clone)]
+pub enum SolverEvent {
+    /// Progress to be validated by the server.
+    Action(SolverAction),
+    /// The level has been restarted.
+    Crash(String),
+    BubblesPopped,
+    PlayMusic(LevelMusic),
+}
+
+impl Default for PlayerControl {
+    fn default() -> Self {
+        Self {
+            jump: false,
+            hold_jump: false,
+            move_dir: vec2::ZERO,
+            pickup: false,
+        }
+    }
+}
+
+impl SolverWorld {
+    /// Create a world without a level, to be loaded with [`Self::load_level`] once it is set up.
+    pub fn new(rules: SolverRules, state: SolverState) -> Self {
+        Self {
+            rules,
+            level: None,
+            state,
+            flags: Flags::new(),
+            platform_aspect: r32(PLATFORM_ASPECT),
+            rng: StdRng::from_entropy(),
+            control: PlayerControl::default(),
+            events: Vec::new(),
+            accumulator: FTime::ZERO,
+            tick: 0,
+            requested_exit: false,
+
+            time: FTime::ZERO,
+            player: Player::new(),
+            previous_position: vec2::ZERO,
+            level_static_colliders: StaticColliders::new(),
+            floor: Collider::aabb(Aabb2::ZERO),
+            walls: Vec::new(),
+            door_entrance: Collider::aabb(Aabb2::ZERO),
+            door_exit: Collider::aabb(Aabb2::ZERO),
+            platforms: Vec::new(),
+            bubble_balls: Vec::new(),
+            items: Vec::new(),
+            picked_up_item: None,
+            explosion: None,
+            grandson_spin: None,
+            grandpa_drill: None,
+            interact_item: None,
+            projectiles: Vec::new(),
+            fish_cooldown: FTime::new(1.0),
+        }
+    }
+
+    /// Make the random parts of the levels reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn load_level(&mut self, level: Option<SolverLevel>) {
+        self.level = level;
+        self.reload_level();
+    }
+
+    /// Start the level over, with the player at the last checkpoint.
+    pub fn reload_level(&mut self) {
+        self.level_static_colliders.clear();
+        self.walls.clear();
+        self.platforms.clear();
+        self.bubble_balls.clear();
+        self.projectiles.clear();
+        self.requested_exit = false;
+
+        self.player_respawn();
+        self.update_level_colliders();
+        self.reset_items();
+        self.fire_enter_triggers();
+    }
+
+    /// Apply the state synced from the server.
+    /// Returns `true` if the level has changed and has to be loaded.
+    pub fn sync_state(&mut self, state: SolverState) -> bool {
+        let old_state = std::mem::replace(&mut self.state, state);
+        let level_changed = old_state.current_level != self.state.current_level;
+        // A checkpoint reached locally may not have been confirmed by the server yet
+        if !level_changed {
+            self.state.checkpoint = self.state.checkpoint.max(old_state.checkpoint);
+        }
+        level_changed
+    }
+
+    /// Apply the flags synced from the server.
+    pub fn sync_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+        self.remove_opened_walls();
+    }
+
+    /// Remove the walls whose flags have been set.
+    fn remove_opened_walls(&mut self) {
+        let flags = &self.flags;
+        let is_open = |wall: &Wall| wall.until.as_ref().is_some_and(|flag| flags.is_set(flag));
+        for wall in self.walls.iter().filter(|wall| is_open(wall)) {
+            self.level_static_colliders
+                .retain(|collider| *collider != wall.collider);
+        }
+        self.walls.retain(|wall| !is_open(wall));
+    }
+
+    /// Spawn the walls and start the music of the level.
+    fn fire_enter_triggers(&mut self) {
+        let Some(level) = &self.level else {
+            return;
+        };
+        let actions: Vec<TriggerAction> = level
+            .triggers
+            .iter()
+            .filter(|trigger| trigger.fires(&TriggerEvent::Enter, &self.state, &self.flags))
+            .flat_map(|trigger| trigger.then.iter().cloned())
+            .collect();
+        for action in actions {
+            match action {
+                TriggerAction::PlayMusic(music) => self.events.push(SolverEvent::PlayMusic(music)),
+                TriggerAction::SpawnWall {
+                    position,
+                    size,
+                    rotation,
+                    sprite,
+                    until,
+                } => {
+                    if until.as_ref().is_some_and(|flag| self.flags.is_set(flag)) {
+                        continue;
+                    }
+                    let collider = Collider {
+                        position,
+                        rotation: Angle::from_degrees(rotation).as_r32(),
+                        shape: Shape::Rectangle {
+                            width: size.x,
+                            height: size.y,
+                        },
+                    };
+                    self.level_static_colliders.push(collider.clone());
+                    self.walls.push(Wall {
+                        collider,
+                        sprite,
+                        until,
+                    });
+                }
+                TriggerAction::SpawnBubbles {
+                    origin,
+                    columns,
+                    rows,
+                } => {
+                    let ball = Collider::circle(vec2::ZERO, r32(0.5));
+                    let rng = &mut self.rng;
+                    self.bubble_balls = (0..columns)
+                        .flat_map(|x| (0..rows).map(move |y| (x, y)))
+                        .map(|(x, y)| {
+                            let mut ball = ball.clone();
+                            ball.position = origin
+                                + vec2(x as f32, y as f32).as_r32()
+                                + vec2(rng.gen_range(-0.01..=0.01), rng.gen_range(-0.01..=0.01))
+                                    .as_r32();
+                            (ball, rng.gen_range(0..=3))
+                        })
+                        .collect();
+                }
+                // Progress is up to the server
+                TriggerAction::OpenExit
+                | TriggerAction::SetFlag(_)
+                | TriggerAction::ClearFlag(_)
+                | TriggerAction::CrashSolver(_) => {}
+            }
+        }
+    }
+
+    /// Restart the level from the last checkpoint, leaving the persistent items where they are.
+    pub fn respawn(&mut self) {
+        let mut persistent: Vec<SolverItem> = self
+            .items
+            .drain(..)
+            .filter(|item| item.persistent)
+            .collect();
+        if let Some(mut item) = self.picked_up_item.take()
+            && item.persistent
+        {
+            item.collider.position = self.player.collider.position;
+            persistent.push(item);
+        }
+        self.reload_level();
+        self.items.retain(|item| !item.persistent);
+        self.items.extend(persistent);
+    }
+
+    /// Restart the level from the last checkpoint.
+    pub fn crash(&mut self, message: impl Into<String>) {
+        self.respawn();
+        self.events.push(SolverEvent::Crash(message.into()));
+    }
+
+    /// Duration of a single simulation step.
+    pub fn timestep(&self) -> FTime {
+        FTime::ONE / self.rules.ticks_per_second
+    }
+
+    /// Accumulate the frame time and return the number of steps that fit into it.
+    pub fn steps_due(&mut self, frame_time: FTime) -> usize {
+        let timestep = self.timestep();
+        self.accumulator =
+            (self.accumulator + frame_time).min(timestep * r32(MAX_STEPS_PER_FRAME as f32));
+        let mut steps = 0;
+        while self.accumulator >= timestep {
+            self.accumulator -= timestep;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Player's collider between the last two steps, matching the time of the frame.
+    pub fn interpolated_player(&self) -> Collider {
+        let t = (self.accumulator / self.timestep()).clamp(FTime::ZERO, FTime::ONE);
+        let mut collider = self.player.collider.clone();
+        collider.position =
+            self.previous_position + (collider.position - self.previous_position) * t;
+        collider
+    }
+
+    /// Replace the player, e.g. with the one synced from the server, without interpolating.
+    pub fn set_player(&mut self, player: Player) {
+        self.previous_position = player.collider.position;
+        self.player = player;
+    }
+
+    /// Events produced outside of the steps, like entering a level.
+    pub fn take_events(&mut self) -> Vec<SolverEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Advance the simulation by a single fixed step with the given input.
+    pub fn step(&mut self, control: PlayerControl) -> Vec<SolverEvent> {
+        let delta_time = self.timestep();
+        self.control = control;
+        self.tick += 1;
+        self.time += delta_time;
+        self.previous_position = self.player.collider.position;
+
+        if self.flags.get(Flag::POPPED) && self.explosion.is_none() {
+            self.explosion = Some((self.player.collider.position, FTime::ZERO));
+        }
+
+        if let Some(spin) = &mut self.grandson_spin {
+            *spin += Angle::from_degrees(r32(360.0) * delta_time);
+            if spin.as_degrees().as_f32() > 360.0 {
+                self.grandson_spin = None;
+            }
+        }
+        if let Some(time) = &mut self.grandpa_drill {
+            let t = *time;
+            *time += delta_time;
+            if t.as_f32() <= 1.0 && time.as_f32() > 1.0 {
+                self.platforms.clear();
+                if self
+                    .level
+                    .as_ref()
+                    .is_some_and(|level| level.drillable_floor)
+                {
+                    self.level_static_colliders.remove(&self.floor);
+                }
+                self.events
+                    .push(SolverEvent::Action(SolverAction::GrandpaDrilled));
+            }
+        }
+
+        if let Some((pos, timer)) = &mut self.explosion {
+            *timer += delta_time;
+            if timer.as_f32() > 1.0 {
+                if self.flags.get(Flag::POPPED) {
+                    self.crash("тебе конец, и игре тоже");
+                    return std::mem::take(&mut self.events);
+                }
+
+                if (self.player.collider.position - *pos).len().as_f32() < 1.5 {
+                    self.crash("ты взорвался");
+                    return std::mem::take(&mut self.events);
+                }
+
+                self.explosion = None;
+                self.events
+                    .push(SolverEvent::Action(SolverAction::Explosion));
+            }
+        }
+
+        self.update_projectiles(delta_time);
+        self.update_platforms(delta_time);
+        self.update_player(delta_time);
+        self.update_items(delta_time);
+        self.update_balls(delta_time);
+
+        std::mem::take(&mut self.events)
+    }
+
+    fn reset_items(&mut self) {
+        let Some(level) = &self.level else {
+            return;
+        };
+        self.items = level.items.clone();
+        self.picked_up_item = None;
+    }
+
+    fn player_respawn(&mut self) {
+        let Some(level) = &self.level else {
+            return;
+        };
+
+        let player = &mut self.player;
+        player.collider.position = level.respawn_point(self.state.checkpoint)
+            + vec2(r32(0.0), player.collider.compute_aabb().height() / r32(2.0));
+        player.velocity = vec2::ZERO;
+        self.previous_position = player.collider.position;
+    }
+
+    fn update_level_colliders(&mut self) {
+        let Some(level) = &self.level else {
+            return;
+        };
+
+        let wall_thickness = r32(1.0);
+        let door_height = r32(2.0);
+
+        // Floor
+        self.floor = Collider::aabb(
+            Aabb2::ZERO
+                .extend_right(r32(LEVEL_SIZE.x))
+                .extend_up(wall_thickness),
+        );
+        self.level_static_colliders.push(self.floor.clone());
+        // Left wall
+        self.level_static_colliders.push(Collider::aabb(
+            Aabb2::point(vec2(r32(0.0), door_height + wall_thickness))
+                .extend_up(r32(LEVEL_SIZE.y))
+                .extend_right(wall_thickness),
+        ));
+        // Right wall
+        self.level_static_colliders.push(Collider::aabb(
+            Aabb2::point(vec2(LEVEL_SIZE.x.as_r32(), door_height + wall_thickness))
+                .extend_up(r32(LEVEL_SIZE.y))
+                .extend_left(wall_thickness),
+        ));
+        // Ceiling
+        self.level_static_colliders.push(Collider::aabb(
+            Aabb2::point(vec2(0.0, LEVEL_SIZE.y).as_r32())
+                .extend_right(r32(LEVEL_SIZE.x))
+                .extend_down(wall_thickness),
+        ));
+
+        // Slopes and ramps
+        self.level_static_colliders
+            .extend(level.geometry.iter().cloned());
+        if let Some(tiles) = &level.tiles {
+            self.level_static_colliders.extend(tiles.colliders());
+        }
+
+        let door_width = r32(0.3);
+
+        // Entrance door
+        self.door_entrance = Collider::aabb(
+            Aabb2::point(vec2(0.0.as_r32(), wall_thickness))
+                .extend_up(door_height)
+                .extend_right(door_width),
+        );
+
+        // Exit door
+        self.door_exit = Collider::aabb(
+            Aabb2::point(vec2(LEVEL_SIZE.x.as_r32(), wall_thickness))
+                .extend_up(door_height)
+                .extend_left(door_width),
+        );
+
+        // Platforms
+        self.platforms = level
+            .platforms
+            .iter()
+            .map(|platform| LevelPlatform {
+                platform: platform.clone(),
+                collider: Collider::aabb(platform.aabb(self.platform_aspect)),
+                travelled: FCoord::ZERO,
+                crumbling: None,
+            })
+            .collect();
+    }
+
+    fn update_projectiles(&mut self, delta_time: FTime) {
+        let player = self.player.collider.cached();
+        let mut remove_projs = Vec::new();
+        for (proj_i, proj) in self.projectiles.iter_mut().enumerate() {
+            proj.collider.position += proj.velocity * delta_time;
+            let collider = proj.collider.cached();
+            if self.level_static_colliders.check(&collider) {
+                remove_projs.push(proj_i);
+            } else if collider.check(&player) {
+                self.crash("ты попался карасю");
+                return;
+                // remove_projs.push(proj_i);
+            }
+        }
+
+        for i in remove_projs.into_iter().rev() {
+            self.projectiles.swap_remove(i);
+        }
+    }
+
+    /// Move the platforms along their paths, carrying the player, and crumble the ones stood on.
+    fn update_platforms(&mut self, delta_time: FTime) {
+        let grounded = matches!(self.player.state, PlayerState::Grounded);
+        let feet = self.player.feet_collider();
+        for platform in &mut self.platforms {
+            let standing = grounded && feet.check(&platform.collider);
+            if let Some(path) = &platform.platform.path {
+                let from = platform.platform.position_at(platform.travelled);
+                platform.travelled += path.speed * delta_time;
+                let delta = platform.platform.position_at(platform.travelled) - from;
+                platform.collider.position += delta;
+                if standing {
+                    self.player.collider.position += delta;
+                }
+            }
+            if standing && platform.platform.crumble_time.is_some() && platform.crumbling.is_none()
+            {
+                platform.crumbling = Some(FTime::ZERO);
+            }
+            if let Some(time) = &mut platform.crumbling {
+                *time += delta_time;
+            }
+        }
+        self.platforms.retain(|platform| {
+            platform
+                .crumbling
+                .zip(platform.platform.crumble_time)
+                .is_none_or(|(time, limit)| time < limit)
+        });
+    }
+
+    /// Platforms that hold the player, unless they are being dropped through.
+    fn solid_platforms(&self) -> impl Iterator<Item = &Collider> {
+        let dropping = self.control.move_dir.y < FCoord::ZERO;
+        self.platforms
+            .iter()
+            .filter(move |platform| !(dropping && platform.platform.drop_through))
+            .map(|platform| &platform.collider)
+    }
+
+    fn update_balls(&mut self, delta_time: FTime) {
+        let platforms = self.platforms.iter().map(|platform| &platform.collider);
+        for (ball, _) in &mut self.bubble_balls {
+            let mut any_collision = false;
+            let mut cached = ball.cached();
+            for static_col in self.level_static_colliders.near(cached.compute_aabb()) {
+                cached.set_position(ball.position);
+                if let Some(collision) = cached.collide(static_col) {
+                    ball.position -= collision.normal * collision.penetration;
+                    any_collision = true;
+                }
+            }
+            for platform in platforms.clone() {
+                if let Some(collision) = ball.collide(platform) {
+                    ball.position -= collision.normal * collision.penetration;
+                    any_collision = true;
+                }
+            }
+            if !any_collision {
+                ball.position += vec2(0.0, -2.0).as_r32() * delta_time;
+            }
+        }
+
+        let items_count = self.bubble_balls.len();
+        for i in 0..items_count {
+            for j in i + 1..items_count {
+                if let Ok([(ball, _), (other, _)]) = self.bubble_balls.get_disjoint_mut([i, j])
+                    && let Some(collision) = ball.collide(other)
+                {
+                    let offset = collision.normal * collision.penetration * r32(0.5);
+                    ball.position -= offset;
+                    other.position += offset;
+                }
+            }
+        }
+    }
+
+    fn update_items(&mut self, delta_time: FTime) {
+        // Item movement
+        for item in &mut self.items {
+            if item.has_gravity {
+                let cached = item.collider.cached();
+                let collision = self
+                    .level_static_colliders
+                    .collisions(&cached)
+                    .chain(
+                        self.platforms
+                            .iter()
+                            .filter_map(|platform| item.collider.collide(&platform.collider)),
+                    )
+                    .max_by_key(|col| col.penetration);
+                match collision {
+                    None => {
+                        item.collider.position += vec2(0.0, -5.0).as_r32() * delta_time;
+                    }
+                    Some(collision) => {
+                        item.collider.position -= collision.normal * collision.penetration;
+                    }
+                }
+            }
+
+            if item.shoots && !self.state.is_exit_open() {
+                // Shoot projectiles
+                self.fish_cooldown -= delta_time;
+                if self.fish_cooldown.as_f32() <= 0.0 {
+                    self.fish_cooldown += FTime::new(0.7);
+                    let position = item.collider.position;
+                    self.projectiles.push(Projectile {
+                        collider: Collider::circle(position, r32(0.2)),
+                        velocity: (self.player.collider.position - position).normalize_or_zero()
+                            * r32(5.0),
+                    });
+                }
+            }
+        }
+
+        // Item collision
+        let items_count = self.items.len();
+        let mut remove_items = Vec::new();
+        for i in 0..items_count {
+            for j in i + 1..items_count {
+                if let Ok([item, other]) = self.items.get_disjoint_mut([i, j]) {
+                    let check_combination = |a, b| {
+                        item.kind == a && other.kind == b || item.kind == b && other.kind == a
+                    };
+
+                    use crate::assets::SolverItemKind::*;
+                    if check_combination(Fish, CinderBlock) && item.collider.check(&other.collider)
+                    {
+                        // Explosion
+                        remove_items.extend([i, j]);
+                        self.explosion = Some((item.collider.position, FTime::ZERO));
+                    }
+                }
+            }
+        }
+        remove_items.sort();
+        for i in remove_items.into_iter().rev() {
+            self.items.swap_remove(i);
+        }
+    }
+
+    fn update_player(&mut self, delta_time: FTime) {
+        let anim_state = self.player.animation_state();
+
+        {
+            let rules = &self.rules;
+            self.player.update_timers(delta_time);
+
+            // Update Jump Buffer
+            if self.control.jump {
+                self.player.jump_buffer = Some(rules.buffer_time);
+            }
+
+            // Update Jump Hold
+            if self.player.can_hold_jump && !self.control.hold_jump {
+                self.player.can_hold_jump = false;
+            }
+
+            // Update look direction
+            let player = &mut self.player;
+            if player.facing_left && player.velocity.x > FCoord::ZERO
+                || !player.facing_left && player.velocity.x < FCoord::ZERO
+            {
+                player.facing_left = !player.facing_left;
+            }
+
+            // Apply gravity
+            self.player.velocity += rules.gravity * delta_time;
+        }
+
+        if self.control.pickup {
+            if let Some(mut item) = self.picked_up_item.take() {
+                // Drop item
+                let dir = if self.player.facing_left {
+                    vec2(-1.0, 0.0)
+                } else {
+                    vec2(1.0, 0.0)
+                }
+                .as_r32();
+                item.collider.position = self.player.collider.position + dir * r32(0.5);
+
+                let mut disappear = false;
+                for other in &self.items {
+                    let check = |a, b| {
+                        item.kind == a && other.kind == b && item.collider.check(&other.collider)
+                    };
+
+                    use SolverItemKind::*;
+                    if check(Recycle, Grandson) {
+                        self.grandson_spin = Some(Angle::ZERO);
+                    } else if check(Recycle, Grandpa) {
+                        self.grandpa_drill = Some(FTime::ZERO);
+                    } else if check(Grandpa, Trashcan) {
+                        self.crash("ДЕДЭНД: вспомни с кем честь имеешь, скорлупа");
+                        return;
+                    } else if check(Grandson, Trashcan) {
+                        disappear = true;
+                    }
+                }
+
+                if !disappear {
+                    self.items.push(item);
+                }
+            } else if let Some(i) = self.interact_item
+                && let Some(item) = self.items.get(i)
+            {
+                if item.kind == SolverItemKind::BubbleCode {
+                } else {
+                    // Pick up an item
+                    self.picked_up_item = Some(self.items.swap_remove(i));
+                }
+            }
+        }
+
+        self.player_variable_jump(delta_time);
+        self.player_horizontal_control(delta_time);
+        self.player_jump(delta_time);
+
+        self.player_move(delta_time);
+        self.player_update_state();
+
+        if anim_state != self.player.animation_state() {
+            self.player.animation_time = FTime::ZERO;
+        }
+
+        self.check_transition();
+        self.check_hazards();
+        self.check_checkpoints();
+        self.check_out_of_bounds();
+
+        self.interact_item = self.items.iter().position(|item| {
+            (item.can_pickup || item.kind == SolverItemKind::BubbleCode)
+                && item.collider.check(&self.player.collider)
+                && !(self.flags.get(Flag::TRASHCAN_EVIL)
+                    && matches!(item.kind, SolverItemKind::Recycle))
+        });
+    }
+
+    fn player_variable_jump(&mut self, delta_time: FTime) {
+        let rules = &self.rules;
+        let player = &mut self.player;
+
+        // Variable jump height
+        if player.velocity.y < FCoord::ZERO {
+            // Faster drop
+            player.velocity.y +=
+                rules.gravity.y * (rules.fall_multiplier - FCoord::ONE) * delta_time;
+            let cap = rules.free_fall_speed;
+            player.velocity.y = player.velocity.y.clamp_abs(cap);
+        } else if player.velocity.y > FCoord::ZERO
+            && !(self.control.hold_jump && player.can_hold_jump)
+        {
+            // Low jump
+            player.velocity.y +=
+                rules.gravity.y * (rules.low_multiplier - FCoord::ONE) * delta_time;
+        }
+    }
+
+    fn player_horizontal_control(&mut self, delta_time: FTime) {
+        let rules = &self.rules;
+        let player = &mut self.player;
+
+        if player.control_timeout.is_some() {
+            return;
+        }
+
+        // Horizontal speed control
+        let current = player.velocity.x;
+        let max_speed = rules.move_speed;
+        let target = self.control.move_dir.x * max_speed;
+
+        let mut acc = FCoord::ZERO;
+
+        // Acceleration
+        let is_grounded = matches!(player.state, PlayerState::Grounded);
+        if target == FCoord::ZERO
+            || target.signum() != current.signum()
+            || target.abs() > current.abs()
+        {
+            // Accelerate towards target
+            acc += if is_grounded {
+                rules.acceleration_ground
+            } else {
+                rules.acceleration_air
+            };
+        } else {
+            // Target is aligned with current velocity and is higher
+            // Decelerate
+            acc += if is_grounded {
+                rules.deceleration_ground
+            } else {
+                rules.deceleration_air
+            };
+        }
+
+        player.velocity.x += (target - current).clamp_abs(acc * delta_time);
+    }
+
+    fn player_jump(&mut self, _delta_time: FTime) {
+        let rules = &self.rules;
+        let player = &mut self.player;
+
+        if player.jump_buffer.is_none() {
+            return;
+        }
+
+        // Try jump
+        let jump = match player.state {
+            PlayerState::Grounded => true,
+            PlayerState::Airborn => player.coyote_time.is_some(),
+        };
+        if !jump {
+            return;
+        }
+
+        // Use jump
+        player.coyote_time = None;
+        player.jump_buffer = None;
+        player.can_hold_jump = true;
+        let push = if self.control.move_dir.x == FCoord::ZERO {
+            FCoord::ZERO
+        } else {
+            rules.jump_push * self.control.move_dir.x.signum()
+        };
+        let jump_vel = vec2(player.velocity.x + push, rules.jump_strength);
+        player.velocity = jump_vel;
+        player.state = PlayerState::Airborn;
+    }
+
+    fn player_check_ground(&mut self) {
+        let player = &mut self.player;
+        let was_grounded = matches!(player.state, PlayerState::Grounded);
+        if was_grounded {
+            player.state = PlayerState::Airborn;
+        }
+        let update_state = (matches!(player.state, PlayerState::Airborn) || was_grounded)
+            && player.velocity.y <= FCoord::ZERO;
+
+        if update_state {
+            let collider = player.feet_collider();
+
+            if self
+                .check_ground_collision(&self.player.collider, &collider)
+                .is_some()
+            {
+                let player = &mut self.player;
+                player.state = PlayerState::Grounded;
+                player.coyote_time = Some(self.rules.coyote_time);
+            }
+        }
+    }
+
+    fn player_move(&mut self, delta_time: FTime) {
+        let player = &mut self.player;
+        player.collider.position += player.velocity * delta_time;
+
+        let walkable = r32(MAX_SLOPE_DEGREES.to_radians().cos());
+        let fix_collision = |player: &mut Player, collision: &Collision| {
+            let up = -collision.normal.y;
+            if up >= walkable {
+                // Push straight up so that the player walks up slopes and does not slide down them
+                player.collider.position.y += collision.penetration / up;
+                player.velocity.y = player.velocity.y.max(FCoord::ZERO);
+            } else {
+                player.collider.position -= collision.normal * collision.penetration;
+                player.velocity -= collision.normal * vec2::dot(player.velocity, collision.normal);
+            }
+        };
+        let collide_with = |player: &mut Player, other: &Collider| {
+            if let Some(collision) = player.collider.collide(other) {
+                fix_collision(player, &collision);
+            }
+        };
+
+        // Bubbles
+        let mut remove_balls = Vec::new();
+        for (ball_i, (ball, _)) in self.bubble_balls.iter().enumerate() {
+            if let Some(collision) = player.collider.collide(ball) {
+                let velocity_offset =
+                    collision.normal * vec2::dot(player.velocity, collision.normal);
+                if velocity_offset.len() > r32(7.0) {
+                    remove_balls.push(ball_i);
+                }
+                player.collider.position -= collision.normal * collision.penetration.min(r32(0.5));
+                player.velocity -= velocity_offset;
+            }
+        }
+        if !remove_balls.is_empty() {
+            self.events.push(SolverEvent::BubblesPopped);
+        }
+        for i in remove_balls.into_iter().rev() {
+            self.bubble_balls.swap_remove(i);
+        }
+
+        // Static colliders, with a margin for the pushes from the earlier ones
+        let mut cached = player.collider.cached();
+        let area = cached.compute_aabb().extend_uniform(r32(0.5));
+        for static_col in self.level_static_colliders.near(area) {
+            cached.set_position(player.collider.position);
+            if let Some(collision) = cached.collide(static_col) {
+                fix_collision(player, &collision);
+            }
+        }
+
+        // Doors
+        collide_with(player, &self.door_entrance);
+        if !self.state.is_exit_open() {
+            collide_with(player, &self.door_exit);
+        }
+
+        // Platforms
+        if player.velocity.y.as_f32() <= 0.0 {
+            let collider = player.feet_collider();
+            let dropping = self.control.move_dir.y < FCoord::ZERO;
+            for platform in &self.platforms {
+                if dropping && platform.platform.drop_through {
+                    continue;
+                }
+                if let Some(collision) = collider.collide(&platform.collider) {
+                    fix_collision(player, &collision);
+                }
+            }
+        }
+
+        // Items
+        for item in &mut self.items {
+            if item.pushable
+                && let Some(collision) = player.collider.collide(&item.collider)
+            {
+                let offset = collision.normal * collision.penetration;
+                // let velocity_offset =
+                //     collision.normal * vec2::dot(player.velocity, collision.normal);
+                player.collider.position -= offset * r32(0.5);
+                // player.velocity -= velocity_offset * r32(0.5);
+                item.collider.position += vec2::UNIT_X * vec2::dot(vec2::UNIT_X, offset) * r32(0.5);
+            }
+        }
+    }
+
+    fn player_update_state(&mut self) {
+        self.player_check_ground();
+    }
+
+    fn check_ground_collision(
+        &self,
+        collider: &Collider,
+        feet_collider: &Collider,
+    ) -> Option<Collision> {
+        let feet = feet_collider.cached();
+        self.level_static_colliders
+            .collisions(&feet)
+            .chain(
+                self.solid_platforms()
+                    .filter_map(|platform| feet_collider.collide(platform)),
+            )
+            .chain(
+                self.bubble_balls
+                    .iter()
+                    .map(|(ball, _)| ball)
+                    .filter_map(|col| collider.collide(col)),
+            )
+            .max_by_key(|col| col.penetration)
+    }
+
+    fn check_transition(&mut self) {
+        let Some(level) = &self.level else {
+            return;
+        };
+        if !self.requested_exit
+            && self.state.is_exit_open()
+            && self
+                .player
+                .collider
+                .check(&Collider::aabb(level.transition))
+        {
+            self.requested_exit = true;
+            self.events
+                .push(SolverEvent::Action(SolverAction::ExitLevel(
+                    self.state.current_level,
+                )));
+        }
+    }
+
+    fn check_hazards(&mut self) {
+        let Some(level) = &self.level else {
+            return;
+        };
+        let hazard = level
+            .hazards
+            .iter()
+            .find(|hazard| self.player.collider.check(&Collider::aabb(hazard.area)))
+            .map(|hazard| hazard.spikes);
+        match hazard {
+            Some(true) => self.crash("ты напоролся на шипы"),
+            Some(false) => self.crash("ты сорвался в пропасть"),
+            None => {}
+        }
+    }
+
+    /// Move the respawn point forward once the player enters a later checkpoint.
+    fn check_checkpoints(&mut self) {
+        let Some(level) = &self.level else {
+            return;
+        };
+        let reached = level
+            .checkpoints
+            .iter()
+            .rposition(|checkpoint| self.player.collider.check(&Collider::aabb(checkpoint.area)))
+            .filter(|&i| self.state.checkpoint.is_none_or(|current| current < i));
+        if let Some(checkpoint) = reached {
+            // Respawn there right away, the server confirms it with the next sync
+            self.state.checkpoint = Some(checkpoint);
+            self.events
+                .push(SolverEvent::Action(SolverAction::ReachCheckpoint {
+                    level: self.state.current_level,
+                    checkpoint,
+                }));
+        }
+    }
+
+    fn check_out_of_bounds(&mut self) {
+        if self.player.collider.position.y < r32(-50.0) {
+            self.player_respawn();
+        }
+    }
+}
+
+impl Player {
+    pub fn new() -> Self {
+        Self {
+            collider: Collider::aabb(
+                Aabb2::point(vec2(0.0, 0.0))
+                    .extend_positive(vec2(1.0, 1.5))
+                    .as_r32(),
+            ),
+            velocity: vec2::ZERO,
+            state: PlayerState::Airborn,
+            control_timeout: None,
+            facing_left: false,
+            can_hold_jump: false,
+            coyote_time: None,
+            jump_buffer: None,
+            animation_time: FTime::ZERO,
+        }
+    }
+
+    fn update_timers(&mut self, delta_time: FTime) {
+        self.animation_time += delta_time;
+
+        // Coyote Time
+        if let Some(time) = &mut self.coyote_time {
+            *time -= delta_time;
+            if *time <= FTime::ZERO {
+                self.coyote_time = None;
+            }
+        }
+
+        // Jump Buffer
+        if let Some(time) = &mut self.jump_buffer {
+            *time -= delta_time;
+            if *time <= FTime::ZERO {
+                self.jump_buffer = None;
+            }
+        }
+
+        // Control timeout
+        if let Some(time) = &mut self.control_timeout {
+            // No horizontal control
+            *time -= delta_time;
+            if *time <= FTime::ZERO {
+                self.control_timeout = None;
+            }
+        }
+    }
+
+    fn feet_collider(&self) -> Collider {
+        let aabb = self.collider.compute_aabb();
+        Collider::aabb(
+            aabb.extend_symmetric(-vec2(aabb.width() * r32(0.05), r32(0.0)))
+                .extend_up(-aabb.height() * r32(0.8)),
+        )
+    }
+
+    pub fn animation_state(&self) -> PlayerAnimationState {
+        match self.state {
+            PlayerState::Grounded => {
+                if self.velocity.x.abs() > r32(0.01) {
+                    PlayerAnimationState::Running
+                } else {
+                    PlayerAnimationState::Idle
+                }
+            }
+            PlayerState::Airborn => PlayerAnimationState::Jumping,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assets::{Checkpoint, Hazard};
+
+    fn assets_dir() -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    fn shipped_rules() -> SolverRules {
+        let rules = std::fs::read_to_string(assets_dir().join("solver").join("rules.ron"))
+            .expect("failed to read the rules");
+        ron::from_str(&rules).expect("invalid rules")
+    }
+
+    /// Second level with its exit already open.
+    fn second_level() -> SolverWorld {
+        let level = crate::assets::load_level(&assets_dir().join("solver").join("levels"), "2.ron")
+            .expect("failed to load the level");
+        let state = SolverState {
+            current_level: 1,
+            levels_completed: 2,
+            checkpoint: None,
+        };
+        let mut world = SolverWorld::new(shipped_rules(), state).with_seed(0);
+        world.load_level(Some(level));
+        world
+    }
+
+    /// Run right, jumping over the cinder block in the way.
+    fn run_right(tick: usize) -> PlayerControl {
+        PlayerControl {
+            jump: tick % 60 == 0,
+            hold_jump: tick % 60 < 30,
+            move_dir: vec2(FCoord::ONE, FCoord::ZERO),
+            pickup: false,
+        }
+    }
+
+    #[test]
+    fn second_level_can_be_exited() {
+        let mut world = second_level();
+        let ticks = 10 * world.rules.ticks_per_second.as_f32() as usize;
+        let exited = (0..ticks).any(|tick| {
+            world
+                .step(run_right(tick))
+                .iter()
+                .any(|event| matches!(event, SolverEvent::Action(SolverAction::ExitLevel(1))))
+        });
+        assert!(exited, "the player has not reached the exit");
+    }
+
+    #[test]
+    fn hazard_respawns_at_the_checkpoint() {
+        let mut world = second_level();
+        let mut level = world.level.clone().unwrap();
+        let respawn = level.spawnpoint + vec2(r32(12.0), FCoord::ZERO);
+        level.checkpoints.push(Checkpoint {
+            area: Aabb2::point(level.spawnpoint).extend_uniform(r32(2.0)),
+            spawnpoint: respawn,
+        });
+        let hazard = vec2(level.spawnpoint.x, r32(-40.0));
+        level.hazards.push(Hazard {
+            area: Aabb2::point(hazard).extend_uniform(r32(5.0)),
+            spikes: true,
+        });
+        level.items.push(SolverItem {
+            kind: SolverItemKind::Fish,
+            pushable: false,
+            can_pickup: false,
+            has_gravity: false,
+            shoots: false,
+            persistent: true,
+            collider: Collider::aabb(
+                Aabb2::point(vec2(r32(5.0), r32(5.0))).extend_uniform(r32(0.5)),
+            ),
+        });
+        world.load_level(Some(level));
+
+        world.step(PlayerControl::default());
+        assert_eq!(world.state.checkpoint, Some(0));
+
+        // Carry the item somewhere else and fall into the hazard
+        let moved = vec2(r32(8.0), r32(6.0));
+        world
+            .items
+            .iter_mut()
+            .find(|item| item.persistent)
+            .unwrap()
+            .collider
+            .position = moved;
+        world.player.collider.position = hazard;
+        let events = world.step(PlayerControl::default());
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, SolverEvent::Crash(_)))
+        );
+
+        let height = world.player.collider.compute_aabb().height();
+        assert_eq!(
+            world.player.collider.position,
+            respawn + vec2(FCoord::ZERO, height / r32(2.0))
+        );
+        assert_eq!(world.state.checkpoint, Some(0));
+        let persistent: Vec<_> = world.items.iter().filter(|item| item.persistent).collect();
+        assert_eq!(persistent.len(), 1);
+        assert_eq!(persistent[0].collider.position, moved);
+    }
+
+    #[test]
+    fn exit_is_requested_once() {
+        let mut world = second_level();
+        let is_exit =
+            |event: &SolverEvent| matches!(event, SolverEvent::Action(SolverAction::ExitLevel(_)));
+        let ticks = 10 * world.rules.ticks_per_second.as_f32() as usize;
+        let mut tick = 0;
+        while !world.step(run_right(tick)).iter().any(is_exit) {
+            tick += 1;
+            assert!(tick < ticks, "the player has not reached the exit");
+        }
+        // Stand in the transition area until the server moves on
+        for _ in 0..ticks / 10 {
+            let events = world.step(PlayerControl::default());
+            assert!(
+                !events.iter().any(is_exit),
+                "the exit has been requested again"
+            );
+        }
+    }
+
+    /// Simulate the steps with the frame times repeated in a cycle.
+    fn simulate(frame_times: &[f32], ticks: u64) -> Collider {
+        let mut world = second_level();
+        for &frame_time in frame_times.iter().cycle() {
+            for _ in 0..world.steps_due(FTime::new(frame_time)) {
+                if world.tick == ticks {
+                    return world.player.collider;
+                }
+                world.step(run_right(world.tick as usize));
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn simulation_does_not_depend_on_frame_rate() {
+        let ticks = 3 * shipped_rules().ticks_per_second.as_f32() as u64;
+        let steady = simulate(&[1.0 / 60.0], ticks);
+        let uneven = simulate(&[0.004, 0.021, 1.0 / 144.0, 0.05, 0.013], ticks);
+        assert_eq!(steady, uneven);
+    }
+}
@@ -12,6 +12,48 @@ pub fn get_pixel_scale(framebuffer_size: vec2<usize>) -> f32 {
     ratio.x.min(ratio.y)
 }
 
+/// Draw the tiles of the map with their sprites from the tileset, a row of square tiles.
+/// All tiles go into a single draw call.
+pub fn draw_tiles(
+    tiles: &crate::assets::TileMap,
+    tileset: &ugli::Texture,
+    geng: &Geng,
+    camera: &impl geng::AbstractCamera2d,
+    framebuffer: &mut ugli::Framebuffer,
+) {
+    let size = tileset.size().as_f32();
+    let count = (size.x / size.y).floor().max(1.0);
+    let mut vertices = Vec::new();
+    for (cell, tile) in tiles.tiles() {
+        let area = tiles.cell_aabb(cell).map(|x| x.as_f32());
+        let index = (tile.index as f32).min(count - 1.0);
+        let uv = Aabb2 {
+            min: vec2(index / count, 0.0),
+            max: vec2((index + 1.0) / count, 1.0),
+        };
+        let corners: Vec<draw2d::TexturedVertex> = area
+            .corners()
+            .into_iter()
+            .zip(uv.corners())
+            .map(|(a_pos, a_vt)| draw2d::TexturedVertex {
+                a_pos,
+                a_color: Rgba::WHITE,
+                a_vt,
+            })
+            .collect();
+        // Two triangles per quad
+        vertices.extend([0, 1, 2, 0, 2, 3].map(|i| corners[i]));
+    }
+    if vertices.is_empty() {
+        return;
+    }
+    geng.draw2d().draw2d(
+        framebuffer,
+        camera,
+        &draw2d::TexturedPolygon::with_mode(vertices, tileset, ugli::DrawMode::Triangles),
+    );
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TextRenderOptions {
     pub size: f32,
@@ -1,20 +1,219 @@
-use super::*;
+use super::{limits::*, room::RoomCommand, shared::*, *};
 
+use geng::prelude::rand::distributions::DistString;
+
+/// Connection of a single client. Handles the handshake and the limits itself
+/// and forwards the messages concerning a room to the room's actor.
 pub struct ClientConnection {
-    pub id: ClientId,
-    pub state: Arc<Mutex<ServerState>>,
+    shared: Arc<Shared>,
+    client: ClientHandle,
+    token: String,
+    /// Whether the client has sent a compatible `Hello`.
+    handshake: bool,
+    /// Spectators only receive updates and cannot affect the game.
+    spectating: bool,
+    limiter: RateLimiter,
+    /// The client has been disconnected for abusing the limits.
+    dropped: bool,
+}
+
+impl ClientConnection {
+    pub fn new(shared: Arc<Shared>, sender: Box<dyn geng::net::Sender<ServerMessage>>) -> Self {
+        let client = ClientHandle::new(shared.next_client_id(), sender);
+        client.send(ServerMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            build: BUILD.to_owned(),
+        });
+        Self {
+            shared,
+            client,
+            token: rand::distributions::Alphanumeric.sample_string(&mut thread_rng(), 16),
+            handshake: false,
+            spectating: false,
+            limiter: RateLimiter::new(),
+            dropped: false,
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.shared.unregister_client(self.client.id);
+        if let Some(room) = self.client.room() {
+            room.send(RoomCommand::Leave {
+                client: self.client.id,
+            });
+        }
+        self.client.set_room(None);
+    }
+
+    fn handle_message(&mut self, message: ClientMessage) {
+        let client_id = self.client.id;
+        self.shared.metrics().count_message(message.kind());
+        if self.dropped {
+            return;
+        }
+        match self.limiter.check(&message) {
+            Ok(()) => {}
+            Err(LimitViolation::Throttled) => {
+                self.shared.metrics().throttled_messages += 1;
+                return;
+            }
+            Err(LimitViolation::Abusive(error)) => {
+                log::warn!("Disconnecting client {client_id}: {error}");
+                self.client.send(ServerMessage::Error(error));
+                self.client.close();
+                self.shared.metrics().dropped_clients += 1;
+                self.dropped = true;
+                self.disconnect();
+                return;
+            }
+        }
+        if !self.handshake && !matches!(message, ClientMessage::Hello { .. }) {
+            log::debug!("Client {client_id} sent a message before the handshake: {message:?}");
+            return;
+        }
+        let room = self.client.room();
+        if room.is_none() {
+            self.spectating = false;
+        }
+        if self.spectating && !matches!(message, ClientMessage::Pong | ClientMessage::LeaveRoom) {
+            log::debug!("Spectator {client_id} sent a message: {message:?}");
+            return;
+        }
+        match message {
+            ClientMessage::Hello {
+                protocol_version,
+                build,
+            } => {
+                if protocol_version != PROTOCOL_VERSION {
+                    log::info!(
+                        "Client {client_id} uses incompatible protocol version {protocol_version} (build {build})"
+                    );
+                    self.client
+                        .send(ServerMessage::Error(ServerError::IncompatibleProtocol {
+                            protocol_version: PROTOCOL_VERSION,
+                            build: BUILD.to_owned(),
+                        }));
+                    return;
+                }
+                if !self.handshake {
+                    self.handshake = true;
+                    self.shared.register_client(self.client.clone());
+                }
+                self.client.send(ServerMessage::Ping);
+                self.client
+                    .send(ServerMessage::YourToken(self.token.clone()));
+            }
+            ClientMessage::Pong => {}
+            ClientMessage::Login(token) => {
+                self.token = token.clone();
+                self.client.send(ServerMessage::YourToken(token));
+            }
+            ClientMessage::ResumeSession(token) => {
+                let Some(room) = self.shared.find_session(&token) else {
+                    self.client
+                        .send(ServerMessage::Error(ServerError::SessionNotFound));
+                    return;
+                };
+                self.token = token.clone();
+                let client = self.client.clone();
+                if !room.send(RoomCommand::Resume { client, token }) {
+                    self.client
+                        .send(ServerMessage::Error(ServerError::SessionNotFound));
+                }
+            }
+            ClientMessage::CreateRoom(pack) => {
+                if room.is_some() {
+                    // The client already has a room, there's some desync
+                    // TODO: fix desync
+                    return;
+                }
+                if self.shared.pack_levels(&pack).is_none() {
+                    self.client
+                        .send(ServerMessage::Error(ServerError::UnknownLevelPack));
+                    return;
+                }
+                if self
+                    .shared
+                    .create_room(&self.client, &self.token, pack)
+                    .is_none()
+                {
+                    // Failed to create a room
+                    // TODO: idk
+                    log::error!("Failed to generate a free room code");
+                }
+            }
+            ClientMessage::JoinRoom(code) => {
+                if room.is_some() {
+                    log::debug!("Client {client_id} tried to join while in a room");
+                    return;
+                }
+                let code = code.to_uppercase();
+                let client = self.client.clone();
+                let token = self.token.clone();
+                let joined = self
+                    .shared
+                    .find_room(&code)
+                    .is_some_and(|room| room.send(RoomCommand::Join { client, token }));
+                if !joined {
+                    self.client
+                        .send(ServerMessage::Error(ServerError::RoomNotFound));
+                }
+            }
+            ClientMessage::SpectateRoom(code) => {
+                if room.is_some() {
+                    log::debug!("Client {client_id} tried to spectate while in a room");
+                    return;
+                }
+                let code = code.to_uppercase();
+                let client = self.client.clone();
+                match self.shared.find_room(&code) {
+                    Some(room) if room.send(RoomCommand::Spectate { client }) => {
+                        self.spectating = true;
+                        self.client.set_room(Some(room));
+                    }
+                    _ => {
+                        self.client
+                            .send(ServerMessage::Error(ServerError::RoomNotFound));
+                    }
+                }
+            }
+            ClientMessage::LeaveRoom => {
+                if let Some(room) = room {
+                    room.send(RoomCommand::Leave { client: client_id });
+                    self.client.leave_room(&room.code);
+                }
+            }
+            ClientMessage::SelectRole(_)
+            | ClientMessage::SolverAction(_)
+            | ClientMessage::DispatcherAction(_)
+            | ClientMessage::SkipToLevel(_)
+            | ClientMessage::SyncSolverPlayer(_)
+            | ClientMessage::CrashOther(_) => {
+                let Some(room) = room else {
+                    return;
+                };
+                if !room.send(RoomCommand::Message {
+                    client: client_id,
+                    message,
+                }) {
+                    // The room has been closed in the meantime
+                    self.client.leave_room(&room.code);
+                }
+            }
+        }
+    }
 }
 
 impl Drop for ClientConnection {
     fn drop(&mut self) {
-        let mut state = self.state.lock().unwrap();
-        state.client_disconnect(self.id);
+        if !self.dropped {
+            self.disconnect();
+        }
     }
 }
 
 impl geng::net::Receiver<ClientMessage> for ClientConnection {
     fn handle(&mut self, message: ClientMessage) {
-        let mut state = self.state.lock().unwrap();
-        state.handle_message(self.id, message);
+        self.handle_message(message);
     }
 }
@@ -0,0 +1,117 @@
+use super::{room::RoomCommand, shared::Shared, *};
+
+const HELP: &str = "Commands:
+  rooms                    list the rooms and their players
+  metrics                  print the metrics in the Prometheus format
+  kick <room> <player>     remove the player (by index in `rooms`) from the room
+  close <room>             close the room
+  set-level <room> <n>     move the game in the room to the level
+  broadcast <message>      show the message to every player";
+
+/// Admin command read from the server's standard input.
+enum Command {
+    Rooms,
+    Metrics,
+    Kick { room: String, player: usize },
+    Close { room: String },
+    SetLevel { room: String, level: usize },
+    Broadcast { message: String },
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mut args = rest.split_whitespace();
+        let mut arg = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing argument <{name}>"))
+        };
+        let number = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("expected a number, found {value:?}"))
+        };
+
+        let command = match name {
+            "rooms" => Self::Rooms,
+            "metrics" => Self::Metrics,
+            "kick" => Self::Kick {
+                room: arg("room")?.to_uppercase(),
+                player: number(arg("player")?)?,
+            },
+            "close" => Self::Close {
+                room: arg("room")?.to_uppercase(),
+            },
+            "set-level" => Self::SetLevel {
+                room: arg("room")?.to_uppercase(),
+                level: number(arg("n")?)?,
+            },
+            "broadcast" => {
+                let message = rest.trim();
+                if message.is_empty() {
+                    return Err("missing argument <message>".to_owned());
+                }
+                Self::Broadcast {
+                    message: message.to_owned(),
+                }
+            }
+            _ => return Err(format!("unknown command {name:?}")),
+        };
+        Ok(command)
+    }
+
+    fn execute(self, shared: &Shared) -> Result<String, String> {
+        let room = |code: &str| {
+            shared
+                .find_room(code)
+                .ok_or_else(|| format!("room {code} does not exist"))
+        };
+        let no_reply = |code: &str| format!("room {code} did not respond");
+        match self {
+            Self::Rooms => return Ok(describe_rooms(shared)),
+            Self::Metrics => return Ok(shared.current_metrics().to_prometheus()),
+            Self::Kick { room: code, player } => room(&code)?
+                .request(|reply| RoomCommand::Kick { player, reply })
+                .ok_or_else(|| no_reply(&code))??,
+            Self::Close { room: code } => room(&code)?
+                .request(RoomCommand::Close)
+                .ok_or_else(|| no_reply(&code))?,
+            Self::SetLevel { room: code, level } => room(&code)?
+                .request(|reply| RoomCommand::SetLevel { level, reply })
+                .ok_or_else(|| no_reply(&code))??,
+            Self::Broadcast { message } => shared.broadcast(&message),
+        }
+        Ok("Done".to_owned())
+    }
+}
+
+/// Human-readable overview of all rooms and their players.
+fn describe_rooms(shared: &Shared) -> String {
+    let descriptions: Vec<String> = shared
+        .room_handles()
+        .iter()
+        .filter_map(|room| room.request(RoomCommand::Describe))
+        .collect();
+    if descriptions.is_empty() {
+        return "No rooms".to_owned();
+    }
+    descriptions.join("\n")
+}
+
+/// Read admin commands from the standard input until it is closed.
+pub fn run_console(shared: Arc<Shared>) {
+    for line in std::io::stdin().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = Command::parse(&line).and_then(|command| command.execute(&shared));
+        match result {
+            Ok(output) => println!("{output}"),
+            Err(error) => println!("Error: {error}\n{HELP}"),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use geng::prelude::*;
+
+use std::io::Write;
+
+/// Room lifecycle events, written to the event log.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    RoomCreated {
+        room: &'a str,
+    },
+    PlayerJoined {
+        room: &'a str,
+        players: usize,
+    },
+    GameStarted {
+        room: &'a str,
+    },
+    SessionResumed {
+        room: &'a str,
+    },
+    LevelCompleted {
+        room: &'a str,
+        level: usize,
+        seconds: f64,
+    },
+    SolverCrashed {
+        room: &'a str,
+        message: &'a str,
+    },
+    PlayerKicked {
+        room: &'a str,
+    },
+    RoomClosed {
+        room: &'a str,
+    },
+    RoomExpired {
+        room: &'a str,
+    },
+    RoomsRestored {
+        count: usize,
+    },
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    /// Seconds since the unix epoch.
+    time: f64,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Structured log of events, one JSON object per line.
+#[derive(Default)]
+pub struct EventLog {
+    file: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl EventLog {
+    pub fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self {
+            file: Some(std::io::BufWriter::new(file)),
+        })
+    }
+
+    pub fn record(&mut self, event: Event) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64());
+        let entry = Entry {
+            time,
+            event: &event,
+        };
+        let result = serde_json::to_writer(&mut *file, &entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(writeln!(file)?));
+        if let Err(error) = result {
+            log::error!("Failed to write to the event log: {error}");
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(file) = &mut self.file
+            && let Err(error) = file.flush()
+        {
+            log::error!("Failed to flush the event log: {error}");
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    interop::{ClientMessage, PLAYER_SYNC_RATE, ServerError},
+    model::{DispatcherAction, SolverAction},
+};
+
+use geng::prelude::*;
+
+use std::time::{Duration, Instant};
+
+/// Longest accepted player token.
+const MAX_TOKEN_LEN: usize = 64;
+/// Longest accepted room code.
+const MAX_CODE_LEN: usize = 16;
+/// Longest accepted crash message.
+const MAX_CRASH_LEN: usize = 256;
+/// Longest accepted build identifier in the handshake.
+const MAX_BUILD_LEN: usize = 64;
+/// Most digits accepted in an entered puzzle code.
+const MAX_PUZZLE_CODE_LEN: usize = 16;
+/// Number of throttled messages within a window after which the client is disconnected.
+const MAX_VIOLATIONS: u32 = 100;
+/// Period over which the throttled messages are counted.
+const VIOLATION_WINDOW: Duration = Duration::from_secs(10);
+
+/// Why a message has been rejected.
+pub enum LimitViolation {
+    /// The message should be dropped.
+    Throttled,
+    /// The client should be disconnected.
+    Abusive(ServerError),
+}
+
+/// Per-client token buckets for every message type.
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+    /// Throttled messages in the current window.
+    violations: u32,
+    window_start: Instant,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            buckets: HashMap::new(),
+            violations: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    pub fn check(&mut self, message: &ClientMessage) -> Result<(), LimitViolation> {
+        if !is_size_ok(message) {
+            return Err(LimitViolation::Abusive(ServerError::MessageTooLong));
+        }
+
+        let kind = message.kind();
+        let (capacity, per_second) = rate_limit(kind);
+        let now = Instant::now();
+        let bucket = self.buckets.entry(kind).or_insert(TokenBucket {
+            tokens: capacity,
+            last_update: now,
+        });
+        let elapsed = now.duration_since(bucket.last_update).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.last_update = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if now.duration_since(self.window_start) > VIOLATION_WINDOW {
+            self.window_start = now;
+            self.violations = 0;
+        }
+        self.violations += 1;
+        if self.violations > MAX_VIOLATIONS {
+            Err(LimitViolation::Abusive(ServerError::TooManyRequests))
+        } else {
+            Err(LimitViolation::Throttled)
+        }
+    }
+}
+
+/// Burst capacity and sustained rate per second of the message type.
+fn rate_limit(kind: &str) -> (f64, f64) {
+    match kind {
+        "create_room" => (3.0, 0.2),
+        "hello" | "login" | "resume_session" | "join_room" | "spectate_room" => (5.0, 1.0),
+        "select_role" => (5.0, 2.0),
+        "crash_other" => (3.0, 1.0),
+        "skip_to_level" => (10.0, 5.0),
+        "solver_action" | "dispatcher_action" => (40.0, 20.0),
+        // Throttled by the client, twice the rate leaves room for network jitter
+        "sync_solver_player" => (2.0 * PLAYER_SYNC_RATE, 2.0 * PLAYER_SYNC_RATE),
+        _ => (20.0, 10.0),
+    }
+}
+
+fn is_size_ok(message: &ClientMessage) -> bool {
+    match message {
+        ClientMessage::Hello { build, .. } => build.len() <= MAX_BUILD_LEN,
+        ClientMessage::Login(token) | ClientMessage::ResumeSession(token) => {
+            token.len() <= MAX_TOKEN_LEN
+        }
+        ClientMessage::JoinRoom(code) | ClientMessage::SpectateRoom(code) => {
+            code.len() <= MAX_CODE_LEN
+        }
+        ClientMessage::CrashOther(message) => message.len() <= MAX_CRASH_LEN,
+        ClientMessage::SolverAction(SolverAction::EnterBubbleCode(code))
+        | ClientMessage::DispatcherAction(DispatcherAction::EnterLoginCode(code)) => {
+            code.len() <= MAX_PUZZLE_CODE_LEN
+        }
+        _ => true,
+    }
+}
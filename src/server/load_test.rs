@@ -0,0 +1,274 @@
+use crate::{
+    interop::*,
+    model::{DispatcherAction, GameRole, Player},
+};
+
+use geng::prelude::*;
+
+use std::{sync::mpsc, time::Duration};
+
+/// Time to wait for the server to respond during the setup of a game.
+const SETUP_TIMEOUT: f64 = 10.0;
+/// Delay between polling the connection for new messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// Interval between the dispatcher's actions, answered by the server with a sync.
+const ACTION_INTERVAL: f64 = 0.1;
+/// Interval between the solver's position updates, matching a 30 fps client.
+const SYNC_INTERVAL: f64 = 1.0 / 30.0;
+
+/// Statistics collected by the fake clients.
+#[derive(Default)]
+struct Report {
+    connected: usize,
+    failed: usize,
+    games_started: usize,
+    messages_received: u64,
+    /// Seconds between sending an action and receiving the resulting sync.
+    round_trips: Vec<f64>,
+}
+
+impl Report {
+    fn merge(&mut self, other: Report) {
+        self.connected += other.connected;
+        self.failed += other.failed;
+        self.games_started += other.games_started;
+        self.messages_received += other.messages_received;
+        self.round_trips.extend(other.round_trips);
+    }
+}
+
+/// Play games with pairs of fake clients against the server at `addr` and print the statistics.
+pub fn run(addr: &str, clients: usize, duration: Duration) {
+    let pairs = (clients / 2).max(1);
+    println!(
+        "Running {} fake clients against {addr} for {}s...",
+        pairs * 2,
+        duration.as_secs()
+    );
+
+    let mut threads = Vec::new();
+    for i in 0..pairs {
+        let (code_sender, code_receiver) = mpsc::channel();
+        let host_addr = addr.to_owned();
+        threads.push(
+            std::thread::Builder::new()
+                .name(format!("dispatcher-{i}"))
+                .spawn(move || run_dispatcher(&host_addr, code_sender, duration)),
+        );
+        let guest_addr = addr.to_owned();
+        threads.push(
+            std::thread::Builder::new()
+                .name(format!("solver-{i}"))
+                .spawn(move || run_solver(&guest_addr, code_receiver, duration)),
+        );
+    }
+
+    let mut report = Report::default();
+    for thread in threads {
+        match thread {
+            Ok(thread) => match thread.join() {
+                Ok(client_report) => report.merge(client_report),
+                Err(_) => report.failed += 1,
+            },
+            Err(error) => {
+                log::error!("Failed to spawn a fake client: {error}");
+                report.failed += 1;
+            }
+        }
+    }
+
+    report.round_trips.sort_by(f64::total_cmp);
+    let percentile = |p: f64| {
+        let rtt = &report.round_trips;
+        if rtt.is_empty() {
+            return 0.0;
+        }
+        let i = ((rtt.len() - 1) as f64 * p).round() as usize;
+        rtt[i] * 1000.0
+    };
+    println!("Connected clients: {}", report.connected);
+    println!("Failed clients: {}", report.failed);
+    println!("Started games: {}", report.games_started / 2);
+    println!(
+        "Received messages: {} ({:.0}/s)",
+        report.messages_received,
+        report.messages_received as f64 / duration.as_secs_f64()
+    );
+    println!(
+        "Action round trip: p50 {:.1}ms, p95 {:.1}ms, p99 {:.1}ms, max {:.1}ms ({} samples)",
+        percentile(0.5),
+        percentile(0.95),
+        percentile(0.99),
+        percentile(1.0),
+        report.round_trips.len()
+    );
+}
+
+/// Create a room, play as the dispatcher and measure the round trip of its actions.
+fn run_dispatcher(addr: &str, code_sender: mpsc::Sender<String>, duration: Duration) -> Report {
+    let Some(mut client) = FakeClient::connect(addr) else {
+        return Report {
+            failed: 1,
+            ..default()
+        };
+    };
+    client.connection.send(ClientMessage::CreateRoom(None));
+    let code = client.wait_for(SETUP_TIMEOUT, |message| match message {
+        ServerMessage::RoomJoined(room) => Some(room.code.clone()),
+        _ => None,
+    });
+    let Some(code) = code else {
+        log::warn!("The server did not create a room");
+        return client.fail();
+    };
+    let _ = code_sender.send(code);
+    if !client.start_game(GameRole::Dispatcher) {
+        return client.fail();
+    }
+
+    let timer = Timer::new();
+    let mut last_action = timer.elapsed().as_secs_f64();
+    let mut pending = std::collections::VecDeque::new();
+    while timer.elapsed().as_secs_f64() < duration.as_secs_f64() {
+        let now = timer.elapsed().as_secs_f64();
+        if now - last_action > ACTION_INTERVAL {
+            last_action = now;
+            pending.push_back(now);
+            client.connection.send(ClientMessage::DispatcherAction(
+                DispatcherAction::ToggleDoorSign,
+            ));
+        }
+        match client.poll() {
+            Some(ServerMessage::SyncFlags(_)) => {
+                if let Some(sent) = pending.pop_front() {
+                    let now = timer.elapsed().as_secs_f64();
+                    client.report.round_trips.push(now - sent);
+                }
+            }
+            Some(_) => {}
+            None => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+    client.report
+}
+
+/// Join the room created by the dispatcher and stream the player's position like the solver.
+fn run_solver(addr: &str, code_receiver: mpsc::Receiver<String>, duration: Duration) -> Report {
+    let Some(mut client) = FakeClient::connect(addr) else {
+        return Report {
+            failed: 1,
+            ..default()
+        };
+    };
+    let Ok(code) = code_receiver.recv_timeout(Duration::from_secs_f64(SETUP_TIMEOUT)) else {
+        return client.fail();
+    };
+    client.connection.send(ClientMessage::JoinRoom(code));
+    let joined = client.wait_for(SETUP_TIMEOUT, |message| match message {
+        ServerMessage::RoomJoined(_) => Some(()),
+        _ => None,
+    });
+    if joined.is_none() {
+        log::warn!("Failed to join the room");
+        return client.fail();
+    }
+    if !client.start_game(GameRole::Solver) {
+        return client.fail();
+    }
+
+    let player = Player::new();
+    let timer = Timer::new();
+    let mut last_sync = timer.elapsed().as_secs_f64();
+    while timer.elapsed().as_secs_f64() < duration.as_secs_f64() {
+        let now = timer.elapsed().as_secs_f64();
+        if now - last_sync > SYNC_INTERVAL {
+            last_sync = now;
+            client
+                .connection
+                .send(ClientMessage::SyncSolverPlayer(player.clone()));
+        }
+        if client.poll().is_none() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+    client.report
+}
+
+struct FakeClient {
+    connection: ClientConnection,
+    report: Report,
+}
+
+impl FakeClient {
+    fn connect(addr: &str) -> Option<Self> {
+        match futures::executor::block_on(ClientConnection::connect(addr)) {
+            Ok(connection) => Some(Self {
+                connection,
+                report: Report {
+                    connected: 1,
+                    ..default()
+                },
+            }),
+            Err(error) => {
+                log::warn!("Failed to connect: {error}");
+                None
+            }
+        }
+    }
+
+    fn fail(mut self) -> Report {
+        self.report.failed += 1;
+        self.report
+    }
+
+    /// Receive the next message, answering the heartbeat.
+    fn poll(&mut self) -> Option<ServerMessage> {
+        match self.connection.try_recv()? {
+            Ok(message) => {
+                self.report.messages_received += 1;
+                if let ServerMessage::Ping = message {
+                    self.connection.send(ClientMessage::Pong);
+                }
+                Some(message)
+            }
+            Err(error) => {
+                log::warn!("Failed to receive a message: {error}");
+                None
+            }
+        }
+    }
+
+    /// Receive messages until one of them is accepted or the time runs out.
+    fn wait_for<T>(
+        &mut self,
+        timeout: f64,
+        mut accept: impl FnMut(&ServerMessage) -> Option<T>,
+    ) -> Option<T> {
+        let timer = Timer::new();
+        while timer.elapsed().as_secs_f64() < timeout {
+            match self.poll() {
+                Some(message) => {
+                    if let Some(value) = accept(&message) {
+                        return Some(value);
+                    }
+                }
+                None => std::thread::sleep(POLL_INTERVAL),
+            }
+        }
+        None
+    }
+
+    fn start_game(&mut self, role: GameRole) -> bool {
+        self.connection.send(ClientMessage::SelectRole(role));
+        let started = self.wait_for(SETUP_TIMEOUT, |message| match message {
+            ServerMessage::StartGame(_) => Some(()),
+            _ => None,
+        });
+        if started.is_none() {
+            log::warn!("The game did not start");
+            return false;
+        }
+        self.report.games_started += 1;
+        true
+    }
+}
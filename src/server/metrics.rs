@@ -0,0 +1,131 @@
+use geng::prelude::*;
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+/// Upper bounds of the buckets for the level completion time, in seconds.
+const LEVEL_TIME_BUCKETS: [f64; 8] = [30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0];
+
+/// Counters and histograms describing the activity on the server.
+#[derive(Debug, Default, Serialize)]
+pub struct Metrics {
+    /// Clients that have completed the handshake.
+    pub connected_clients: usize,
+    pub active_rooms: usize,
+    /// Number of received messages by their type.
+    pub messages: BTreeMap<&'static str, u64>,
+    /// Time to complete each solver level, in seconds.
+    pub level_time: BTreeMap<usize, Histogram>,
+    /// Crashes of the solver caused by the puzzles.
+    pub solver_crashes: u64,
+    /// Crashes requested by the other player.
+    pub crash_others: u64,
+    /// Messages dropped by the rate limits.
+    pub throttled_messages: u64,
+    /// Clients disconnected for abusing the limits.
+    pub dropped_clients: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Histogram {
+    /// Cumulative counts of the observations below each of [`LEVEL_TIME_BUCKETS`].
+    pub buckets: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; LEVEL_TIME_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&mut self, value: f64) {
+        for (bucket, &bound) in self.buckets.iter_mut().zip(&LEVEL_TIME_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn count_message(&mut self, kind: &'static str) {
+        *self.messages.entry(kind).or_default() += 1;
+    }
+
+    pub fn level_completed(&mut self, level: usize, seconds: f64) {
+        self.level_time.entry(level).or_default().observe(seconds);
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE ded_end_connected_clients gauge");
+        let _ = writeln!(out, "ded_end_connected_clients {}", self.connected_clients);
+        let _ = writeln!(out, "# TYPE ded_end_active_rooms gauge");
+        let _ = writeln!(out, "ded_end_active_rooms {}", self.active_rooms);
+
+        let _ = writeln!(out, "# TYPE ded_end_messages_total counter");
+        for (kind, count) in &self.messages {
+            let _ = writeln!(out, "ded_end_messages_total{{type=\"{kind}\"}} {count}");
+        }
+
+        let _ = writeln!(out, "# TYPE ded_end_level_seconds histogram");
+        for (level, histogram) in &self.level_time {
+            for (count, bound) in histogram.buckets.iter().zip(LEVEL_TIME_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "ded_end_level_seconds_bucket{{level=\"{level}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "ded_end_level_seconds_bucket{{level=\"{level}\",le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "ded_end_level_seconds_sum{{level=\"{level}\"}} {}",
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "ded_end_level_seconds_count{{level=\"{level}\"}} {}",
+                histogram.count
+            );
+        }
+
+        let _ = writeln!(out, "# TYPE ded_end_solver_crashes_total counter");
+        let _ = writeln!(out, "ded_end_solver_crashes_total {}", self.solver_crashes);
+        let _ = writeln!(out, "# TYPE ded_end_crash_others_total counter");
+        let _ = writeln!(out, "ded_end_crash_others_total {}", self.crash_others);
+        let _ = writeln!(out, "# TYPE ded_end_throttled_messages_total counter");
+        let _ = writeln!(
+            out,
+            "ded_end_throttled_messages_total {}",
+            self.throttled_messages
+        );
+        let _ = writeln!(out, "# TYPE ded_end_dropped_clients_total counter");
+        let _ = write!(
+            out,
+            "ded_end_dropped_clients_total {}",
+            self.dropped_clients
+        );
+        out
+    }
+
+    /// Overwrite the file with a JSON dump of the metrics.
+    pub fn dump(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
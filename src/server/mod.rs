@@ -1,28 +1,66 @@
 mod connection;
-mod state;
+mod console;
+mod events;
+mod limits;
+mod metrics;
+mod room;
+mod shared;
+mod storage;
 
-use self::{connection::ClientConnection, state::*};
+pub mod load_test;
+
+use self::{connection::ClientConnection, shared::Shared};
 
 use crate::interop::*;
 
 use geng::prelude::*;
 
+pub struct ServerConfig {
+    /// Accept test-only requests, like skipping levels.
+    pub test: bool,
+    /// Directory to persist ongoing games in, so they survive a restart.
+    pub data_dir: Option<std::path::PathBuf>,
+    /// Time without any connected players after which a room is closed.
+    pub room_ttl: std::time::Duration,
+    /// Read admin commands from the standard input.
+    pub console: bool,
+    /// File to periodically dump the metrics into as JSON.
+    pub metrics_file: Option<std::path::PathBuf>,
+    /// File to append the room events to as JSON lines.
+    pub event_log: Option<std::path::PathBuf>,
+    /// Directories of the level packs that rooms can be created with.
+    pub level_packs: Vec<std::path::PathBuf>,
+}
+
 pub struct App {
-    state: Arc<Mutex<ServerState>>,
+    shared: Arc<Shared>,
     #[allow(dead_code)]
     background_thread: std::thread::JoinHandle<()>,
 }
 
 impl App {
-    pub fn new(test: bool) -> Self {
-        let state = Arc::new(Mutex::new(ServerState::new(test)));
+    /// Interval in seconds between writing out the events and the metrics.
+    const FLUSH_INTERVAL: f64 = 10.0;
+
+    pub fn new(config: ServerConfig) -> Self {
+        let console = config.console;
+        let shared = Shared::new(config);
+        if console {
+            let shared = shared.clone();
+            std::thread::spawn(move || console::run_console(shared));
+        }
         Self {
-            state: state.clone(),
+            shared: shared.clone(),
             background_thread: std::thread::spawn(move || {
+                let mut flush_timer = Timer::new();
                 loop {
-                    state.lock().unwrap().tick();
+                    shared.ping_clients();
+                    if flush_timer.elapsed().as_secs_f64() > Self::FLUSH_INTERVAL {
+                        flush_timer.reset();
+                        shared.flush();
+                    }
                     std::thread::sleep(std::time::Duration::from_secs_f32(
-                        1.0 / ServerState::TICKS_PER_SECOND,
+                        1.0 / Shared::TICKS_PER_SECOND,
                     ));
                 }
             }),
@@ -30,6 +68,13 @@ impl App {
     }
 }
 
+impl Drop for App {
+    fn drop(&mut self) {
+        self.shared.save_rooms();
+        self.shared.flush();
+    }
+}
+
 impl geng::net::server::App for App {
     type Client = ClientConnection;
 
@@ -38,11 +83,6 @@ impl geng::net::server::App for App {
     type ClientMessage = ClientMessage;
 
     fn connect(&mut self, sender: Box<dyn geng::net::Sender<Self::ServerMessage>>) -> Self::Client {
-        let mut state = self.state.lock().unwrap();
-        let my_id = state.client_connect(sender);
-        ClientConnection {
-            id: my_id,
-            state: self.state.clone(),
-        }
+        ClientConnection::new(self.shared.clone(), sender)
     }
 }
@@ -0,0 +1,95 @@
+use super::*;
+
+use std::fmt::Write as _;
+
+impl Room {
+    /// Human-readable overview of the room and its players.
+    pub fn describe(&self) -> String {
+        let code = &self.code;
+        let idle = self.last_active.elapsed().map_or(0, |idle| idle.as_secs());
+        let status = match &self.state {
+            RoomState::RoleSelection { .. } => "role selection".to_owned(),
+            RoomState::Game(state) => format!(
+                "level {} ({} completed)",
+                state.solver.current_level, state.solver.levels_completed
+            ),
+        };
+        let mut out = format!(
+            "{code}: {status}, {} spectators, idle for {idle}s",
+            self.spectators.len()
+        );
+        for (i, player) in self.players.iter().enumerate() {
+            let role = match (&self.state, &player.client) {
+                (RoomState::RoleSelection { roles }, Some(client)) => {
+                    roles.get(&client.id).copied()
+                }
+                (RoomState::RoleSelection { .. }, None) => None,
+                (RoomState::Game(state), _) => state.roles.get(&player.token).copied(),
+            };
+            let role = role.map_or("no role".to_owned(), |role| format!("{role:?}"));
+            let connection = if player.client.is_some() {
+                "connected"
+            } else {
+                "disconnected"
+            };
+            let _ = write!(out, "\n  player {i}: {role}, {connection}");
+        }
+        out
+    }
+
+    /// Remove a player from the room, identified by the index shown in `describe`.
+    /// During a game the role of the player is left for the next client joining the room.
+    pub fn kick(&mut self, player: usize) -> Result<(), String> {
+        let code = &self.code;
+        if player >= self.players.len() {
+            return Err(format!("room {code} has no player {player}"));
+        }
+
+        let Player { token, client } = self.players.remove(player);
+        self.kicked.insert(token.clone());
+        match &mut self.state {
+            RoomState::RoleSelection { roles } => {
+                if let Some(client) = &client {
+                    roles.remove(&client.id);
+                }
+            }
+            RoomState::Game(state) => {
+                state.roles.remove(&token);
+                self.shared.remove_session(&token);
+            }
+        }
+        if let Some(client) = client {
+            client.leave_room(code);
+            client.send(ServerMessage::Error(ServerError::Kicked));
+        }
+        for client in self
+            .players
+            .iter()
+            .filter_map(|player| player.client.as_ref())
+        {
+            client.send(ServerMessage::SyncRoomPlayers(self.players.len()));
+        }
+        log::info!("Kicked player {player} from room {code}");
+        self.shared.record(Event::PlayerKicked { room: code });
+        Ok(())
+    }
+
+    /// Close the room, removing everyone from it.
+    pub fn close_by_admin(&mut self) {
+        for client in self.close() {
+            client.send(ServerMessage::Error(ServerError::RoomClosed));
+        }
+        log::info!("Closed room {}", self.code);
+        self.shared.record(Event::RoomClosed { room: &self.code });
+    }
+
+    /// Move the ongoing game to the given level.
+    pub fn set_level(&mut self, level: usize) -> Result<(), String> {
+        let levels = self.levels.clone();
+        if !self.update_game(|state| state.skip_to_level(level, &levels)) {
+            return Err(format!("room {} has not started the game yet", self.code));
+        }
+        log::info!("Set level {level} in room {}", self.code);
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use super::*;
+
+use crate::{
+    assets::{DispatcherItem, SolverLevel},
+    model::{
+        DispatcherAction, Flag, FlagValue, Flags, Player, SolverAction, TriggerAction, TriggerEvent,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoomGameState {
+    /// Roles of the players, identified by their tokens.
+    pub roles: HashMap<String, GameRole>,
+    /// Puzzle flags shared by both roles.
+    #[serde(default = "Flags::new")]
+    pub flags: Flags,
+    pub solver: SolverState,
+    /// Number of buttons the dispatcher has pressed since the solver entered the current level.
+    #[serde(default, alias = "bubble_buttons")]
+    pub level_buttons: usize,
+    /// Last known state of the solver's player and the level it is on, restored on session resume.
+    #[serde(default)]
+    pub level_player: Option<(usize, Player)>,
+    /// When the solver entered the current level.
+    #[serde(default = "std::time::SystemTime::now")]
+    pub level_started: std::time::SystemTime,
+}
+
+/// Changes caused by an action that have to be propagated to the room.
+#[derive(Debug, Default)]
+pub struct ActionEffects {
+    pub sync_solver: bool,
+    pub sync_flags: bool,
+    pub crash_solver: Option<String>,
+    /// Level completed by the solver and the time it took in seconds.
+    pub completed_level: Option<(usize, f64)>,
+}
+
+impl ActionEffects {
+    fn merge(&mut self, other: Self) {
+        self.sync_solver |= other.sync_solver;
+        self.sync_flags |= other.sync_flags;
+        self.crash_solver = self.crash_solver.take().or(other.crash_solver);
+        self.completed_level = self.completed_level.or(other.completed_level);
+    }
+}
+
+impl RoomGameState {
+    pub fn new(roles: HashMap<String, GameRole>) -> Self {
+        Self {
+            roles,
+            flags: Flags::new(),
+            solver: SolverState::new(),
+            level_buttons: 0,
+            level_player: None,
+            level_started: std::time::SystemTime::now(),
+        }
+    }
+
+    /// Last known state of the solver's player, if it is on the current level.
+    pub fn current_player(&self) -> Option<&Player> {
+        self.level_player
+            .as_ref()
+            .filter(|(level, _)| *level == self.solver.current_level)
+            .map(|(_, player)| player)
+    }
+
+    pub fn skip_to_level(&mut self, level: usize, levels: &[SolverLevel]) -> ActionEffects {
+        self.solver.current_level = level;
+        self.solver.levels_completed = level;
+        let mut effects = self.enter_level(levels);
+        effects.sync_solver = true;
+        effects
+    }
+
+    /// Start the solver's current level over.
+    fn enter_level(&mut self, levels: &[SolverLevel]) -> ActionEffects {
+        self.level_started = std::time::SystemTime::now();
+        self.level_buttons = 0;
+        self.solver.checkpoint = None;
+        self.level_player = None;
+        self.fire(TriggerEvent::Enter, levels)
+    }
+
+    /// Apply the actions of the current level's triggers that fire on the event.
+    fn fire(&mut self, event: TriggerEvent, levels: &[SolverLevel]) -> ActionEffects {
+        let mut effects = ActionEffects::default();
+        let Some(level) = levels.get(self.solver.current_level) else {
+            return effects;
+        };
+        let actions: Vec<TriggerAction> = level
+            .triggers
+            .iter()
+            .filter(|trigger| trigger.fires(&event, &self.solver, &self.flags))
+            .flat_map(|trigger| trigger.then.iter().cloned())
+            .collect();
+        for action in actions {
+            match action {
+                TriggerAction::OpenExit => {
+                    if !self.solver.is_exit_open() {
+                        self.solver.levels_completed = self.solver.current_level + 1;
+                        effects.sync_solver = true;
+                    }
+                }
+                TriggerAction::SetFlag(name) => {
+                    effects.sync_flags |= self.flags.set_value(&name, FlagValue::Bool(true));
+                }
+                TriggerAction::ClearFlag(name) => {
+                    effects.sync_flags |= self.flags.set_value(&name, FlagValue::Bool(false));
+                }
+                TriggerAction::SetValue(name, value) => {
+                    effects.sync_flags |= self.flags.set_value(&name, value);
+                }
+                TriggerAction::CrashSolver(message) => effects.crash_solver = Some(message),
+                // Handled by the solver's client
+                TriggerAction::PlayMusic(_)
+                | TriggerAction::SpawnWall { .. }
+                | TriggerAction::SpawnBubbles { .. } => {}
+            }
+        }
+        effects
+    }
+
+    pub fn apply_solver_action(
+        &mut self,
+        action: SolverAction,
+        levels: &[SolverLevel],
+    ) -> ActionEffects {
+        match action {
+            SolverAction::ExitLevel(level) => {
+                let solver = &mut self.solver;
+                if solver.current_level != level || !solver.is_exit_open() {
+                    return ActionEffects::default();
+                }
+                solver.current_level += 1;
+                let seconds = self
+                    .level_started
+                    .elapsed()
+                    .map_or(0.0, |time| time.as_secs_f64());
+                let mut effects = self.enter_level(levels);
+                effects.sync_solver = true;
+                effects.completed_level = Some((level, seconds));
+                effects
+            }
+            SolverAction::ReachCheckpoint { level, checkpoint } => {
+                let solver = &mut self.solver;
+                let exists = levels
+                    .get(level)
+                    .is_some_and(|level| checkpoint < level.checkpoints.len());
+                // Checkpoints only move forward
+                if solver.current_level != level
+                    || !exists
+                    || solver
+                        .checkpoint
+                        .is_some_and(|current| current >= checkpoint)
+                {
+                    return ActionEffects::default();
+                }
+                solver.checkpoint = Some(checkpoint);
+                ActionEffects {
+                    sync_solver: true,
+                    ..default()
+                }
+            }
+            SolverAction::EnterBubbleCode(code) => {
+                self.fire(TriggerEvent::BubbleCode(code), levels)
+            }
+            SolverAction::Explosion => self.fire(TriggerEvent::Explosion, levels),
+            SolverAction::GrandpaDrilled => self.fire(TriggerEvent::GrandpaDrilled, levels),
+        }
+    }
+
+    pub fn apply_dispatcher_action(
+        &mut self,
+        action: DispatcherAction,
+        levels: &[SolverLevel],
+    ) -> ActionEffects {
+        let mut effects = ActionEffects::default();
+        let flags = &mut self.flags;
+        match action {
+            DispatcherAction::ToggleDoorSign => {
+                flags.toggle(Flag::DOOR_SIGN_OPEN);
+                effects.sync_flags = true;
+            }
+            DispatcherAction::ToggleButtonStation => {
+                flags.toggle(Flag::BUTTON_STATION_OPEN);
+                effects.sync_flags = true;
+            }
+            DispatcherAction::EnterLoginCode(code) => {
+                if code == [6, 6, 6] {
+                    effects.sync_flags = flags.set(Flag::MONITOR_UNLOCKED, true);
+                }
+            }
+            DispatcherAction::PressButton(item) => {
+                if item.is_button() && flags.get(Flag::BUTTON_STATION_OPEN) {
+                    effects = self.press_button(item, levels);
+                }
+            }
+            DispatcherAction::PopSolver => {
+                effects.sync_flags = flags.set(Flag::POPPED, true);
+            }
+        }
+        effects
+    }
+
+    fn press_button(&mut self, item: DispatcherItem, levels: &[SolverLevel]) -> ActionEffects {
+        self.level_buttons += 1;
+        let mut effects = self.fire(TriggerEvent::ButtonPresses(self.level_buttons), levels);
+        effects.merge(self.fire(TriggerEvent::ButtonPressed(item), levels));
+        effects
+    }
+}
@@ -0,0 +1,697 @@
+mod admin;
+mod game;
+
+pub use self::game::*;
+
+use super::{events::*, shared::*, storage::*, *};
+
+use crate::{
+    assets::SolverLevel,
+    model::{GameRole, SolverState},
+};
+
+use std::sync::mpsc;
+
+/// Commands processed by the room actor, in the order they were sent.
+pub enum RoomCommand {
+    /// The client wants to join the room as the player with the token.
+    Join {
+        client: ClientHandle,
+        token: String,
+    },
+    Spectate {
+        client: ClientHandle,
+    },
+    /// The client has reconnected and continues the game as the player with the token.
+    Resume {
+        client: ClientHandle,
+        token: String,
+    },
+    /// A message from a client in the room.
+    Message {
+        client: ClientId,
+        message: ClientMessage,
+    },
+    /// The client has disconnected.
+    Leave {
+        client: ClientId,
+    },
+    Describe(mpsc::Sender<String>),
+    Kick {
+        player: usize,
+        reply: mpsc::Sender<Result<(), String>>,
+    },
+    Close(mpsc::Sender<()>),
+    SetLevel {
+        level: usize,
+        reply: mpsc::Sender<Result<(), String>>,
+    },
+    Save(mpsc::Sender<()>),
+}
+
+pub struct Player {
+    pub token: String,
+    /// `None` while the player is disconnected.
+    pub client: Option<ClientHandle>,
+}
+
+pub enum RoomState {
+    RoleSelection { roles: HashMap<ClientId, GameRole> },
+    Game(RoomGameState),
+}
+
+/// Actor owning a single room, running on its own thread.
+pub struct Room {
+    shared: Arc<Shared>,
+    handle: RoomHandle,
+    commands: mpsc::Receiver<RoomCommand>,
+    pub code: Arc<str>,
+    /// Level pack the room is played with, `None` for the shipped levels.
+    pub pack: Option<String>,
+    /// Levels of the pack, whose triggers drive the progression.
+    levels: Arc<Vec<SolverLevel>>,
+    pub players: Vec<Player>,
+    /// Tokens of the players kicked by the admin, who cannot join again.
+    kicked: HashSet<String>,
+    pub spectators: Vec<ClientHandle>,
+    pub state: RoomState,
+    /// Last time any of the players was connected.
+    pub last_active: std::time::SystemTime,
+    tick_timer: Timer,
+    save_timer: Timer,
+    closed: bool,
+}
+
+impl Room {
+    /// Interval in seconds between saving the room to the storage.
+    const SAVE_INTERVAL: f64 = 10.0;
+
+    pub fn new(shared: Arc<Shared>, code: Arc<str>, pack: Option<String>) -> Self {
+        let (sender, commands) = mpsc::channel();
+        let levels = shared.pack_levels(&pack).unwrap_or_else(|| {
+            log::error!("Room {code} uses the level pack {pack:?} that is not loaded");
+            Arc::new(Vec::new())
+        });
+        Self {
+            shared,
+            handle: RoomHandle::new(code.clone(), sender),
+            commands,
+            code,
+            pack,
+            levels,
+            players: vec![],
+            kicked: HashSet::new(),
+            spectators: vec![],
+            state: RoomState::RoleSelection {
+                roles: HashMap::new(),
+            },
+            last_active: std::time::SystemTime::now(),
+            tick_timer: Timer::new(),
+            save_timer: Timer::new(),
+            closed: false,
+        }
+    }
+
+    pub fn restore(shared: Arc<Shared>, snapshot: RoomSnapshot) -> Self {
+        let mut room = Self::new(shared, snapshot.code.into(), snapshot.pack);
+        room.players = snapshot
+            .players
+            .into_iter()
+            .map(|token| Player {
+                token,
+                client: None,
+            })
+            .collect();
+        room.state = RoomState::Game(snapshot.game);
+        room.last_active = snapshot.last_active;
+        room
+    }
+
+    pub fn with_player(mut self, client: ClientHandle, token: String) -> Self {
+        client.set_room(Some(self.handle.clone()));
+        client.send(ServerMessage::RoomJoined(RoomInfo {
+            code: self.code.to_string(),
+            players: self.players.len() + 1,
+            pack: self.pack.clone(),
+        }));
+        self.players.push(Player {
+            token,
+            client: Some(client),
+        });
+        self
+    }
+
+    /// Start the actor on a new thread.
+    pub fn spawn(self) -> RoomHandle {
+        let handle = self.handle.clone();
+        let result = std::thread::Builder::new()
+            .name(format!("room-{}", self.code))
+            .spawn(move || self.run());
+        if let Err(error) = result {
+            log::error!("Failed to start the room {}: {error}", handle.code);
+        }
+        handle
+    }
+
+    fn run(mut self) {
+        let tick = std::time::Duration::from_secs_f32(1.0 / Shared::TICKS_PER_SECOND);
+        while !self.closed {
+            match self.commands.recv_timeout(tick) {
+                Ok(command) => self.handle_command(command),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if self.tick_timer.elapsed().as_secs_f64() > tick.as_secs_f64() {
+                self.tick_timer.reset();
+                self.tick();
+            }
+        }
+
+        // Reject the commands that were sent before the room left the directory
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                RoomCommand::Join { client, .. } | RoomCommand::Spectate { client } => {
+                    client.leave_room(&self.code);
+                    client.send(ServerMessage::Error(ServerError::RoomNotFound));
+                }
+                RoomCommand::Resume { client, .. } => {
+                    client.send(ServerMessage::Error(ServerError::SessionNotFound));
+                }
+                RoomCommand::Kick { reply, .. } | RoomCommand::SetLevel { reply, .. } => {
+                    let _ = reply.send(Err(format!("room {} does not exist", self.code)));
+                }
+                RoomCommand::Close(reply) | RoomCommand::Save(reply) => {
+                    let _ = reply.send(());
+                }
+                RoomCommand::Message { .. } | RoomCommand::Leave { .. } => {}
+                RoomCommand::Describe(_) => {}
+            }
+        }
+        log::debug!("Room {} stopped", self.code);
+    }
+
+    fn handle_command(&mut self, command: RoomCommand) {
+        match command {
+            RoomCommand::Join { client, token } => self.join(client, token),
+            RoomCommand::Spectate { client } => self.spectate(client),
+            RoomCommand::Resume { client, token } => self.resume(client, token),
+            RoomCommand::Message { client, message } => self.handle_message(client, message),
+            RoomCommand::Leave { client } => self.leave(client),
+            RoomCommand::Describe(reply) => {
+                let _ = reply.send(self.describe());
+            }
+            RoomCommand::Kick { player, reply } => {
+                let _ = reply.send(self.kick(player));
+            }
+            RoomCommand::Close(reply) => {
+                self.close_by_admin();
+                let _ = reply.send(());
+            }
+            RoomCommand::SetLevel { level, reply } => {
+                let _ = reply.send(self.set_level(level));
+            }
+            RoomCommand::Save(reply) => {
+                self.save();
+                let _ = reply.send(());
+            }
+        }
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            code: self.code.to_string(),
+            players: self.players.len(),
+            pack: self.pack.clone(),
+        }
+    }
+
+    /// Only rooms with an ongoing game are worth persisting.
+    pub fn snapshot(&self) -> Option<RoomSnapshot> {
+        let RoomState::Game(game) = &self.state else {
+            return None;
+        };
+        Some(RoomSnapshot {
+            code: self.code.to_string(),
+            players: self
+                .players
+                .iter()
+                .map(|player| player.token.clone())
+                .collect(),
+            pack: self.pack.clone(),
+            game: game.clone(),
+            last_active: self.last_active,
+        })
+    }
+
+    fn is_expired(&self) -> bool {
+        match self.state {
+            RoomState::RoleSelection { .. } if self.players.is_empty() => true,
+            _ => self
+                .last_active
+                .elapsed()
+                .is_ok_and(|idle| idle > self.shared.room_ttl),
+        }
+    }
+
+    /// Messages that bring a spectator up to date with the ongoing game.
+    pub fn spectate_messages(&self) -> Vec<ServerMessage> {
+        let RoomState::Game(state) = &self.state else {
+            return vec![];
+        };
+        let mut messages = vec![
+            ServerMessage::SpectateGame(self.info()),
+            ServerMessage::SyncSolverState(state.solver.clone()),
+            ServerMessage::SyncFlags(state.flags.clone()),
+        ];
+        messages.extend(
+            state
+                .current_player()
+                .cloned()
+                .map(ServerMessage::SyncSolverPlayer),
+        );
+        messages
+    }
+
+    fn player_index(&self, client_id: ClientId) -> Option<usize> {
+        self.players.iter().position(|player| {
+            player
+                .client
+                .as_ref()
+                .is_some_and(|client| client.id == client_id)
+        })
+    }
+
+    /// Connected players other than the given client, and then the spectators.
+    fn others(&self, client_id: ClientId) -> impl Iterator<Item = &ClientHandle> {
+        self.players
+            .iter()
+            .filter_map(|player| player.client.as_ref())
+            .chain(&self.spectators)
+            .filter(move |client| client.id != client_id)
+    }
+
+    fn join(&mut self, client: ClientHandle, token: String) {
+        if self.kicked.contains(&token) {
+            client.send(ServerMessage::Error(ServerError::Kicked));
+            return;
+        }
+        match &self.state {
+            RoomState::RoleSelection { .. } => {
+                if self.players.len() >= 2 {
+                    client.send(ServerMessage::Error(ServerError::RoomFull));
+                    return;
+                }
+                // Roles are kept by the tokens once the game starts
+                if self.players.iter().any(|player| player.token == token) {
+                    client.send(ServerMessage::Error(ServerError::AlreadyInRoom));
+                    return;
+                }
+                self.players.push(Player {
+                    token,
+                    client: Some(client.clone()),
+                });
+                client.set_room(Some(self.handle.clone()));
+                client.send(ServerMessage::RoomJoined(self.info()));
+                self.shared.record(Event::PlayerJoined {
+                    room: &self.code,
+                    players: self.players.len(),
+                });
+                for player in &self.players {
+                    if let Some(other) = &player.client
+                        && other.id != client.id
+                    {
+                        other.send(ServerMessage::SyncRoomPlayers(self.players.len()));
+                    }
+                }
+            }
+            RoomState::Game(_) => self.rejoin(client, token),
+        }
+    }
+
+    /// Join the ongoing game back, or take over the role of a kicked player.
+    fn rejoin(&mut self, client: ClientHandle, token: String) {
+        let RoomState::Game(state) = &mut self.state else {
+            return;
+        };
+        let (role, took_over) = match state.roles.get(&token) {
+            Some(&role) => (role, false),
+            None => {
+                let Some(role) = [GameRole::Solver, GameRole::Dispatcher]
+                    .into_iter()
+                    .find(|role| !state.roles.values().any(|taken| taken == role))
+                else {
+                    client.send(ServerMessage::Error(ServerError::GameInProgress));
+                    return;
+                };
+                log::debug!(
+                    "Client {} took over {role:?} in room {}",
+                    client.id,
+                    self.code
+                );
+                state.roles.insert(token.clone(), role);
+                self.shared.add_sessions(&self.code, &[token.clone()]);
+                self.players.push(Player {
+                    token: token.clone(),
+                    client: None,
+                });
+                (role, true)
+            }
+        };
+        let solver = state.solver.clone();
+        let flags = state.flags.clone();
+
+        if let Some(player) = self.players.iter_mut().find(|player| player.token == token) {
+            player.client = Some(client.clone());
+        }
+        client.set_room(Some(self.handle.clone()));
+        client.send(ServerMessage::RoomJoined(self.info()));
+        client.send(ServerMessage::StartGame(role));
+        client.send(ServerMessage::SyncSolverState(solver));
+        client.send(ServerMessage::SyncFlags(flags));
+        if took_over {
+            for other in self.others(client.id) {
+                other.send(ServerMessage::SyncRoomPlayers(self.players.len()));
+            }
+        }
+    }
+
+    fn spectate(&mut self, client: ClientHandle) {
+        log::debug!("Client {} is spectating room {}", client.id, self.code);
+        if let RoomState::RoleSelection { .. } = self.state {
+            client.send(ServerMessage::RoomJoined(self.info()));
+        }
+        for message in self.spectate_messages() {
+            client.send(message);
+        }
+        self.spectators.push(client);
+    }
+
+    fn resume(&mut self, client: ClientHandle, token: String) {
+        let RoomState::Game(state) = &self.state else {
+            client.send(ServerMessage::Error(ServerError::SessionNotFound));
+            return;
+        };
+        let Some(&role) = state.roles.get(&token) else {
+            client.send(ServerMessage::Error(ServerError::SessionNotFound));
+            return;
+        };
+        let info = self.info();
+        let streamed = self
+            .levels
+            .get(state.solver.current_level)
+            .is_some_and(|level| level.stream_player);
+        let solver_player = state
+            .current_player()
+            .filter(|_| role == GameRole::Solver || streamed)
+            .map(|player| (state.solver.current_level, player.clone()));
+        if let Some(player) = self.players.iter_mut().find(|player| player.token == token) {
+            player.client = Some(client.clone());
+        }
+        log::debug!("Player {} resumed session in room {}", client.id, self.code);
+        self.shared
+            .record(Event::SessionResumed { room: &self.code });
+        client.set_room(Some(self.handle.clone()));
+        client.send(ServerMessage::YourToken(token));
+        client.send(ServerMessage::SessionResumed(SessionInfo {
+            room: info,
+            role,
+            solver: state.solver.clone(),
+            flags: state.flags.clone(),
+            solver_player,
+        }));
+    }
+
+    fn leave(&mut self, client_id: ClientId) {
+        self.spectators.retain(|client| client.id != client_id);
+        let Some(i) = self.player_index(client_id) else {
+            return;
+        };
+        match &mut self.state {
+            RoomState::RoleSelection { roles } => {
+                roles.remove(&client_id);
+                self.players.remove(i);
+            }
+            RoomState::Game(_) => {
+                self.players[i].client = None;
+            }
+        }
+    }
+
+    fn handle_message(&mut self, client_id: ClientId, message: ClientMessage) {
+        if self.spectators.iter().any(|client| client.id == client_id) {
+            log::debug!("Spectator {client_id} sent a message: {message:?}");
+            return;
+        }
+        let Some(player) = self.player_index(client_id) else {
+            log::debug!("Client {client_id} is not a player in room {}", self.code);
+            return;
+        };
+        match message {
+            ClientMessage::SelectRole(role) => self.select_role(client_id, role),
+            ClientMessage::SolverAction(action) => {
+                let levels = self.levels.clone();
+                self.handle_game_action(player, Some(GameRole::Solver), |state| {
+                    state.apply_solver_action(action, &levels)
+                });
+            }
+            ClientMessage::DispatcherAction(action) => {
+                let levels = self.levels.clone();
+                self.handle_game_action(player, Some(GameRole::Dispatcher), |state| {
+                    state.apply_dispatcher_action(action, &levels)
+                });
+            }
+            ClientMessage::SkipToLevel(level) => {
+                if self.shared.test {
+                    let levels = self.levels.clone();
+                    self.handle_game_action(player, None, |state| {
+                        state.skip_to_level(level, &levels)
+                    });
+                }
+            }
+            ClientMessage::SyncSolverPlayer(player) => {
+                let RoomState::Game(state) = &mut self.state else {
+                    return;
+                };
+                state.level_player = Some((state.solver.current_level, player.clone()));
+                // The dispatcher only sees the player on the levels streaming it to the monitor
+                let streamed = self
+                    .levels
+                    .get(state.solver.current_level)
+                    .is_some_and(|level| level.stream_player);
+                let players = self
+                    .players
+                    .iter()
+                    .filter_map(|player| player.client.as_ref())
+                    .filter(|client| streamed && client.id != client_id);
+                let message = ServerMessage::SyncSolverPlayer(player);
+                for client in players.chain(&self.spectators) {
+                    client.send(message.clone());
+                }
+            }
+            ClientMessage::CrashOther(message) => {
+                self.shared.metrics().crash_others += 1;
+                if let RoomState::Game(_) = &self.state {
+                    for player in &self.players {
+                        if let Some(client) = &player.client
+                            && client.id != client_id
+                        {
+                            client.send(ServerMessage::GameCrash(message.clone()));
+                        }
+                    }
+                }
+            }
+            _ => {
+                log::debug!("Room {} ignored a message: {message:?}", self.code);
+            }
+        }
+    }
+
+    fn select_role(&mut self, client_id: ClientId, role: GameRole) {
+        let RoomState::RoleSelection { roles } = &mut self.state else {
+            return;
+        };
+        log::debug!("Player {client_id} selected role {role:?}");
+        roles.insert(client_id, role);
+
+        if roles.len() == self.players.len() {
+            if roles.len() == 2 {
+                let roles_list: Vec<GameRole> = roles.values().copied().collect();
+                if roles_list[0] == roles_list[1] {
+                    // Select roles randomly
+                    let mut role = (GameRole::Dispatcher, GameRole::Solver);
+                    if thread_rng().gen_bool(0.5) {
+                        std::mem::swap(&mut role.0, &mut role.1);
+                    }
+                    for (role, player) in [role.0, role.1].into_iter().zip(roles.values_mut()) {
+                        *player = role;
+                    }
+                }
+
+                let roles = roles.clone();
+                self.state =
+                    RoomState::Game(RoomGameState::new(token_roles(&self.players, &roles)));
+                for client in self
+                    .players
+                    .iter()
+                    .filter_map(|player| player.client.as_ref())
+                {
+                    if let Some(&role) = roles.get(&client.id) {
+                        client.send(ServerMessage::StartGame(role));
+                    }
+                }
+            } else if self.shared.test && roles.len() == 1 {
+                let role = *roles.values().next().unwrap();
+                if let Some(client) = &self.players[0].client {
+                    let token_roles = token_roles(&self.players, roles);
+                    self.state = RoomState::Game(RoomGameState::new(token_roles));
+                    client.send(ServerMessage::StartGame(role));
+                }
+            }
+        }
+
+        if let RoomState::Game(_) = self.state {
+            let tokens: Vec<String> = self
+                .players
+                .iter()
+                .map(|player| player.token.clone())
+                .collect();
+            self.shared.add_sessions(&self.code, &tokens);
+            self.shared.record(Event::GameStarted { room: &self.code });
+            let messages = self.spectate_messages();
+            for client in &self.spectators {
+                for message in &messages {
+                    client.send(message.clone());
+                }
+            }
+        }
+    }
+
+    /// Apply an action of the player to the game state
+    /// and propagate its effects to the clients.
+    fn handle_game_action(
+        &mut self,
+        player: usize,
+        role: Option<GameRole>,
+        apply: impl FnOnce(&mut RoomGameState) -> ActionEffects,
+    ) {
+        let RoomState::Game(state) = &self.state else {
+            return;
+        };
+        if let Some(role) = role
+            && state.roles.get(&self.players[player].token) != Some(&role)
+        {
+            log::debug!(
+                "Player {player} in room {} sent an action for role {role:?} they do not have",
+                self.code
+            );
+            return;
+        }
+
+        self.update_game(apply);
+    }
+
+    /// Apply a change to the ongoing game and propagate its effects to the clients.
+    /// Returns `false` if there is no ongoing game in the room.
+    fn update_game(&mut self, apply: impl FnOnce(&mut RoomGameState) -> ActionEffects) -> bool {
+        let RoomState::Game(state) = &mut self.state else {
+            return false;
+        };
+
+        let effects = apply(state);
+        if let Some((level, seconds)) = effects.completed_level {
+            self.shared.metrics().level_completed(level, seconds);
+            self.shared.record(Event::LevelCompleted {
+                room: &self.code,
+                level,
+                seconds,
+            });
+        }
+        if let Some(message) = &effects.crash_solver {
+            self.shared.metrics().solver_crashes += 1;
+            self.shared.record(Event::SolverCrashed {
+                room: &self.code,
+                message,
+            });
+        }
+
+        let players = self
+            .players
+            .iter()
+            .filter_map(|player| Some((player.client.as_ref()?, Some(&player.token))));
+        let spectators = self.spectators.iter().map(|client| (client, None));
+        for (client, token) in players.chain(spectators) {
+            if effects.sync_solver {
+                client.send(ServerMessage::SyncSolverState(state.solver.clone()));
+            }
+            if effects.sync_flags {
+                client.send(ServerMessage::SyncFlags(state.flags.clone()));
+            }
+            if let Some(message) = &effects.crash_solver
+                && let Some(token) = token
+                && state.roles.get(token) == Some(&GameRole::Solver)
+            {
+                client.send(ServerMessage::GameCrash(message.clone()));
+            }
+        }
+        true
+    }
+
+    fn tick(&mut self) {
+        if self.players.iter().any(|player| player.client.is_some()) {
+            self.last_active = std::time::SystemTime::now();
+        }
+
+        if self.is_expired() {
+            log::debug!("Room {} expired", self.code);
+            self.shared.record(Event::RoomExpired { room: &self.code });
+            self.close();
+            return;
+        }
+
+        if self.save_timer.elapsed().as_secs_f64() > Self::SAVE_INTERVAL {
+            self.save_timer.reset();
+            self.save();
+        }
+    }
+
+    /// Remove the room from the directory and the storage and stop the actor.
+    /// Returns the clients that were in the room.
+    fn close(&mut self) -> Vec<ClientHandle> {
+        self.closed = true;
+        self.shared.remove_room(&self.code);
+        if let Some(storage) = &self.shared.storage {
+            storage.remove_room(&self.code);
+        }
+
+        let players = self.players.drain(..).filter_map(|player| player.client);
+        let clients: Vec<ClientHandle> = players.chain(self.spectators.drain(..)).collect();
+        for client in &clients {
+            client.leave_room(&self.code);
+        }
+        clients
+    }
+
+    /// Write a snapshot of the ongoing game to the storage, if there is one.
+    fn save(&self) {
+        if let Some(storage) = &self.shared.storage
+            && let Some(snapshot) = self.snapshot()
+            && let Err(error) = storage.save_room(&snapshot)
+        {
+            log::error!("Failed to save room {}: {error}", snapshot.code);
+        }
+    }
+}
+
+/// Convert roles of the connected clients into roles of the players' tokens.
+fn token_roles(
+    players: &[Player],
+    roles: &HashMap<ClientId, GameRole>,
+) -> HashMap<String, GameRole> {
+    players
+        .iter()
+        .filter_map(|player| {
+            let id = player.client.as_ref()?.id;
+            roles.get(&id).map(|&role| (player.token.clone(), role))
+        })
+        .collect()
+}
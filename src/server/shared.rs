@@ -0,0 +1,339 @@
+use super::{events::*, metrics::*, room::*, storage::*, *};
+
+use crate::assets::{LevelPack, SolverLevel, default_levels_dir, load_levels};
+
+use geng::prelude::rand::prelude::Distribution;
+
+use std::sync::{
+    MutexGuard,
+    atomic::{AtomicBool, AtomicI64, Ordering},
+    mpsc,
+};
+
+/// Time to wait for a room to reply to an admin request.
+const REPLY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Connected client, shared between its connection and the room it is in.
+#[derive(Clone)]
+pub struct ClientHandle {
+    pub id: ClientId,
+    sender: Arc<Mutex<Box<dyn geng::net::Sender<ServerMessage>>>>,
+    /// Set once the client has been dropped, nothing is sent to it anymore.
+    closed: Arc<AtomicBool>,
+    room: Arc<Mutex<Option<RoomHandle>>>,
+}
+
+impl ClientHandle {
+    pub fn new(id: ClientId, sender: Box<dyn geng::net::Sender<ServerMessage>>) -> Self {
+        Self {
+            id,
+            sender: Arc::new(Mutex::new(sender)),
+            closed: Arc::new(AtomicBool::new(false)),
+            room: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn send(&self, message: ServerMessage) {
+        if self.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        self.sender.lock().unwrap().send(message);
+    }
+
+    /// Stop sending anything to the client.
+    /// geng::net cannot close the socket from the server side, so it stays open
+    /// until the client closes it after receiving a fatal error.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn room(&self) -> Option<RoomHandle> {
+        self.room.lock().unwrap().clone()
+    }
+
+    pub fn set_room(&self, room: Option<RoomHandle>) {
+        *self.room.lock().unwrap() = room;
+    }
+
+    /// Forget the room, unless the client has already moved to another one.
+    pub fn leave_room(&self, code: &str) {
+        let mut room = self.room.lock().unwrap();
+        if room.as_ref().is_some_and(|room| *room.code == *code) {
+            *room = None;
+        }
+    }
+}
+
+/// Queue of commands to the actor running the room.
+#[derive(Clone)]
+pub struct RoomHandle {
+    pub code: Arc<str>,
+    sender: mpsc::Sender<RoomCommand>,
+}
+
+impl RoomHandle {
+    pub fn new(code: Arc<str>, sender: mpsc::Sender<RoomCommand>) -> Self {
+        Self { code, sender }
+    }
+
+    /// Returns `false` if the room has already been closed.
+    pub fn send(&self, command: RoomCommand) -> bool {
+        self.sender.send(command).is_ok()
+    }
+
+    /// Send a command and wait for the room to reply to it.
+    pub fn request<T>(&self, command: impl FnOnce(mpsc::Sender<T>) -> RoomCommand) -> Option<T> {
+        let (sender, receiver) = mpsc::channel();
+        if !self.send(command(sender)) {
+            return None;
+        }
+        receiver.recv_timeout(REPLY_TIMEOUT).ok()
+    }
+}
+
+/// State shared by the connections and the room actors.
+/// Every lock is only held for a quick lookup, never while handling a message.
+pub struct Shared {
+    pub test: bool,
+    pub room_ttl: std::time::Duration,
+    pub storage: Option<Storage>,
+    /// Levels of the solver by their pack, whose triggers drive the progression.
+    /// The shipped levels are under `None`.
+    packs: HashMap<Option<String>, Arc<Vec<SolverLevel>>>,
+    next_id: AtomicI64,
+    /// Directory of the open rooms by their code.
+    rooms: Mutex<HashMap<Arc<str>, RoomHandle>>,
+    /// Rooms with an ongoing game by the tokens of their players, to resume sessions.
+    sessions: Mutex<HashMap<String, Arc<str>>>,
+    /// Clients that have completed the handshake.
+    clients: Mutex<HashMap<ClientId, ClientHandle>>,
+    metrics: Mutex<Metrics>,
+    metrics_file: Option<std::path::PathBuf>,
+    events: Mutex<EventLog>,
+}
+
+impl Shared {
+    pub const TICKS_PER_SECOND: f32 = 2.0;
+
+    pub fn new(config: ServerConfig) -> Arc<Self> {
+        let storage = config.data_dir.and_then(|dir| match Storage::new(&dir) {
+            Ok(storage) => Some(storage),
+            Err(error) => {
+                log::error!("Failed to open the data directory {dir:?}: {error}");
+                None
+            }
+        });
+        let events = config
+            .event_log
+            .and_then(|path| match EventLog::open(&path) {
+                Ok(events) => Some(events),
+                Err(error) => {
+                    log::error!("Failed to open the event log {path:?}: {error}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let levels_dir = default_levels_dir();
+        let levels = match load_levels(&levels_dir) {
+            Ok(levels) => {
+                log::info!("Loaded {} levels", levels.len());
+                levels
+            }
+            Err(error) => {
+                log::error!("Failed to load the levels from {levels_dir:?}: {error:#}");
+                Vec::new()
+            }
+        };
+        let mut packs = HashMap::new();
+        packs.insert(None, Arc::new(levels));
+        for dir in &config.level_packs {
+            match LevelPack::load(dir) {
+                Ok(pack) => {
+                    log::info!(
+                        "Loaded the level pack {} with {} levels",
+                        pack.name,
+                        pack.levels.len()
+                    );
+                    packs.insert(Some(pack.name), Arc::new(pack.levels));
+                }
+                Err(error) => {
+                    log::error!("Failed to load the level pack from {dir:?}: {error:#}");
+                }
+            }
+        }
+
+        let shared = Arc::new(Self {
+            test: config.test,
+            room_ttl: config.room_ttl,
+            storage,
+            packs,
+            next_id: AtomicI64::new(1),
+            rooms: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            clients: Mutex::new(HashMap::new()),
+            metrics: Mutex::new(Metrics::default()),
+            metrics_file: config.metrics_file,
+            events: Mutex::new(events),
+        });
+        shared.restore_rooms();
+        shared
+    }
+
+    /// Start the actors of the rooms saved in the storage.
+    fn restore_rooms(self: &Arc<Self>) {
+        let snapshots = self
+            .storage
+            .iter()
+            .flat_map(Storage::load_rooms)
+            .collect::<Vec<_>>();
+        if snapshots.is_empty() {
+            return;
+        }
+
+        let count = snapshots.len();
+        for snapshot in snapshots {
+            let code: Arc<str> = snapshot.code.as_str().into();
+            self.add_sessions(&code, &snapshot.players);
+            let handle = Room::restore(self.clone(), snapshot).spawn();
+            self.rooms.lock().unwrap().insert(code, handle);
+        }
+        log::info!("Restored {count} rooms");
+        self.record(Event::RoomsRestored { count });
+    }
+
+    pub fn next_client_id(&self) -> ClientId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn register_client(&self, client: ClientHandle) {
+        self.clients.lock().unwrap().insert(client.id, client);
+    }
+
+    pub fn unregister_client(&self, client_id: ClientId) {
+        self.clients.lock().unwrap().remove(&client_id);
+    }
+
+    /// Levels of the pack, if the server has it.
+    pub fn pack_levels(&self, pack: &Option<String>) -> Option<Arc<Vec<SolverLevel>>> {
+        self.packs.get(pack).cloned()
+    }
+
+    /// Open a new room with a random code, with the client as its first player.
+    pub fn create_room(
+        self: &Arc<Self>,
+        client: &ClientHandle,
+        token: &str,
+        pack: Option<String>,
+    ) -> Option<RoomHandle> {
+        let mut rooms = self.rooms.lock().unwrap();
+        for _ in 0..10 {
+            let code: String = rand::distributions::Uniform::new_inclusive('A', 'Z')
+                .sample_iter(&mut thread_rng())
+                .take(4)
+                .collect();
+            let code: Arc<str> = code.into();
+            if let std::collections::hash_map::Entry::Vacant(entry) = rooms.entry(code.clone()) {
+                let handle = Room::new(self.clone(), code.clone(), pack.clone())
+                    .with_player(client.clone(), token.to_owned())
+                    .spawn();
+                entry.insert(handle.clone());
+                drop(rooms);
+                self.record(Event::RoomCreated { room: &code });
+                return Some(handle);
+            }
+        }
+        None
+    }
+
+    pub fn find_room(&self, code: &str) -> Option<RoomHandle> {
+        self.rooms.lock().unwrap().get(code).cloned()
+    }
+
+    /// Room with an ongoing game that the player with the token takes part in.
+    pub fn find_session(&self, token: &str) -> Option<RoomHandle> {
+        let code = self.sessions.lock().unwrap().get(token).cloned()?;
+        self.find_room(&code)
+    }
+
+    /// Remove the room from the directory, so that no new clients can reach it.
+    pub fn remove_room(&self, code: &str) {
+        self.rooms.lock().unwrap().remove(code);
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, room| **room != *code);
+    }
+
+    pub fn add_sessions(&self, code: &Arc<str>, tokens: &[String]) {
+        let mut sessions = self.sessions.lock().unwrap();
+        for token in tokens {
+            sessions.insert(token.clone(), code.clone());
+        }
+    }
+
+    pub fn remove_session(&self, token: &str) {
+        self.sessions.lock().unwrap().remove(token);
+    }
+
+    /// All open rooms, sorted by their code.
+    pub fn room_handles(&self) -> Vec<RoomHandle> {
+        let mut rooms: Vec<RoomHandle> = self.rooms.lock().unwrap().values().cloned().collect();
+        rooms.sort_by(|a, b| a.code.cmp(&b.code));
+        rooms
+    }
+
+    pub fn metrics(&self) -> MutexGuard<'_, Metrics> {
+        self.metrics.lock().unwrap()
+    }
+
+    /// Metrics with the gauges brought up to date.
+    pub fn current_metrics(&self) -> MutexGuard<'_, Metrics> {
+        let connected_clients = self.clients.lock().unwrap().len();
+        let active_rooms = self.rooms.lock().unwrap().len();
+        let mut metrics = self.metrics();
+        metrics.connected_clients = connected_clients;
+        metrics.active_rooms = active_rooms;
+        metrics
+    }
+
+    pub fn record(&self, event: Event) {
+        self.events.lock().unwrap().record(event);
+    }
+
+    /// Heartbeat for the clients to detect a lost connection.
+    pub fn ping_clients(&self) {
+        for client in self.clients.lock().unwrap().values() {
+            client.send(ServerMessage::Ping);
+        }
+    }
+
+    /// Write out the buffered events and the current metrics.
+    pub fn flush(&self) {
+        self.events.lock().unwrap().flush();
+        if let Some(path) = &self.metrics_file
+            && let Err(error) = self.current_metrics().dump(path)
+        {
+            log::error!("Failed to write the metrics to {path:?}: {error}");
+        }
+    }
+
+    /// Ask every room to write its snapshot to the storage, if there is one.
+    pub fn save_rooms(&self) {
+        if self.storage.is_none() {
+            return;
+        }
+        for room in self.room_handles() {
+            if room.request(RoomCommand::Save).is_none() {
+                log::error!("Room {} did not respond to the save request", room.code);
+            }
+        }
+    }
+
+    /// Send a message to every connected client.
+    pub fn broadcast(&self, message: &str) {
+        for client in self.clients.lock().unwrap().values() {
+            client.send(ServerMessage::Announcement(message.to_owned()));
+        }
+        log::info!("Broadcasted: {message}");
+    }
+}
@@ -0,0 +1,81 @@
+use super::room::RoomGameState;
+
+use geng::prelude::*;
+
+use std::path::{Path, PathBuf};
+
+/// Snapshot of a room with an ongoing game.
+#[derive(Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub code: String,
+    /// Tokens of the players in the room.
+    pub players: Vec<String>,
+    /// Level pack of the room, snapshots of older versions only had the shipped levels.
+    #[serde(default)]
+    pub pack: Option<String>,
+    pub game: RoomGameState,
+    pub last_active: std::time::SystemTime,
+}
+
+/// Directory with the snapshots of the rooms, one JSON file per room.
+pub struct Storage {
+    rooms_dir: PathBuf,
+}
+
+impl Storage {
+    pub fn new(data_dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let rooms_dir = data_dir.as_ref().join("rooms");
+        std::fs::create_dir_all(&rooms_dir)?;
+        Ok(Self { rooms_dir })
+    }
+
+    /// Load all readable snapshots, logging the ones that failed to load.
+    pub fn load_rooms(&self) -> Vec<RoomSnapshot> {
+        let entries = match std::fs::read_dir(&self.rooms_dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                log::error!("Failed to read {:?}: {error}", self.rooms_dir);
+                return vec![];
+            }
+        };
+
+        let mut rooms = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let load = || -> anyhow::Result<RoomSnapshot> {
+                let file = std::fs::File::open(&path)?;
+                Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+            };
+            match load() {
+                Ok(room) => rooms.push(room),
+                Err(error) => log::error!("Failed to load room snapshot {path:?}: {error}"),
+            }
+        }
+        rooms
+    }
+
+    pub fn save_room(&self, room: &RoomSnapshot) -> anyhow::Result<()> {
+        let path = self.room_path(&room.code);
+        // Write to a temporary file first, so a crash never leaves a corrupted snapshot
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(room)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    pub fn remove_room(&self, code: &str) {
+        let path = self.room_path(code);
+        if let Err(error) = std::fs::remove_file(&path)
+            && error.kind() != std::io::ErrorKind::NotFound
+        {
+            log::error!("Failed to remove room snapshot {path:?}: {error}");
+        }
+    }
+
+    fn room_path(&self, code: &str) -> PathBuf {
+        self.rooms_dir.join(format!("{code}.json"))
+    }
+}
@@ -13,6 +13,8 @@ pub type Text = Rc<str>;
 
 /// Max distance that the cursor can travel for a click to register as a stationary one.
 const MAX_CLICK_DISTANCE: f32 = 5.0;
+/// Stick tilt needed to move the focus to the next widget.
+const GAMEPAD_NAVIGATION_DEADZONE: f32 = 0.5;
 
 #[derive(Clone)]
 pub struct UiContext {
@@ -57,13 +59,75 @@ impl UiContext {
     pub fn update(&mut self, delta_time: f32) {
         self.real_time += delta_time;
         self.delta_time = delta_time;
+        let gamepad_press = self.update_gamepad();
         let window = self.context.geng.window();
         self.cursor.update(
-            geng_utils::key::is_key_pressed(window, [geng::MouseButton::Left]),
+            geng_utils::key::is_key_pressed(window, [geng::MouseButton::Left]) || gamepad_press,
             geng_utils::key::is_key_pressed(window, [geng::MouseButton::Right]),
         );
     }
 
+    /// Move the cursor between the widgets with the gamepad.
+    /// Returns whether the gamepad holds the cursor pressed.
+    fn update_gamepad(&mut self) -> bool {
+        use crate::context::{AxisDirection::*, GamepadAxis::*, GamepadButton::*, GamepadInput};
+
+        let mut gamepads = self.context.gamepads.borrow_mut();
+        gamepads.poll();
+        let directions = [
+            (DPadUp, LeftStickY, Positive, vec2(0.0, 1.0)),
+            (DPadDown, LeftStickY, Negative, vec2(0.0, -1.0)),
+            (DPadLeft, LeftStickX, Negative, vec2(-1.0, 0.0)),
+            (DPadRight, LeftStickX, Positive, vec2(1.0, 0.0)),
+        ];
+        let direction = directions
+            .into_iter()
+            .find(|&(button, axis, axis_direction, _)| {
+                gamepads.is_just_pressed(
+                    &[
+                        GamepadInput::Button(button),
+                        GamepadInput::Axis(axis, axis_direction),
+                    ],
+                    GAMEPAD_NAVIGATION_DEADZONE,
+                )
+            })
+            .map(|(.., direction)| direction);
+        let pressed = gamepads.is_pressed(&[GamepadInput::Button(South)], 0.0);
+        drop(gamepads);
+
+        if let Some(target) = direction.and_then(|direction| self.focus_target(direction)) {
+            self.cursor.cursor_move(target);
+        }
+        pressed
+    }
+
+    /// Center of the closest focusable widget in the direction from the cursor.
+    fn focus_target(&self, direction: vec2<f32>) -> Option<vec2<f32>> {
+        let from = self.cursor.position;
+        let mut best: Option<(f32, vec2<f32>)> = None;
+        self.state.iter_widgets(
+            |widget| {
+                let state = widget.state();
+                if !widget.focusable() || !state.visible {
+                    return;
+                }
+                let center = state.position.center();
+                let delta = center - from;
+                let along = vec2::dot(delta, direction);
+                if along <= 0.0 {
+                    return;
+                }
+                // Prefer the widgets straight in the direction over the closer ones to the side
+                let score = along + vec2::skew(delta, direction).abs() * 2.0;
+                if best.is_none_or(|(best, _)| score < best) {
+                    best = Some((score, center));
+                }
+            },
+            |_| {},
+        );
+        best.map(|(_, center)| center)
+    }
+
     /// Should be called after the layout.
     /// Reset accumulators to prepare for the next frame.
     pub fn frame_end(&mut self) {
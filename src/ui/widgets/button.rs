@@ -31,6 +31,10 @@ impl ButtonWidget {
 impl Widget for ButtonWidget {
     crate::simple_widget_state!();
 
+    fn focusable(&self) -> bool {
+        true
+    }
+
     fn draw(&self, context: &UiContext) -> Geometry {
         let mut geometry = Geometry::new();
         geometry.merge(context.geometry.texture(
@@ -208,6 +208,10 @@ impl InputWidget {
 impl Widget for InputWidget {
     crate::simple_widget_state!();
 
+    fn focusable(&self) -> bool {
+        true
+    }
+
     fn draw(&self, context: &UiContext) -> Geometry {
         self.draw_colored(context, self.name.options.color)
     }
@@ -12,11 +12,17 @@ use std::any::Any;
 #[macro_export]
 macro_rules! simple_widget_state {
     () => {
+        fn state(&self) -> &WidgetState {
+            &self.state
+        }
         fn state_mut(&mut self) -> &mut WidgetState {
             &mut self.state
         }
     };
     ($path:tt) => {
+        fn state(&self) -> &WidgetState {
+            &self.$path.state
+        }
         fn state_mut(&mut self) -> &mut WidgetState {
             &mut self.$path.state
         }
@@ -24,7 +30,12 @@ macro_rules! simple_widget_state {
 }
 
 pub trait Widget: WidgetToAny {
+    fn state(&self) -> &WidgetState;
     fn state_mut(&mut self) -> &mut WidgetState;
+    /// Whether the gamepad can move the focus onto the widget.
+    fn focusable(&self) -> bool {
+        false
+    }
     #[must_use]
     fn draw_top(&self, context: &UiContext) -> Geometry {
         #![allow(unused_variables)]